@@ -44,6 +44,8 @@ fn test() {
             &polytone_note::msg::InstantiateMsg {
                 pair: None,
                 block_max_gas: Uint64::new(110_000),
+                multi_pair: None,
+                timeouts: None,
             },
             &[],
             "note1",
@@ -57,6 +59,8 @@ fn test() {
             &polytone_note::msg::InstantiateMsg {
                 pair: None,
                 block_max_gas: Uint64::new(110_000),
+                multi_pair: None,
+                timeouts: None,
             },
             &[],
             "note2",
@@ -89,6 +93,8 @@ fn test() {
         initiator: Addr::unchecked(INITIATOR_ADDR),
         initiator_msg: to_json_binary(INITIATOR_MSG).unwrap(),
         result: Callback::Execute(Result::Err("ERROR".to_string())),
+        channel_id: None,
+        sequence: None,
     };
     app.execute_contract(
         note1,
@@ -1,18 +1,29 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    to_json_binary, Binary, Deps, DepsMut, Env, IbcMsg, IbcTimeout, MessageInfo, Response,
-    StdResult,
+    to_json_binary, Addr, Api, BankMsg, Binary, CosmosMsg, Deps, DepsMut, DistributionMsg, Env,
+    GovMsg, IbcMsg, IbcTimeout, MessageInfo, Order, Response, StakingMsg, StdError, StdResult,
+    Storage, Uint64, WasmMsg,
 };
 use cw2::set_contract_version;
+use cw_storage_plus::Bound;
 use polytone::callbacks::CallbackRequestType;
 use polytone::{accounts, callbacks, ibc};
 
 use crate::error::ContractError;
 
-use crate::ibc::ERR_GAS_NEEDED;
-use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, Pair, QueryMsg};
-use crate::state::{increment_sequence_number, BLOCK_MAX_GAS, CHANNEL, CONNECTION_REMOTE_PORT};
+use crate::ibc::{callback_submsg, ERR_GAS_NEEDED};
+use crate::msg::{
+    DelegateGrant, DestinationResponse, ExecuteMsg, ExecuteResponse, InstantiateMsg, MigrateMsg,
+    Pair, PendingPacket, QueryMsg, RateLimit, RemoteAccount, RequestOptions, RequestTimeout,
+    SubAccount, TimeoutConfig,
+};
+use crate::state::{
+    destination_channel, increment_sequence_number, is_multi_pair, resolve_destination, timeouts,
+    GrantUsage, PacketInfo, PairChange, ALIASES, BLOCK_MAX_GAS, CHANNEL, CHANNELS, CLOSED_CHANNELS,
+    CONNECTION_REMOTE_PORT, GRANTS, GRANT_USAGE, MULTI_PAIR, PAIRS, PAIR_HISTORY, PENDING_PACKETS,
+    TIMEOUTS,
+};
 
 const CONTRACT_NAME: &str = "crates.io:polytone-note";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -32,9 +43,18 @@ pub fn instantiate(
 
     BLOCK_MAX_GAS.save(deps.storage, &msg.block_max_gas.u64())?;
 
+    let multi_pair = msg.multi_pair.unwrap_or_default();
+    MULTI_PAIR.save(deps.storage, &multi_pair)?;
+
+    if let Some(timeouts) = msg.timeouts {
+        validate_timeouts(&timeouts)?;
+        TIMEOUTS.save(deps.storage, &timeouts)?;
+    }
+
     let mut response = Response::default()
         .add_attribute("method", "instantiate")
-        .add_attribute("block_max_gas", msg.block_max_gas);
+        .add_attribute("block_max_gas", msg.block_max_gas)
+        .add_attribute("multi_pair", multi_pair.to_string());
 
     if let Some(Pair {
         connection_id,
@@ -44,7 +64,11 @@ pub fn instantiate(
         response = response
             .add_attribute("pair_connection", connection_id.to_string())
             .add_attribute("pair_port", remote_port.to_string());
-        CONNECTION_REMOTE_PORT.save(deps.storage, &(connection_id, remote_port))?;
+        if multi_pair {
+            PAIRS.save(deps.storage, connection_id, &remote_port)?;
+        } else {
+            CONNECTION_REMOTE_PORT.save(deps.storage, &(connection_id, remote_port))?;
+        }
     };
 
     Ok(response)
@@ -57,32 +81,221 @@ pub fn execute(
     info: MessageInfo,
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
-    let (msg, callback, timeout_seconds, request_type) = match msg {
+    let (msg, callback, timeout_seconds, request_type, options, grantor) = match msg {
         ExecuteMsg::Execute {
             msgs,
             callback,
             timeout_seconds,
+            mode,
+            on_behalf_of,
+            options,
+        } => {
+            let grantor = resolve_grantor(
+                deps.storage,
+                deps.api,
+                &env,
+                &info,
+                on_behalf_of,
+                Some(&msgs),
+            )?;
+            (
+                ibc::Msg::Execute { msgs, mode },
+                callback,
+                timeout_seconds,
+                CallbackRequestType::Execute,
+                options,
+                grantor,
+            )
+        }
+        ExecuteMsg::Query {
+            msgs,
+            callback,
+            timeout_seconds,
+            partial,
+            options,
+        } => (
+            ibc::Msg::Query { msgs, partial },
+            Some(callback),
+            timeout_seconds,
+            CallbackRequestType::Query,
+            options.map(|options| RequestOptions {
+                account_id: None,
+                ..options
+            }),
+            None,
+        ),
+        ExecuteMsg::ConditionalExecute {
+            queries,
+            conditions,
+            msgs,
+            callback,
+            timeout_seconds,
+            on_behalf_of,
+            options,
+        } => {
+            let grantor = resolve_grantor(
+                deps.storage,
+                deps.api,
+                &env,
+                &info,
+                on_behalf_of,
+                Some(&msgs),
+            )?;
+            (
+                ibc::Msg::ConditionalExecute {
+                    queries,
+                    conditions,
+                    msgs,
+                },
+                callback,
+                timeout_seconds,
+                CallbackRequestType::Execute,
+                options,
+                grantor,
+            )
+        }
+        ExecuteMsg::TemplatedExecute {
+            queries,
+            msgs,
+            callback,
+            timeout_seconds,
+            on_behalf_of,
+            options,
+        } => {
+            // the types of templated messages are not known until
+            // they are resolved on the voice chain.
+            let grantor = resolve_grantor(deps.storage, deps.api, &env, &info, on_behalf_of, None)?;
+            (
+                ibc::Msg::TemplatedExecute { queries, msgs },
+                callback,
+                timeout_seconds,
+                CallbackRequestType::Execute,
+                options,
+                grantor,
+            )
+        }
+        ExecuteMsg::TransferAccount {
+            new_owner,
+            callback,
+            timeout_seconds,
+            options,
         } => (
-            ibc::Msg::Execute { msgs },
+            ibc::Msg::TransferAccount {
+                new_owner: deps.api.addr_validate(&new_owner)?.into_string(),
+            },
             callback,
             timeout_seconds,
             CallbackRequestType::Execute,
+            options,
+            None,
         ),
-        ExecuteMsg::Query {
-            msgs,
+        ExecuteMsg::SetMigrationOptOut {
+            opt_out,
             callback,
             timeout_seconds,
+            options,
         } => (
-            ibc::Msg::Query { msgs },
-            Some(callback),
+            ibc::Msg::SetMigrationOptOut { opt_out },
+            callback,
             timeout_seconds,
-            CallbackRequestType::Query,
+            CallbackRequestType::Execute,
+            options,
+            None,
         ),
+        ExecuteMsg::RetryCallback {
+            channel_id,
+            sequence,
+        } => {
+            let callback = callbacks::deliver_callback(deps.storage, channel_id.clone(), sequence)?
+                .ok_or(ContractError::NoFailedCallback {
+                    channel_id: channel_id.clone(),
+                    sequence,
+                })?;
+            return Ok(Response::default()
+                .add_attribute("method", "retry_callback")
+                .add_attribute("channel_id", channel_id)
+                .add_attribute("sequence_number", sequence.to_string())
+                .add_message(callback));
+        }
+        ExecuteMsg::FlushClosedChannel { channel_id, limit } => {
+            let closed_at = CLOSED_CHANNELS
+                .may_load(deps.storage, channel_id.clone())?
+                .ok_or(ContractError::ChannelNotClosed {
+                    channel_id: channel_id.clone(),
+                })?;
+            let sequences = accounts::pending_packets(deps.storage, Some(channel_id.clone()), None)
+                .take(limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize)
+                .map(|item| item.map(|((_, sequence), _)| sequence))
+                .collect::<StdResult<Vec<u64>>>()?;
+            let mut submsgs = vec![];
+            for &sequence in &sequences {
+                submsgs.extend(
+                    callbacks::on_channel_closed(
+                        deps.storage,
+                        channel_id.clone(),
+                        sequence,
+                        closed_at,
+                    )?
+                    .map(|callback| callback_submsg(deps.storage, &env, callback)),
+                );
+                accounts::on_timeout(deps.storage, channel_id.clone(), sequence);
+                PENDING_PACKETS.remove(deps.storage, (channel_id.clone(), sequence));
+            }
+            return Ok(Response::default()
+                .add_attribute("method", "flush_closed_channel")
+                .add_attribute("channel_id", channel_id)
+                .add_attribute("flushed", sequences.len().to_string())
+                .add_submessages(submsgs));
+        }
+        ExecuteMsg::Grant { delegate, grant } => {
+            let delegate = deps.api.addr_validate(&delegate)?;
+            if grant.rate_limit.as_ref().map_or(false, |limit| {
+                limit.period_seconds.is_zero() || limit.max_packets == 0
+            }) {
+                return Err(ContractError::InvalidRateLimit);
+            }
+            GRANTS.save(deps.storage, (&info.sender, &delegate), &grant)?;
+            GRANT_USAGE.remove(deps.storage, (&info.sender, &delegate));
+            return Ok(Response::default()
+                .add_attribute("method", "grant")
+                .add_attribute("grantor", info.sender)
+                .add_attribute("delegate", delegate));
+        }
+        ExecuteMsg::Revoke { delegate } => {
+            let delegate = deps.api.addr_validate(&delegate)?;
+            GRANTS.remove(deps.storage, (&info.sender, &delegate));
+            GRANT_USAGE.remove(deps.storage, (&info.sender, &delegate));
+            return Ok(Response::default()
+                .add_attribute("method", "revoke")
+                .add_attribute("grantor", info.sender)
+                .add_attribute("delegate", delegate));
+        }
     };
 
-    let channel_id = CHANNEL
-        .may_load(deps.storage)?
-        .ok_or(ContractError::NoPair)?;
+    // requests sent by a delegate are sent from the grantor's
+    // account, but the delegate receives their callbacks.
+    let (sender, delegate) = match grantor {
+        Some(grantor) => (grantor, Some(info.sender.clone())),
+        None => (info.sender.clone(), None),
+    };
+
+    let RequestOptions {
+        timeout,
+        destination,
+        gas_limit,
+        not_before,
+        not_after,
+        nonce,
+        idempotency_key,
+        account_id,
+    } = options.unwrap_or_default();
+    if let (Some(not_before), Some(not_after)) = (not_before, not_after) {
+        if not_before > not_after {
+            return Err(ContractError::InvalidWindow);
+        }
+    }
+    let timeout = ibc_timeout(deps.storage, &env, timeout_seconds, timeout)?;
+    let channel_id = destination_channel(deps.storage, destination)?;
 
     let sequence_number = increment_sequence_number(deps.storage, channel_id.clone())?;
 
@@ -93,32 +306,247 @@ pub fn execute(
         sequence_number,
         info.sender.clone(),
         callback,
-        request_type,
+        request_type.clone(),
     )?;
 
     accounts::on_send_packet(
         deps.storage,
         channel_id.clone(),
         sequence_number,
-        &info.sender,
+        &sender,
+        account_id.clone(),
+    )?;
+    if let ibc::Msg::TransferAccount { new_owner } = &msg {
+        accounts::on_send_transfer(
+            deps.storage,
+            channel_id.clone(),
+            sequence_number,
+            &Addr::unchecked(new_owner),
+        )?;
+    }
+
+    PENDING_PACKETS.save(
+        deps.storage,
+        (channel_id.clone(), sequence_number),
+        &PacketInfo {
+            request_type,
+            sent_at: env.block.time,
+        },
     )?;
 
-    Ok(Response::default()
+    let mut response = Response::default()
         .add_attribute("method", "execute")
+        .add_attribute("channel_id", channel_id.as_str())
+        .add_attribute("sequence_number", sequence_number.to_string());
+    if let Some(delegate) = delegate {
+        response = response
+            .add_attribute("on_behalf_of", sender.as_str())
+            .add_attribute("delegate", delegate);
+    }
+
+    Ok(response
+        .set_data(to_json_binary(&ExecuteResponse {
+            channel_id: channel_id.clone(),
+            sequence: sequence_number,
+        })?)
         .add_message(IbcMsg::SendPacket {
             channel_id,
             data: to_json_binary(&ibc::Packet {
-                sender: info.sender.into_string(),
+                sender: sender.into_string(),
                 msg,
+                gas_limit,
+                not_before,
+                not_after,
+                nonce,
+                idempotency_key,
+                account_id,
             })
             .expect("msgs are known to be serializable"),
-            timeout: IbcTimeout::with_timestamp(env.block.time.plus_seconds(timeout_seconds.u64())),
+            timeout,
         }))
 }
 
+/// Resolves a request's timeout, enforcing the note's bounds on its
+/// timestamp. Block height timeouts are not bounded.
+fn ibc_timeout(
+    storage: &dyn Storage,
+    env: &Env,
+    timeout_seconds: Option<Uint64>,
+    timeout: Option<RequestTimeout>,
+) -> Result<IbcTimeout, ContractError> {
+    let config = timeouts(storage)?;
+    let RequestTimeout { timestamp, block } = timeout.unwrap_or(RequestTimeout {
+        timestamp: None,
+        block: None,
+    });
+    let timestamp = match (timeout_seconds, timestamp) {
+        (Some(_), Some(_)) => return Err(ContractError::ConflictingTimeouts),
+        (Some(seconds), None) => Some(env.block.time.plus_seconds(seconds.u64())),
+        (None, Some(timestamp)) => Some(timestamp),
+        (None, None) if block.is_none() => Some(
+            env.block.time.plus_seconds(
+                config
+                    .default_seconds
+                    .ok_or(ContractError::NoTimeout)?
+                    .u64(),
+            ),
+        ),
+        (None, None) => None,
+    };
+    if let Some(timestamp) = timestamp {
+        let seconds = timestamp.seconds().saturating_sub(env.block.time.seconds());
+        if seconds < config.min_seconds.u64() || seconds > config.max_seconds.u64() {
+            return Err(ContractError::TimeoutOutOfBounds {
+                seconds,
+                min_seconds: config.min_seconds.u64(),
+                max_seconds: config.max_seconds.u64(),
+            });
+        }
+    }
+    if block.map_or(false, |block| block.height == 0) {
+        return Err(ContractError::ZeroTimeoutBlock);
+    }
+    Ok(match (block, timestamp) {
+        (Some(block), Some(timestamp)) => IbcTimeout::with_both(block, timestamp),
+        (Some(block), None) => IbcTimeout::with_block(block),
+        (None, Some(timestamp)) => IbcTimeout::with_timestamp(timestamp),
+        (None, None) => unreachable!("the default is used if there is no block timeout"),
+    })
+}
+
+/// Returns the address a request from `info.sender` is sent on
+/// behalf of, if any, after checking and using the sender's grant
+/// from it. `msgs` is `None` if the request's messages are not known.
+fn resolve_grantor(
+    storage: &mut dyn Storage,
+    api: &dyn Api,
+    env: &Env,
+    info: &MessageInfo,
+    on_behalf_of: Option<String>,
+    msgs: Option<&[CosmosMsg]>,
+) -> Result<Option<Addr>, ContractError> {
+    on_behalf_of
+        .map(|grantor| {
+            let grantor = api.addr_validate(&grantor)?;
+            use_grant(storage, env, &grantor, &info.sender, msgs)?;
+            Ok(grantor)
+        })
+        .transpose()
+}
+
+/// Checks that `delegate` may send `msgs` on behalf of `grantor`, and
+/// records the use of the grant against its rate limit. Grants
+/// limited to message types do not allow requests whose messages are
+/// not known.
+fn use_grant(
+    storage: &mut dyn Storage,
+    env: &Env,
+    grantor: &Addr,
+    delegate: &Addr,
+    msgs: Option<&[CosmosMsg]>,
+) -> Result<(), ContractError> {
+    let grant = GRANTS
+        .may_load(storage, (grantor, delegate))?
+        .ok_or_else(|| ContractError::NoGrant {
+            grantor: grantor.to_string(),
+            delegate: delegate.to_string(),
+        })?;
+    if grant
+        .expires
+        .map_or(false, |expires| env.block.time >= expires)
+    {
+        return Err(ContractError::GrantExpired {
+            grantor: grantor.to_string(),
+        });
+    }
+    if let Some(msg_types) = grant.msg_types {
+        let msgs = msgs.ok_or(ContractError::TemplatesNotAllowed)?;
+        for msg in msgs {
+            let msg_type = msg_type_url(msg);
+            if !msg_type.map_or(false, |msg_type| msg_types.iter().any(|t| t == msg_type)) {
+                return Err(ContractError::MsgTypeNotAllowed {
+                    msg_type: msg_type.unwrap_or("unknown").to_string(),
+                });
+            }
+        }
+    }
+    if let Some(RateLimit {
+        period_seconds,
+        max_packets,
+    }) = grant.rate_limit
+    {
+        let mut usage = GRANT_USAGE
+            .may_load(storage, (grantor, delegate))?
+            .filter(|usage| usage.period_start.plus_seconds(period_seconds.u64()) > env.block.time)
+            .unwrap_or(GrantUsage {
+                period_start: env.block.time,
+                packets: 0,
+            });
+        if usage.packets >= max_packets {
+            return Err(ContractError::GrantRateLimited {
+                max_packets,
+                period_seconds: period_seconds.u64(),
+            });
+        }
+        usage.packets += 1;
+        GRANT_USAGE.save(storage, (grantor, delegate), &usage)?;
+    }
+    Ok(())
+}
+
+/// The type URL of the Cosmos SDK message `msg` is executed as, or
+/// `None` if it is not known.
+fn msg_type_url(msg: &CosmosMsg) -> Option<&str> {
+    Some(match msg {
+        CosmosMsg::Bank(BankMsg::Send { .. }) => "/cosmos.bank.v1beta1.MsgSend",
+        CosmosMsg::Bank(BankMsg::Burn { .. }) => "/cosmos.bank.v1beta1.MsgBurn",
+        CosmosMsg::Staking(StakingMsg::Delegate { .. }) => "/cosmos.staking.v1beta1.MsgDelegate",
+        CosmosMsg::Staking(StakingMsg::Undelegate { .. }) => {
+            "/cosmos.staking.v1beta1.MsgUndelegate"
+        }
+        CosmosMsg::Staking(StakingMsg::Redelegate { .. }) => {
+            "/cosmos.staking.v1beta1.MsgBeginRedelegate"
+        }
+        CosmosMsg::Distribution(DistributionMsg::SetWithdrawAddress { .. }) => {
+            "/cosmos.distribution.v1beta1.MsgSetWithdrawAddress"
+        }
+        CosmosMsg::Distribution(DistributionMsg::WithdrawDelegatorReward { .. }) => {
+            "/cosmos.distribution.v1beta1.MsgWithdrawDelegatorReward"
+        }
+        CosmosMsg::Stargate { type_url, .. } => type_url,
+        CosmosMsg::Ibc(IbcMsg::Transfer { .. }) => "/ibc.applications.transfer.v1.MsgTransfer",
+        CosmosMsg::Wasm(WasmMsg::Execute { .. }) => "/cosmwasm.wasm.v1.MsgExecuteContract",
+        CosmosMsg::Wasm(WasmMsg::Instantiate { .. }) => "/cosmwasm.wasm.v1.MsgInstantiateContract",
+        CosmosMsg::Wasm(WasmMsg::Migrate { .. }) => "/cosmwasm.wasm.v1.MsgMigrateContract",
+        CosmosMsg::Wasm(WasmMsg::UpdateAdmin { .. }) => "/cosmwasm.wasm.v1.MsgUpdateAdmin",
+        CosmosMsg::Wasm(WasmMsg::ClearAdmin { .. }) => "/cosmwasm.wasm.v1.MsgClearAdmin",
+        CosmosMsg::Gov(GovMsg::Vote { .. }) => "/cosmos.gov.v1.MsgVote",
+        _ => return None,
+    })
+}
+
+fn validate_timeouts(config: &TimeoutConfig) -> Result<(), ContractError> {
+    let TimeoutConfig {
+        default_seconds,
+        min_seconds,
+        max_seconds,
+    } = config;
+    if min_seconds.is_zero()
+        || min_seconds > max_seconds
+        || default_seconds.map_or(false, |d| d < *min_seconds || d > *max_seconds)
+    {
+        Err(ContractError::InvalidTimeouts)
+    } else {
+        Ok(())
+    }
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
+        QueryMsg::ActiveChannel | QueryMsg::Pair if is_multi_pair(deps.storage)? => Err(
+            StdError::generic_err("contract pairs with many voices, query `Destination` instead"),
+        ),
         QueryMsg::ActiveChannel => to_json_binary(&CHANNEL.may_load(deps.storage)?),
         QueryMsg::Pair => to_json_binary(&CONNECTION_REMOTE_PORT.may_load(deps.storage)?.map(
             |(connection_id, remote_port)| Pair {
@@ -126,16 +554,267 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
                 remote_port,
             },
         )),
-        QueryMsg::RemoteAddress { local_address } => to_json_binary(&accounts::query_account(
+        QueryMsg::RemoteAddress {
+            local_address,
+            destination,
+            account_id,
+        } => to_json_binary(&accounts::query_account(
             deps.storage,
+            account_connection(deps.storage, destination)?,
             deps.api.addr_validate(&local_address)?,
+            account_id,
+        )?),
+        QueryMsg::LocalAddress {
+            remote_address,
+            destination,
+        } => to_json_binary(&accounts::query_local_account(
+            deps.storage,
+            account_connection(deps.storage, destination)?,
+            remote_address,
         )?),
+        QueryMsg::RemoteAccounts {
+            destination,
+            start_after,
+            limit,
+        } => to_json_binary(
+            &accounts::query_accounts(
+                deps.storage,
+                account_connection(deps.storage, destination)?,
+                start_after
+                    .map(|start_after| deps.api.addr_validate(&start_after))
+                    .transpose()?,
+                limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize,
+            )?
+            .into_iter()
+            .map(|(local_address, remote_address)| RemoteAccount {
+                local_address: local_address.into_string(),
+                remote_address,
+            })
+            .collect::<Vec<_>>(),
+        ),
+        QueryMsg::SubAccounts {
+            local_address,
+            destination,
+            start_after,
+            limit,
+        } => to_json_binary(
+            &accounts::query_sub_accounts(
+                deps.storage,
+                account_connection(deps.storage, destination)?,
+                deps.api.addr_validate(&local_address)?,
+                start_after,
+                limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize,
+            )?
+            .into_iter()
+            .map(|(account_id, remote_address)| SubAccount {
+                account_id,
+                remote_address,
+            })
+            .collect::<Vec<_>>(),
+        ),
         QueryMsg::BlockMaxGas => to_json_binary(&BLOCK_MAX_GAS.load(deps.storage)?),
+        QueryMsg::Timeouts => to_json_binary(&timeouts(deps.storage)?),
+        QueryMsg::Grant { grantor, delegate } => to_json_binary(&GRANTS.may_load(
+            deps.storage,
+            (
+                &deps.api.addr_validate(&grantor)?,
+                &deps.api.addr_validate(&delegate)?,
+            ),
+        )?),
+        QueryMsg::Grants {
+            grantor,
+            start_after,
+            limit,
+        } => {
+            let start_after = start_after
+                .map(|start_after| deps.api.addr_validate(&start_after))
+                .transpose()?;
+            to_json_binary(
+                &GRANTS
+                    .prefix(&deps.api.addr_validate(&grantor)?)
+                    .range(
+                        deps.storage,
+                        start_after.as_ref().map(Bound::exclusive),
+                        None,
+                        Order::Ascending,
+                    )
+                    .take(limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize)
+                    .map(|item| {
+                        item.map(|(delegate, grant)| DelegateGrant {
+                            delegate: delegate.into_string(),
+                            grant,
+                        })
+                    })
+                    .collect::<StdResult<Vec<_>>>()?,
+            )
+        }
+        QueryMsg::Destination { destination } => {
+            let connection_id = resolve_destination(deps.storage, destination)?;
+            to_json_binary(&query_destination(deps.storage, connection_id)?)
+        }
+        QueryMsg::Destinations { start_after, limit } => {
+            let destinations = if is_multi_pair(deps.storage)? {
+                PAIRS
+                    .range(
+                        deps.storage,
+                        start_after.map(Bound::exclusive),
+                        None,
+                        Order::Ascending,
+                    )
+                    .take(limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize)
+                    .map(|item| {
+                        let (connection_id, remote_port) = item?;
+                        Ok(DestinationResponse {
+                            active_channel: CHANNELS
+                                .may_load(deps.storage, connection_id.clone())?,
+                            pair: Pair {
+                                connection_id,
+                                remote_port,
+                            },
+                        })
+                    })
+                    .collect::<StdResult<Vec<_>>>()?
+            } else {
+                // without multi-pair mode there is at most one pair.
+                match CONNECTION_REMOTE_PORT.may_load(deps.storage)? {
+                    Some((connection_id, _))
+                        if start_after.map_or(true, |start_after| connection_id > start_after) =>
+                    {
+                        query_destination(deps.storage, connection_id)?
+                            .into_iter()
+                            .collect()
+                    }
+                    _ => vec![],
+                }
+            };
+            to_json_binary(&destinations)
+        }
+        QueryMsg::PairHistory { start_after, limit } => to_json_binary(
+            &PAIR_HISTORY
+                .range(
+                    deps.storage,
+                    start_after.map(Bound::exclusive),
+                    None,
+                    Order::Ascending,
+                )
+                .take(limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize)
+                .collect::<StdResult<Vec<_>>>()?,
+        ),
+        QueryMsg::PendingPackets {
+            sender,
+            channel_id,
+            start_after,
+            limit,
+        } => {
+            let sender = sender
+                .map(|sender| deps.api.addr_validate(&sender))
+                .transpose()?;
+            to_json_binary(
+                &accounts::pending_packets(deps.storage, channel_id, start_after)
+                    .filter(|item| {
+                        item.as_ref().map_or(true, |(_, initiator)| {
+                            sender.as_ref().map_or(true, |sender| sender == initiator)
+                        })
+                    })
+                    .take(limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize)
+                    .map(|item| {
+                        let ((channel_id, sequence), initiator) = item?;
+                        let callback = callbacks::query_pending_callback(
+                            deps.storage,
+                            channel_id.clone(),
+                            sequence,
+                        )?;
+                        let info = PENDING_PACKETS
+                            .may_load(deps.storage, (channel_id.clone(), sequence))?;
+                        Ok(PendingPacket {
+                            channel_id,
+                            sequence,
+                            initiator: initiator.into_string(),
+                            callback_receiver: callback
+                                .as_ref()
+                                .map(|callback| callback.receiver.to_string()),
+                            request_type: info
+                                .as_ref()
+                                .map(|info| info.request_type.clone())
+                                .or(callback.map(|callback| callback.request_type)),
+                            sent_at: info.map(|info| info.sent_at),
+                        })
+                    })
+                    .collect::<StdResult<Vec<_>>>()?,
+            )
+        }
+        QueryMsg::FailedCallbacks { start_after, limit } => {
+            to_json_binary(&callbacks::query_failed_callbacks(
+                deps.storage,
+                start_after,
+                limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize,
+            )?)
+        }
+    }
+}
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
+
+/// The connection ID that accounts on `destination` are tracked
+/// under, or `None` if the contract is not in multi-pair mode.
+fn account_connection(
+    storage: &dyn Storage,
+    destination: Option<String>,
+) -> StdResult<Option<String>> {
+    if is_multi_pair(storage)? {
+        destination
+            .map(|destination| resolve_destination(storage, destination))
+            .transpose()
+    } else {
+        Ok(None)
+    }
+}
+
+fn query_destination(
+    storage: &dyn Storage,
+    connection_id: String,
+) -> StdResult<Option<DestinationResponse>> {
+    if is_multi_pair(storage)? {
+        PAIRS
+            .may_load(storage, connection_id.clone())?
+            .map(|remote_port| {
+                Ok(DestinationResponse {
+                    active_channel: CHANNELS.may_load(storage, connection_id.clone())?,
+                    pair: Pair {
+                        connection_id,
+                        remote_port,
+                    },
+                })
+            })
+            .transpose()
+    } else {
+        CONNECTION_REMOTE_PORT
+            .may_load(storage)?
+            .filter(|(pair_connection, _)| *pair_connection == connection_id)
+            .map(|(connection_id, remote_port)| {
+                Ok(DestinationResponse {
+                    active_channel: CHANNEL.may_load(storage)?,
+                    pair: Pair {
+                        connection_id,
+                        remote_port,
+                    },
+                })
+            })
+            .transpose()
     }
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn migrate(deps: DepsMut, _env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+pub fn migrate(deps: DepsMut, env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    // notes migrated from versions that needed less gas to handle
+    // callback errors may have a block max gas too low to deliver
+    // callbacks with, which must be raised before anything else.
+    if !matches!(msg, MigrateMsg::WithUpdate { .. })
+        && BLOCK_MAX_GAS.load(deps.storage)? <= ERR_GAS_NEEDED
+    {
+        return Err(ContractError::GasLimitsMismatch);
+    }
     match msg {
         MigrateMsg::WithUpdate { block_max_gas } => {
             if block_max_gas.u64() <= ERR_GAS_NEEDED {
@@ -147,5 +826,1527 @@ pub fn migrate(deps: DepsMut, _env: Env, msg: MigrateMsg) -> Result<Response, Co
                 .add_attribute("method", "migrate_with_update")
                 .add_attribute("block_max_gas", block_max_gas))
         }
+        MigrateMsg::IndexAccounts {
+            destination,
+            start_after,
+            limit,
+        } => {
+            let start_after = start_after
+                .map(|start_after| deps.api.addr_validate(&start_after))
+                .transpose()?;
+            let connection_id = account_connection(deps.storage, destination)?;
+            let accounts = accounts::index_accounts(
+                deps.storage,
+                connection_id,
+                start_after,
+                limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize,
+            )?;
+            let mut response = Response::default()
+                .add_attribute("method", "migrate_index_accounts")
+                .add_attribute("accounts", accounts.len().to_string());
+            if let Some(last) = accounts.last() {
+                response = response.add_attribute("last_account", last.as_str());
+            }
+            Ok(response)
+        }
+        MigrateMsg::SetTimeouts { timeouts } => {
+            validate_timeouts(&timeouts)?;
+            TIMEOUTS.save(deps.storage, &timeouts)?;
+            Ok(Response::default().add_attribute("method", "migrate_set_timeouts"))
+        }
+        MigrateMsg::SetAlias {
+            alias,
+            connection_id,
+        } => {
+            let response = Response::default()
+                .add_attribute("method", "migrate_set_alias")
+                .add_attribute("alias", alias.as_str());
+            Ok(match connection_id {
+                Some(connection_id) => {
+                    ALIASES.save(deps.storage, alias, &connection_id)?;
+                    response.add_attribute("connection_id", connection_id)
+                }
+                None => {
+                    ALIASES.remove(deps.storage, alias);
+                    response
+                }
+            })
+        }
+        MigrateMsg::AddPair { pair } => {
+            if !is_multi_pair(deps.storage)? {
+                return Err(ContractError::SinglePair);
+            }
+            if PAIRS.has(deps.storage, pair.connection_id.clone()) {
+                return Err(ContractError::ConnectionPaired {
+                    connection_id: pair.connection_id,
+                });
+            }
+            PAIRS.save(deps.storage, pair.connection_id.clone(), &pair.remote_port)?;
+            Ok(Response::default()
+                .add_attribute("method", "migrate_add_pair")
+                .add_attribute("pair_connection", pair.connection_id)
+                .add_attribute("pair_port", pair.remote_port))
+        }
+        MigrateMsg::ReplacePair { destination, pair } => {
+            let previous = if is_multi_pair(deps.storage)? {
+                if PAIRS.has(deps.storage, pair.connection_id.clone()) {
+                    return Err(ContractError::ConnectionPaired {
+                        connection_id: pair.connection_id,
+                    });
+                }
+                let destination = destination.ok_or(ContractError::NoDestination)?;
+                let connection_id = resolve_destination(deps.storage, destination.clone())?;
+                let remote_port = PAIRS
+                    .may_load(deps.storage, connection_id.clone())?
+                    .ok_or(ContractError::NoPairFor { destination })?;
+
+                PAIRS.remove(deps.storage, connection_id.clone());
+                // the replaced channel keeps its connection until it
+                // closes, so that acks for packets in flight on it
+                // still update accounts.
+                CHANNELS.remove(deps.storage, connection_id.clone());
+                PAIRS.save(deps.storage, pair.connection_id.clone(), &pair.remote_port)?;
+
+                let aliases = ALIASES
+                    .range(deps.storage, None, None, Order::Ascending)
+                    .filter(|item| {
+                        item.as_ref()
+                            .map_or(true, |(_, aliased)| *aliased == connection_id)
+                    })
+                    .map(|item| item.map(|(alias, _)| alias))
+                    .collect::<StdResult<Vec<_>>>()?;
+                for alias in aliases {
+                    ALIASES.save(deps.storage, alias, &pair.connection_id)?;
+                }
+
+                Some(Pair {
+                    connection_id,
+                    remote_port,
+                })
+            } else {
+                let previous = CONNECTION_REMOTE_PORT.may_load(deps.storage)?;
+                CONNECTION_REMOTE_PORT.save(
+                    deps.storage,
+                    &(pair.connection_id.clone(), pair.remote_port.clone()),
+                )?;
+                CHANNEL.remove(deps.storage);
+                previous.map(|(connection_id, remote_port)| Pair {
+                    connection_id,
+                    remote_port,
+                })
+            };
+
+            let index = PAIR_HISTORY
+                .keys(deps.storage, None, None, Order::Descending)
+                .next()
+                .transpose()?
+                .map_or(0, |last| last + 1);
+            PAIR_HISTORY.save(
+                deps.storage,
+                index,
+                &PairChange {
+                    previous: previous.clone(),
+                    replacement: pair.clone(),
+                    block_height: env.block.height,
+                },
+            )?;
+
+            let mut response = Response::default().add_attribute("method", "migrate_replace_pair");
+            if let Some(previous) = previous {
+                response = response
+                    .add_attribute("previous_pair_connection", previous.connection_id)
+                    .add_attribute("previous_pair_port", previous.remote_port);
+            }
+            Ok(response
+                .add_attribute("pair_connection", pair.connection_id)
+                .add_attribute("pair_port", pair.remote_port))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{
+        coins, from_json,
+        testing::{
+            mock_dependencies, mock_env, mock_ibc_channel, mock_ibc_channel_close_init,
+            mock_ibc_channel_open_init, mock_ibc_packet_ack, mock_info,
+        },
+        to_json_binary, Attribute, BankMsg, Binary, CosmosMsg, DepsMut, IbcAcknowledgement,
+        IbcChannelCloseMsg, IbcChannelConnectMsg, IbcMsg, IbcOrder, IbcTimeout, IbcTimeoutBlock,
+        Reply, Response, SubMsg, SubMsgResult, Uint64, WasmMsg,
+    };
+    use polytone::{
+        ack::{ack_execute_success, ack_query_success},
+        callbacks::{
+            self, Callback, CallbackRequest, CallbackRequestType, CallbackVersion, FailedCallback,
+        },
+        ibc,
+    };
+
+    use crate::{
+        error::ContractError,
+        ibc::{
+            ibc_channel_close, ibc_channel_connect, ibc_channel_open, ibc_packet_ack, reply,
+            ERR_GAS_NEEDED,
+        },
+        msg::{
+            DelegateGrant, DestinationResponse, ExecuteMsg, ExecuteResponse, Grant, InstantiateMsg,
+            MigrateMsg, Pair, PendingPacket, QueryMsg, RateLimit, RequestOptions, RequestTimeout,
+            TimeoutConfig,
+        },
+        state::{BLOCK_MAX_GAS, CHANNEL, CHANNEL_TO_CONNECTION},
+    };
+
+    use super::{execute, instantiate, migrate, query};
+
+    #[test]
+    fn test_pending_packets() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        instantiate(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("creator", &[]),
+            InstantiateMsg {
+                pair: None,
+                block_max_gas: Uint64::new(110_000),
+                multi_pair: None,
+                timeouts: None,
+            },
+        )
+        .unwrap();
+        CHANNEL
+            .save(deps.as_mut().storage, &"channel-0".to_string())
+            .unwrap();
+
+        let response = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("alice", &[]),
+            ExecuteMsg::Execute {
+                msgs: vec![],
+                callback: None,
+                timeout_seconds: Some(Uint64::new(100)),
+                mode: None,
+                on_behalf_of: None,
+                options: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            from_json::<ExecuteResponse>(response.data.unwrap()).unwrap(),
+            ExecuteResponse {
+                channel_id: "channel-0".to_string(),
+                sequence: 1,
+            }
+        );
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("bob", &[]),
+            ExecuteMsg::Query {
+                msgs: vec![],
+                callback: CallbackRequest {
+                    receiver: "receiver".to_string(),
+                    msg: Binary::default(),
+                    gas_limit: None,
+                    delivery: None,
+                    version: None,
+                },
+                timeout_seconds: Some(Uint64::new(100)),
+                partial: None,
+                options: None,
+            },
+        )
+        .unwrap();
+
+        let pending: Vec<PendingPacket> = from_json(
+            query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::PendingPackets {
+                    sender: Some("bob".to_string()),
+                    channel_id: None,
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            pending,
+            vec![PendingPacket {
+                channel_id: "channel-0".to_string(),
+                sequence: 2,
+                initiator: "bob".to_string(),
+                callback_receiver: Some("receiver".to_string()),
+                request_type: Some(CallbackRequestType::Query),
+                sent_at: Some(env.block.time),
+            }]
+        );
+
+        let pending: Vec<PendingPacket> = from_json(
+            query(
+                deps.as_ref(),
+                env,
+                QueryMsg::PendingPackets {
+                    sender: None,
+                    channel_id: Some("channel-0".to_string()),
+                    start_after: None,
+                    limit: Some(1),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].initiator, "alice");
+        assert_eq!(pending[0].callback_receiver, None);
+    }
+
+    #[test]
+    fn test_retry_callback() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        instantiate(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("creator", &[]),
+            InstantiateMsg {
+                pair: None,
+                block_max_gas: Uint64::new(1_000_000),
+                multi_pair: None,
+                timeouts: None,
+            },
+        )
+        .unwrap();
+        CHANNEL
+            .save(deps.as_mut().storage, &"channel-0".to_string())
+            .unwrap();
+
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("alice", &[]),
+            ExecuteMsg::Query {
+                msgs: vec![],
+                callback: CallbackRequest {
+                    receiver: "receiver".to_string(),
+                    msg: Binary::default(),
+                    gas_limit: None,
+                    delivery: None,
+                    version: None,
+                },
+                timeout_seconds: Some(Uint64::new(100)),
+                partial: None,
+                options: None,
+            },
+        )
+        .unwrap();
+
+        let mut ack = mock_ibc_packet_ack(
+            "channel-0",
+            &(),
+            IbcAcknowledgement::new(ack_query_success(vec![])),
+        )
+        .unwrap();
+        ack.original_packet.sequence = 1;
+        let response = ibc_packet_ack(deps.as_mut(), env.clone(), ack).unwrap();
+        let retry = ExecuteMsg::RetryCallback {
+            channel_id: "channel-0".to_string(),
+            sequence: 1,
+        };
+        assert_eq!(
+            response.messages,
+            vec![SubMsg::reply_on_error(
+                WasmMsg::Execute {
+                    contract_addr: env.contract.address.to_string(),
+                    msg: to_json_binary(&retry).unwrap(),
+                    funds: vec![],
+                },
+                1
+            )
+            .with_gas_limit(1_000_000 - ERR_GAS_NEEDED)]
+        );
+
+        // delivering the callback fails.
+        reply(
+            deps.as_mut(),
+            env.clone(),
+            Reply {
+                id: 1,
+                result: SubMsgResult::Err("codespace: wasm, code: 5".to_string()),
+            },
+        )
+        .unwrap();
+        let failed: Vec<FailedCallback> = from_json(
+            query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::FailedCallbacks {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].receiver, "receiver");
+        assert_eq!(failed[0].callback.result, Callback::Query(Ok(vec![])));
+        assert_eq!(
+            failed[0].error,
+            Some("codespace: wasm, code: 5".to_string())
+        );
+
+        // anyone may retry it.
+        let response = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("anyone", &[]),
+            retry.clone(),
+        )
+        .unwrap();
+        assert!(matches!(
+            &response.messages[0].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, .. }) if contract_addr == "receiver"
+        ));
+        let failed: Vec<FailedCallback> = from_json(
+            query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::FailedCallbacks {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert!(failed.is_empty());
+
+        let err = execute(deps.as_mut(), env, mock_info("anyone", &[]), retry).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::NoFailedCallback {
+                channel_id: "channel-0".to_string(),
+                sequence: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_flush_closed_channel() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        instantiate(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("creator", &[]),
+            InstantiateMsg {
+                pair: None,
+                block_max_gas: Uint64::new(1_000_000),
+                multi_pair: None,
+                timeouts: None,
+            },
+        )
+        .unwrap();
+        CHANNEL
+            .save(deps.as_mut().storage, &"channel-0".to_string())
+            .unwrap();
+
+        for callback in [
+            Some(CallbackRequest {
+                receiver: "receiver".to_string(),
+                msg: Binary::default(),
+                gas_limit: None,
+                delivery: None,
+                version: Some(CallbackVersion::V2),
+            }),
+            None,
+        ] {
+            execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info("alice", &[]),
+                ExecuteMsg::Execute {
+                    msgs: vec![],
+                    callback,
+                    timeout_seconds: Some(Uint64::new(100)),
+                    mode: None,
+                    on_behalf_of: None,
+                    options: None,
+                },
+            )
+            .unwrap();
+        }
+
+        let flush = ExecuteMsg::FlushClosedChannel {
+            channel_id: "channel-0".to_string(),
+            limit: Some(1),
+        };
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("anyone", &[]),
+            flush.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::ChannelNotClosed {
+                channel_id: "channel-0".to_string()
+            }
+        );
+
+        ibc_channel_close(
+            deps.as_mut(),
+            env.clone(),
+            mock_ibc_channel_close_init("channel-0", IbcOrder::Unordered, "polytone-1"),
+        )
+        .unwrap();
+
+        let pending = |deps: &cosmwasm_std::OwnedDeps<_, _, _>| {
+            from_json::<Vec<PendingPacket>>(
+                query(
+                    deps.as_ref(),
+                    mock_env(),
+                    QueryMsg::PendingPackets {
+                        sender: None,
+                        channel_id: None,
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap(),
+            )
+            .unwrap()
+        };
+
+        // the first request's callback is delivered.
+        let response = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("anyone", &[]),
+            flush.clone(),
+        )
+        .unwrap();
+        assert_eq!(response.messages.len(), 1);
+        assert_eq!(pending(&deps).len(), 1);
+        let failed: Vec<FailedCallback> = from_json(
+            query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::FailedCallbacks {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            failed[0].callback.result,
+            Callback::ChannelClosed(env.block.time)
+        );
+
+        // the second request has no callback.
+        let response = execute(deps.as_mut(), env, mock_info("anyone", &[]), flush).unwrap();
+        assert!(response.messages.is_empty());
+        assert!(pending(&deps).is_empty());
+    }
+
+    #[test]
+    fn test_open_multi_pair() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        instantiate(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("creator", &[]),
+            InstantiateMsg {
+                pair: None,
+                block_max_gas: Uint64::new(110_000),
+                multi_pair: Some(true),
+                timeouts: None,
+            },
+        )
+        .unwrap();
+
+        // handshakes on connections without a pair are rejected.
+        let open = mock_ibc_channel_open_init("channel-0", IbcOrder::Unordered, "polytone-1");
+        let err = ibc_channel_open(deps.as_mut(), env.clone(), open.clone()).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::UnknownConnection {
+                connection_id: "connection-2".to_string()
+            }
+        );
+
+        migrate(
+            deps.as_mut(),
+            env.clone(),
+            MigrateMsg::AddPair {
+                pair: Pair {
+                    connection_id: "connection-2".to_string(),
+                    remote_port: "their_port".to_string(),
+                },
+            },
+        )
+        .unwrap();
+        ibc_channel_open(deps.as_mut(), env.clone(), open).unwrap();
+
+        // and the single pair queries point to `Destination`.
+        query(deps.as_ref(), env.clone(), QueryMsg::ActiveChannel).unwrap_err();
+        query(deps.as_ref(), env, QueryMsg::Pair).unwrap_err();
+    }
+
+    #[test]
+    fn test_close_replaced_channel() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        instantiate(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("creator", &[]),
+            InstantiateMsg {
+                pair: Some(Pair {
+                    connection_id: "connection-0".to_string(),
+                    remote_port: "their_port".to_string(),
+                }),
+                block_max_gas: Uint64::new(110_000),
+                multi_pair: Some(true),
+                timeouts: None,
+            },
+        )
+        .unwrap();
+        let channel = |channel_id: &str, connection_id: &str| {
+            let mut channel = mock_ibc_channel(channel_id, IbcOrder::Unordered, "polytone-1");
+            channel.connection_id = connection_id.to_string();
+            channel
+        };
+        ibc_channel_connect(
+            deps.as_mut(),
+            env.clone(),
+            IbcChannelConnectMsg::new_confirm(channel("channel-0", "connection-0")),
+        )
+        .unwrap();
+
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("alice", &[]),
+            ExecuteMsg::Execute {
+                msgs: vec![],
+                callback: None,
+                timeout_seconds: Some(Uint64::new(100)),
+                mode: None,
+                on_behalf_of: None,
+                options: Some(RequestOptions {
+                    destination: Some("connection-0".to_string()),
+                    ..Default::default()
+                }),
+            },
+        )
+        .unwrap();
+
+        // replace the pair while the request is in flight, and open
+        // a channel for the new pair.
+        migrate(
+            deps.as_mut(),
+            env.clone(),
+            MigrateMsg::ReplacePair {
+                destination: Some("connection-0".to_string()),
+                pair: Pair {
+                    connection_id: "connection-1".to_string(),
+                    remote_port: "their_port".to_string(),
+                },
+            },
+        )
+        .unwrap();
+        ibc_channel_connect(
+            deps.as_mut(),
+            env.clone(),
+            IbcChannelConnectMsg::new_confirm(channel("channel-1", "connection-1")),
+        )
+        .unwrap();
+
+        // the ack for the request creates the account on the old
+        // connection.
+        let mut ack = mock_ibc_packet_ack(
+            "channel-0",
+            &(),
+            IbcAcknowledgement::new(ack_execute_success(vec![], "proxy".to_string())),
+        )
+        .unwrap();
+        ack.original_packet.sequence = 1;
+        ibc_packet_ack(deps.as_mut(), env.clone(), ack).unwrap();
+        let remote: Option<String> = from_json(
+            query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::RemoteAddress {
+                    local_address: "alice".to_string(),
+                    destination: Some("connection-0".to_string()),
+                    account_id: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(remote, Some("proxy".to_string()));
+
+        // closing the old channel leaves the new one open.
+        ibc_channel_close(
+            deps.as_mut(),
+            env.clone(),
+            IbcChannelCloseMsg::new_init(channel("channel-0", "connection-0")),
+        )
+        .unwrap();
+        assert!(!CHANNEL_TO_CONNECTION.has(deps.as_ref().storage, "channel-0".to_string()));
+        let destination: Option<DestinationResponse> = from_json(
+            query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::Destination {
+                    destination: "connection-1".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            destination.unwrap().active_channel,
+            Some("channel-1".to_string())
+        );
+
+        // as it is in single-pair mode.
+        let mut deps = mock_dependencies();
+        instantiate(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("creator", &[]),
+            InstantiateMsg {
+                pair: None,
+                block_max_gas: Uint64::new(110_000),
+                multi_pair: None,
+                timeouts: None,
+            },
+        )
+        .unwrap();
+        ibc_channel_connect(
+            deps.as_mut(),
+            env.clone(),
+            IbcChannelConnectMsg::new_confirm(channel("channel-0", "connection-0")),
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("alice", &[]),
+            ExecuteMsg::Execute {
+                msgs: vec![],
+                callback: None,
+                timeout_seconds: Some(Uint64::new(100)),
+                mode: None,
+                on_behalf_of: None,
+                options: None,
+            },
+        )
+        .unwrap();
+        migrate(
+            deps.as_mut(),
+            env.clone(),
+            MigrateMsg::ReplacePair {
+                destination: None,
+                pair: Pair {
+                    connection_id: "connection-1".to_string(),
+                    remote_port: "their_port".to_string(),
+                },
+            },
+        )
+        .unwrap();
+        ibc_channel_connect(
+            deps.as_mut(),
+            env.clone(),
+            IbcChannelConnectMsg::new_confirm(channel("channel-1", "connection-1")),
+        )
+        .unwrap();
+
+        // accounts are not stored by connection, so a late ack on the
+        // old channel records the account on the old voice, which
+        // `RemoteAddress` returns until it is used over the new pair.
+        let mut ack = mock_ibc_packet_ack(
+            "channel-0",
+            &(),
+            IbcAcknowledgement::new(ack_execute_success(vec![], "proxy".to_string())),
+        )
+        .unwrap();
+        ack.original_packet.sequence = 1;
+        ibc_packet_ack(deps.as_mut(), env.clone(), ack).unwrap();
+        let remote: Option<String> = from_json(
+            query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::RemoteAddress {
+                    local_address: "alice".to_string(),
+                    destination: None,
+                    account_id: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(remote, Some("proxy".to_string()));
+
+        ibc_channel_close(
+            deps.as_mut(),
+            env,
+            IbcChannelCloseMsg::new_init(channel("channel-0", "connection-0")),
+        )
+        .unwrap();
+        assert_eq!(
+            CHANNEL.load(deps.as_ref().storage).unwrap(),
+            "channel-1".to_string()
+        );
+    }
+
+    #[test]
+    fn test_timeouts() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let now = env.block.time;
+
+        let instantiate_msg = |timeouts| InstantiateMsg {
+            pair: None,
+            block_max_gas: Uint64::new(110_000),
+            multi_pair: None,
+            timeouts: Some(timeouts),
+        };
+        let err = instantiate(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("creator", &[]),
+            instantiate_msg(TimeoutConfig {
+                default_seconds: None,
+                min_seconds: Uint64::zero(),
+                max_seconds: Uint64::new(1000),
+            }),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::InvalidTimeouts);
+        instantiate(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("creator", &[]),
+            instantiate_msg(TimeoutConfig {
+                default_seconds: Some(Uint64::new(60)),
+                min_seconds: Uint64::new(10),
+                max_seconds: Uint64::new(1000),
+            }),
+        )
+        .unwrap();
+        CHANNEL
+            .save(deps.as_mut().storage, &"channel-0".to_string())
+            .unwrap();
+
+        let mut send = |timeout_seconds: Option<u64>, timeout: Option<RequestTimeout>| {
+            execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info("alice", &[]),
+                ExecuteMsg::Execute {
+                    msgs: vec![],
+                    callback: None,
+                    timeout_seconds: timeout_seconds.map(Uint64::new),
+                    mode: None,
+                    on_behalf_of: None,
+                    options: Some(RequestOptions {
+                        timeout,
+                        ..Default::default()
+                    }),
+                },
+            )
+            .map(|response| match &response.messages[0].msg {
+                CosmosMsg::Ibc(IbcMsg::SendPacket { timeout, .. }) => timeout.clone(),
+                _ => panic!("expected a packet to be sent"),
+            })
+        };
+        let block = IbcTimeoutBlock {
+            revision: 1,
+            height: 100,
+        };
+
+        assert_eq!(
+            send(None, None).unwrap(),
+            IbcTimeout::with_timestamp(now.plus_seconds(60))
+        );
+        assert_eq!(
+            send(Some(100), None).unwrap(),
+            IbcTimeout::with_timestamp(now.plus_seconds(100))
+        );
+        assert_eq!(
+            send(
+                None,
+                Some(RequestTimeout {
+                    timestamp: Some(now.plus_seconds(500)),
+                    block: Some(block),
+                })
+            )
+            .unwrap(),
+            IbcTimeout::with_both(block, now.plus_seconds(500))
+        );
+        assert_eq!(
+            send(
+                None,
+                Some(RequestTimeout {
+                    timestamp: None,
+                    block: Some(block),
+                })
+            )
+            .unwrap(),
+            IbcTimeout::with_block(block)
+        );
+
+        for seconds in [0, 5, 1001] {
+            assert_eq!(
+                send(Some(seconds), None).unwrap_err(),
+                ContractError::TimeoutOutOfBounds {
+                    seconds,
+                    min_seconds: 10,
+                    max_seconds: 1000,
+                }
+            );
+        }
+        assert!(matches!(
+            send(
+                None,
+                Some(RequestTimeout {
+                    timestamp: Some(now.minus_seconds(1)),
+                    block: None,
+                })
+            )
+            .unwrap_err(),
+            ContractError::TimeoutOutOfBounds { seconds: 0, .. }
+        ));
+        assert_eq!(
+            send(
+                Some(100),
+                Some(RequestTimeout {
+                    timestamp: Some(now.plus_seconds(100)),
+                    block: None,
+                })
+            )
+            .unwrap_err(),
+            ContractError::ConflictingTimeouts
+        );
+        assert_eq!(
+            send(
+                None,
+                Some(RequestTimeout {
+                    timestamp: None,
+                    block: Some(IbcTimeoutBlock {
+                        revision: 1,
+                        height: 0,
+                    }),
+                })
+            )
+            .unwrap_err(),
+            ContractError::ZeroTimeoutBlock
+        );
+
+        migrate(
+            deps.as_mut(),
+            env.clone(),
+            MigrateMsg::SetTimeouts {
+                timeouts: TimeoutConfig::default(),
+            },
+        )
+        .unwrap();
+        let timeouts: TimeoutConfig =
+            from_json(query(deps.as_ref(), env.clone(), QueryMsg::Timeouts).unwrap()).unwrap();
+        assert_eq!(timeouts, TimeoutConfig::default());
+        let err = execute(
+            deps.as_mut(),
+            env,
+            mock_info("alice", &[]),
+            ExecuteMsg::Execute {
+                msgs: vec![],
+                callback: None,
+                timeout_seconds: None,
+                mode: None,
+                on_behalf_of: None,
+                options: None,
+            },
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::NoTimeout);
+    }
+
+    /// Notes migrated with a block max gas too low to deliver
+    /// callbacks with may only be migrated to raise it.
+    #[test]
+    fn test_migrate_low_block_max_gas() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        BLOCK_MAX_GAS
+            .save(deps.as_mut().storage, &(ERR_GAS_NEEDED - 1))
+            .unwrap();
+
+        let set_timeouts = MigrateMsg::SetTimeouts {
+            timeouts: TimeoutConfig::default(),
+        };
+        let err = migrate(deps.as_mut(), env.clone(), set_timeouts.clone()).unwrap_err();
+        assert_eq!(err, ContractError::GasLimitsMismatch);
+        let err = migrate(
+            deps.as_mut(),
+            env.clone(),
+            MigrateMsg::WithUpdate {
+                block_max_gas: Uint64::new(ERR_GAS_NEEDED),
+            },
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::GasLimitsMismatch);
+
+        migrate(
+            deps.as_mut(),
+            env.clone(),
+            MigrateMsg::WithUpdate {
+                block_max_gas: Uint64::new(ERR_GAS_NEEDED + 1),
+            },
+        )
+        .unwrap();
+        migrate(deps.as_mut(), env, set_timeouts).unwrap();
+    }
+
+    /// Notes without timeouts set use `TimeoutConfig::default`, which
+    /// has no default timeout and bounds timeouts to between one
+    /// second and thirty days.
+    #[test]
+    fn test_unset_timeouts() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        instantiate(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("creator", &[]),
+            InstantiateMsg {
+                pair: None,
+                block_max_gas: Uint64::new(110_000),
+                multi_pair: None,
+                timeouts: None,
+            },
+        )
+        .unwrap();
+        CHANNEL
+            .save(deps.as_mut().storage, &"channel-0".to_string())
+            .unwrap();
+        let timeouts: TimeoutConfig =
+            from_json(query(deps.as_ref(), env.clone(), QueryMsg::Timeouts).unwrap()).unwrap();
+        assert_eq!(timeouts, TimeoutConfig::default());
+
+        let mut send = |timeout_seconds: Option<u64>| {
+            execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info("alice", &[]),
+                ExecuteMsg::Execute {
+                    msgs: vec![],
+                    callback: None,
+                    timeout_seconds: timeout_seconds.map(Uint64::new),
+                    mode: None,
+                    on_behalf_of: None,
+                    options: None,
+                },
+            )
+        };
+        assert_eq!(send(None).unwrap_err(), ContractError::NoTimeout);
+        for seconds in [0, 30 * 24 * 60 * 60 + 1, 365 * 24 * 60 * 60] {
+            assert_eq!(
+                send(Some(seconds)).unwrap_err(),
+                ContractError::TimeoutOutOfBounds {
+                    seconds,
+                    min_seconds: 1,
+                    max_seconds: 30 * 24 * 60 * 60,
+                }
+            );
+        }
+        for seconds in [1, 30 * 24 * 60 * 60] {
+            let response = send(Some(seconds)).unwrap();
+            let CosmosMsg::Ibc(IbcMsg::SendPacket { timeout, .. }) = &response.messages[0].msg
+            else {
+                panic!("expected a packet to be sent")
+            };
+            assert_eq!(
+                timeout,
+                &IbcTimeout::with_timestamp(env.block.time.plus_seconds(seconds))
+            );
+        }
+    }
+
+    /// Returns the packet sent by the response to a request.
+    fn sent_packet(response: &Response) -> ibc::Packet {
+        let CosmosMsg::Ibc(IbcMsg::SendPacket { data, .. }) = &response.messages[0].msg else {
+            panic!("expected a packet to be sent")
+        };
+        from_json(data).unwrap()
+    }
+
+    #[test]
+    fn test_gas_limit() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        instantiate(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("creator", &[]),
+            InstantiateMsg {
+                pair: None,
+                block_max_gas: Uint64::new(110_000),
+                multi_pair: None,
+                timeouts: None,
+            },
+        )
+        .unwrap();
+        CHANNEL
+            .save(deps.as_mut().storage, &"channel-0".to_string())
+            .unwrap();
+
+        for gas_limit in [None, Some(Uint64::new(500_000))] {
+            let response = execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info("alice", &[]),
+                ExecuteMsg::Execute {
+                    msgs: vec![],
+                    callback: None,
+                    timeout_seconds: Some(Uint64::new(100)),
+                    mode: None,
+                    on_behalf_of: None,
+                    options: Some(RequestOptions {
+                        gas_limit,
+                        ..Default::default()
+                    }),
+                },
+            )
+            .unwrap();
+            assert_eq!(sent_packet(&response).gas_limit, gas_limit);
+        }
+    }
+
+    #[test]
+    fn test_nonce() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        instantiate(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("creator", &[]),
+            InstantiateMsg {
+                pair: None,
+                block_max_gas: Uint64::new(110_000),
+                multi_pair: None,
+                timeouts: None,
+            },
+        )
+        .unwrap();
+        CHANNEL
+            .save(deps.as_mut().storage, &"channel-0".to_string())
+            .unwrap();
+
+        for nonce in [None, Some(Uint64::new(7))] {
+            let response = execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info("alice", &[]),
+                ExecuteMsg::Execute {
+                    msgs: vec![],
+                    callback: None,
+                    timeout_seconds: Some(Uint64::new(100)),
+                    mode: None,
+                    on_behalf_of: None,
+                    options: Some(RequestOptions {
+                        nonce,
+                        ..Default::default()
+                    }),
+                },
+            )
+            .unwrap();
+            assert_eq!(sent_packet(&response).nonce, nonce);
+        }
+    }
+
+    #[test]
+    fn test_execution_window() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        instantiate(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("creator", &[]),
+            InstantiateMsg {
+                pair: None,
+                block_max_gas: Uint64::new(110_000),
+                multi_pair: None,
+                timeouts: None,
+            },
+        )
+        .unwrap();
+        CHANNEL
+            .save(deps.as_mut().storage, &"channel-0".to_string())
+            .unwrap();
+
+        let mut send = |not_before: Option<u64>, not_after: Option<u64>| {
+            execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info("alice", &[]),
+                ExecuteMsg::Execute {
+                    msgs: vec![],
+                    callback: None,
+                    timeout_seconds: Some(Uint64::new(100)),
+                    mode: None,
+                    on_behalf_of: None,
+                    options: Some(RequestOptions {
+                        not_before: not_before.map(|s| env.block.time.plus_seconds(s)),
+                        not_after: not_after.map(|s| env.block.time.plus_seconds(s)),
+                        ..Default::default()
+                    }),
+                },
+            )
+        };
+
+        for (not_before, not_after) in [(None, Some(50)), (Some(1), None), (Some(1), Some(1))] {
+            let packet = sent_packet(&send(not_before, not_after).unwrap());
+            assert_eq!(
+                (packet.not_before, packet.not_after),
+                (
+                    not_before.map(|s| env.block.time.plus_seconds(s)),
+                    not_after.map(|s| env.block.time.plus_seconds(s))
+                )
+            );
+        }
+
+        let err = send(Some(2), Some(1)).unwrap_err();
+        assert_eq!(err, ContractError::InvalidWindow);
+    }
+
+    /// Delegates may send requests from their grantor's account
+    /// within the limits of their grant, and receive the callbacks.
+    #[test]
+    fn test_grants() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+
+        instantiate(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("creator", &[]),
+            InstantiateMsg {
+                pair: None,
+                block_max_gas: Uint64::new(110_000),
+                multi_pair: None,
+                timeouts: None,
+            },
+        )
+        .unwrap();
+        CHANNEL
+            .save(deps.as_mut().storage, &"channel-0".to_string())
+            .unwrap();
+
+        let send = |msgs: Vec<CosmosMsg>| ExecuteMsg::Execute {
+            msgs,
+            callback: Some(CallbackRequest {
+                receiver: "bob".to_string(),
+                msg: Binary::default(),
+                gas_limit: None,
+                delivery: None,
+                version: None,
+            }),
+            timeout_seconds: Some(Uint64::new(100)),
+            mode: None,
+            on_behalf_of: Some("alice".to_string()),
+            options: None,
+        };
+        let bank_send = || {
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                to_address: "carol".to_string(),
+                amount: coins(1, "ucoin"),
+            })]
+        };
+
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("bob", &[]),
+            send(vec![]),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::NoGrant {
+                grantor: "alice".to_string(),
+                delegate: "bob".to_string(),
+            }
+        );
+
+        let grant = |rate_limit| ExecuteMsg::Grant {
+            delegate: "bob".to_string(),
+            grant: Grant {
+                msg_types: Some(vec!["/cosmos.bank.v1beta1.MsgSend".to_string()]),
+                expires: Some(env.block.time.plus_seconds(100)),
+                rate_limit: Some(rate_limit),
+            },
+        };
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("alice", &[]),
+            grant(RateLimit {
+                period_seconds: Uint64::zero(),
+                max_packets: 1,
+            }),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::InvalidRateLimit);
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("alice", &[]),
+            grant(RateLimit {
+                period_seconds: Uint64::new(10),
+                max_packets: 2,
+            }),
+        )
+        .unwrap();
+
+        let grants: Vec<DelegateGrant> = from_json(
+            query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::Grants {
+                    grantor: "alice".to_string(),
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(grants.len(), 1);
+        assert_eq!(grants[0].delegate, "bob");
+
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("bob", &[]),
+            send(bank_send()),
+        )
+        .unwrap();
+        assert!(res
+            .attributes
+            .contains(&Attribute::new("on_behalf_of", "alice")));
+        assert!(res.attributes.contains(&Attribute::new("delegate", "bob")));
+        let packet: ibc::Packet = match &res.messages[0].msg {
+            CosmosMsg::Ibc(IbcMsg::SendPacket { data, .. }) => from_json(data).unwrap(),
+            _ => panic!("expected a packet to be sent"),
+        };
+        assert_eq!(packet.sender, "alice");
+        let callback =
+            callbacks::query_pending_callback(deps.as_ref().storage, "channel-0".to_string(), 1)
+                .unwrap()
+                .unwrap();
+        assert_eq!(callback.initiator, "bob");
+
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("bob", &[]),
+            send(vec![CosmosMsg::Wasm(WasmMsg::ClearAdmin {
+                contract_addr: "contract".to_string(),
+            })]),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::MsgTypeNotAllowed {
+                msg_type: "/cosmwasm.wasm.v1.MsgClearAdmin".to_string()
+            }
+        );
+
+        // the second packet of the period is allowed, the third is
+        // not, and the next period allows more.
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("bob", &[]),
+            send(bank_send()),
+        )
+        .unwrap();
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("bob", &[]),
+            send(bank_send()),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::GrantRateLimited {
+                max_packets: 2,
+                period_seconds: 10,
+            }
+        );
+        env.block.time = env.block.time.plus_seconds(10);
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("bob", &[]),
+            send(bank_send()),
+        )
+        .unwrap();
+
+        env.block.time = env.block.time.plus_seconds(90);
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("bob", &[]),
+            send(bank_send()),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::GrantExpired {
+                grantor: "alice".to_string()
+            }
+        );
+
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("alice", &[]),
+            ExecuteMsg::Revoke {
+                delegate: "bob".to_string(),
+            },
+        )
+        .unwrap();
+        let grant: Option<Grant> = from_json(
+            query(
+                deps.as_ref(),
+                env,
+                QueryMsg::Grant {
+                    grantor: "alice".to_string(),
+                    delegate: "bob".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(grant, None);
+    }
+
+    /// Conditional and templated requests may be sent on behalf of a
+    /// grantor, and templated ones only if the grant allows any
+    /// message type.
+    #[test]
+    fn test_grant_request_types() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        instantiate(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("creator", &[]),
+            InstantiateMsg {
+                pair: None,
+                block_max_gas: Uint64::new(110_000),
+                multi_pair: None,
+                timeouts: None,
+            },
+        )
+        .unwrap();
+        CHANNEL
+            .save(deps.as_mut().storage, &"channel-0".to_string())
+            .unwrap();
+
+        let grant = |deps: DepsMut, msg_types: Option<Vec<String>>| {
+            execute(
+                deps,
+                env.clone(),
+                mock_info("alice", &[]),
+                ExecuteMsg::Grant {
+                    delegate: "bob".to_string(),
+                    grant: Grant {
+                        msg_types,
+                        expires: None,
+                        rate_limit: None,
+                    },
+                },
+            )
+            .unwrap();
+        };
+        grant(
+            deps.as_mut(),
+            Some(vec!["/cosmos.bank.v1beta1.MsgSend".to_string()]),
+        );
+
+        let conditional = |msgs: Vec<CosmosMsg>| ExecuteMsg::ConditionalExecute {
+            queries: vec![],
+            conditions: vec![],
+            msgs,
+            callback: None,
+            timeout_seconds: Some(Uint64::new(100)),
+            on_behalf_of: Some("alice".to_string()),
+            options: None,
+        };
+        let templated = || ExecuteMsg::TemplatedExecute {
+            queries: vec![],
+            msgs: vec![],
+            callback: None,
+            timeout_seconds: Some(Uint64::new(100)),
+            on_behalf_of: Some("alice".to_string()),
+            options: None,
+        };
+        let send =
+            |deps: DepsMut, msg: ExecuteMsg| execute(deps, env.clone(), mock_info("bob", &[]), msg);
+
+        let err = send(
+            deps.as_mut(),
+            conditional(vec![CosmosMsg::Wasm(WasmMsg::ClearAdmin {
+                contract_addr: "contract".to_string(),
+            })]),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::MsgTypeNotAllowed {
+                msg_type: "/cosmwasm.wasm.v1.MsgClearAdmin".to_string()
+            }
+        );
+        let res = send(
+            deps.as_mut(),
+            conditional(vec![CosmosMsg::Bank(BankMsg::Send {
+                to_address: "carol".to_string(),
+                amount: coins(1, "ucoin"),
+            })]),
+        )
+        .unwrap();
+        assert_eq!(sent_packet(&res).sender, "alice");
+
+        let err = send(deps.as_mut(), templated()).unwrap_err();
+        assert_eq!(err, ContractError::TemplatesNotAllowed);
+        grant(deps.as_mut(), None);
+        let res = send(deps.as_mut(), templated()).unwrap();
+        assert_eq!(sent_packet(&res).sender, "alice");
     }
 }
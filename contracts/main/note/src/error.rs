@@ -20,6 +20,72 @@ pub enum ContractError {
     #[error("contract has no pair, establish a channel with a voice module to create one")]
     NoPair,
 
+    #[error("contract has no pair on ({destination}), establish a channel with a voice module to create one")]
+    NoPairFor { destination: String },
+
+    #[error("contract pairs with many voices, requests must specify a destination")]
+    NoDestination,
+
+    #[error("contract already has a pair on connection ({connection_id})")]
+    ConnectionPaired { connection_id: String },
+
+    #[error("contract has no pair on connection ({connection_id}), the contract admin may add one with `MigrateMsg::AddPair`")]
+    UnknownConnection { connection_id: String },
+
+    #[error("contract pairs with one voice, use `MigrateMsg::ReplacePair` to change it")]
+    SinglePair,
+
+    #[error("no failed callback for sequence ({sequence}) on channel ({channel_id})")]
+    NoFailedCallback { channel_id: String, sequence: u64 },
+
+    #[error("channel ({channel_id}) is not closed")]
+    ChannelNotClosed { channel_id: String },
+
+    #[error(
+        "timeout of ({seconds}) seconds is not between ({min_seconds}) and ({max_seconds}) seconds"
+    )]
+    TimeoutOutOfBounds {
+        seconds: u64,
+        min_seconds: u64,
+        max_seconds: u64,
+    },
+
+    #[error("request has no timeout and the note has no default")]
+    NoTimeout,
+
+    #[error("request may set one of `timeout_seconds` and `timeout.timestamp`")]
+    ConflictingTimeouts,
+
+    #[error("timeout block height must be greater than zero")]
+    ZeroTimeoutBlock,
+
+    #[error("invalid timeout config, need 0 < min_seconds <= default_seconds <= max_seconds")]
+    InvalidTimeouts,
+
+    #[error("`not_before` must not be after `not_after`")]
+    InvalidWindow,
+
+    #[error("({delegate}) has no grant from ({grantor})")]
+    NoGrant { grantor: String, delegate: String },
+
+    #[error("grant from ({grantor}) expired")]
+    GrantExpired { grantor: String },
+
+    #[error("grant does not allow messages of type ({msg_type})")]
+    MsgTypeNotAllowed { msg_type: String },
+
+    #[error("grant limited to message types does not allow templated messages")]
+    TemplatesNotAllowed,
+
+    #[error("grant allows at most ({max_packets}) packets every ({period_seconds}) seconds")]
+    GrantRateLimited {
+        max_packets: u32,
+        period_seconds: u64,
+    },
+
+    #[error("grant rate limits must have a non-zero period and packet count")]
+    InvalidRateLimit,
+
     #[error("ERR_GAS_NEEDED can't be higher then BLOCK_MAX_GAS")]
     GasLimitsMismatch,
 
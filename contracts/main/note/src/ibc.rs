@@ -1,21 +1,31 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    DepsMut, Env, IbcBasicResponse, IbcChannelCloseMsg, IbcChannelConnectMsg, IbcChannelOpenMsg,
-    IbcChannelOpenResponse, IbcPacketAckMsg, IbcPacketReceiveMsg, IbcPacketTimeoutMsg,
-    IbcReceiveResponse, Never, Reply, Response, SubMsg,
+    to_json_binary, DepsMut, Env, IbcBasicResponse, IbcChannelCloseMsg, IbcChannelConnectMsg,
+    IbcChannelOpenMsg, IbcChannelOpenResponse, IbcPacketAckMsg, IbcPacketReceiveMsg,
+    IbcPacketTimeoutMsg, IbcReceiveResponse, Never, Reply, Response, Storage, SubMsg, WasmMsg,
+};
+use polytone::{
+    accounts,
+    callbacks::{self, QueuedCallback},
+    handshake::note,
 };
-use polytone::{accounts, callbacks, handshake::note};
 
 use crate::{
     error::ContractError,
-    state::{BLOCK_MAX_GAS, CHANNEL, CONNECTION_REMOTE_PORT},
+    msg::ExecuteMsg,
+    state::{
+        is_multi_pair, BLOCK_MAX_GAS, CHANNEL, CHANNELS, CHANNEL_TO_CONNECTION, CLOSED_CHANNELS,
+        CONNECTION_REMOTE_PORT, PAIRS, PENDING_PACKETS,
+    },
 };
 
 /// The amount of gas that needs to be reserved for handling a
 /// callback error in the reply method. See `TestNoteOutOfGas` in the
-/// simulation tests for a test that can be used to tune thise.
-pub(crate) const ERR_GAS_NEEDED: u64 = 101_000;
+/// simulation tests for a test that can be used to tune thise. This
+/// includes roughly 7,000 gas for finding the callback's channel and
+/// recording the error, which wasmd redacts to a short string.
+pub(crate) const ERR_GAS_NEEDED: u64 = 108_000;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn ibc_channel_open(
@@ -24,7 +34,20 @@ pub fn ibc_channel_open(
     msg: IbcChannelOpenMsg,
 ) -> Result<IbcChannelOpenResponse, ContractError> {
     let response = note::open(&msg, &["JSON-CosmosMsg"])?;
-    match CONNECTION_REMOTE_PORT.may_load(deps.storage)? {
+    let pair = if is_multi_pair(deps.storage)? {
+        // in multi-pair mode, pairs are only ever added by the
+        // contract admin.
+        let conn = msg.channel().connection_id.clone();
+        let port = PAIRS.may_load(deps.storage, conn.clone())?.ok_or(
+            ContractError::UnknownConnection {
+                connection_id: conn.clone(),
+            },
+        )?;
+        Some((conn, port))
+    } else {
+        CONNECTION_REMOTE_PORT.may_load(deps.storage)?
+    };
+    match pair {
         Some((conn, port)) => {
             if msg.channel().counterparty_endpoint.port_id != port
                 || msg.channel().connection_id != conn
@@ -50,14 +73,33 @@ pub fn ibc_channel_connect(
     msg: IbcChannelConnectMsg,
 ) -> Result<IbcBasicResponse, ContractError> {
     note::connect(&msg, &["JSON-CosmosMsg"])?;
-    CONNECTION_REMOTE_PORT.save(
-        deps.storage,
-        &(
-            msg.channel().connection_id.clone(),
-            msg.channel().counterparty_endpoint.port_id.clone(),
-        ),
-    )?;
-    CHANNEL.save(deps.storage, &msg.channel().endpoint.channel_id)?;
+    if is_multi_pair(deps.storage)? {
+        let connection_id = msg.channel().connection_id.clone();
+        PAIRS.save(
+            deps.storage,
+            connection_id.clone(),
+            &msg.channel().counterparty_endpoint.port_id,
+        )?;
+        CHANNELS.save(
+            deps.storage,
+            connection_id.clone(),
+            &msg.channel().endpoint.channel_id,
+        )?;
+        CHANNEL_TO_CONNECTION.save(
+            deps.storage,
+            msg.channel().endpoint.channel_id.clone(),
+            &connection_id,
+        )?;
+    } else {
+        CONNECTION_REMOTE_PORT.save(
+            deps.storage,
+            &(
+                msg.channel().connection_id.clone(),
+                msg.channel().counterparty_endpoint.port_id.clone(),
+            ),
+        )?;
+        CHANNEL.save(deps.storage, &msg.channel().endpoint.channel_id)?;
+    }
     Ok(IbcBasicResponse::new()
         .add_attribute("method", "ibc_channel_connect")
         .add_attribute("channel_id", &msg.channel().endpoint.channel_id))
@@ -66,12 +108,35 @@ pub fn ibc_channel_connect(
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn ibc_channel_close(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     msg: IbcChannelCloseMsg,
 ) -> Result<IbcBasicResponse, ContractError> {
-    CHANNEL.remove(deps.storage);
+    // the closing channel may have been replaced by `ReplacePair`,
+    // and a channel opened for the new pair, which is left open.
+    let channel_id = &msg.channel().endpoint.channel_id;
+    if is_multi_pair(deps.storage)? {
+        let connection_id = msg.channel().connection_id.clone();
+        if CHANNELS
+            .may_load(deps.storage, connection_id.clone())?
+            .as_ref()
+            == Some(channel_id)
+        {
+            CHANNELS.remove(deps.storage, connection_id);
+        }
+    } else if CHANNEL.may_load(deps.storage)?.as_ref() == Some(channel_id) {
+        CHANNEL.remove(deps.storage);
+    }
+    CHANNEL_TO_CONNECTION.remove(deps.storage, channel_id.clone());
+    // requests in flight are flushed by `ExecuteMsg::FlushClosedChannel`
+    // rather than here, as there may be more than fit in one block.
+    CLOSED_CHANNELS.save(
+        deps.storage,
+        msg.channel().endpoint.channel_id.clone(),
+        &env.block.time,
+    )?;
     Ok(IbcBasicResponse::default()
         .add_attribute("method", "ibc_channel_close")
+        .add_attribute("channel_id", msg.channel().endpoint.channel_id.clone())
         .add_attribute("connection_id", msg.channel().connection_id.clone())
         .add_attribute(
             "counterparty_port_id",
@@ -91,25 +156,28 @@ pub fn ibc_packet_receive(
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn ibc_packet_ack(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     ack: IbcPacketAckMsg,
 ) -> Result<IbcBasicResponse, ContractError> {
-    let (callback, executed_by) = callbacks::on_ack(deps.storage, &ack);
-    let callback = callback.map(|callback| {
-        SubMsg::reply_on_error(callback, ack.original_packet.sequence).with_gas_limit(
-            BLOCK_MAX_GAS
-                .load(deps.storage)
-                .expect("set during instantiation")
-                - ERR_GAS_NEEDED,
-        )
-    });
+    let (callback, executed_by) = callbacks::on_ack(deps.storage, &ack)?;
+    let callback = callback.map(|callback| callback_submsg(deps.storage, &env, callback));
 
+    let connection_id =
+        CHANNEL_TO_CONNECTION.may_load(deps.storage, ack.original_packet.src.channel_id.clone())?;
     accounts::on_ack(
         deps.storage,
         ack.original_packet.src.channel_id.clone(),
         ack.original_packet.sequence,
+        connection_id,
         executed_by,
     );
+    PENDING_PACKETS.remove(
+        deps.storage,
+        (
+            ack.original_packet.src.channel_id.clone(),
+            ack.original_packet.sequence,
+        ),
+    );
 
     Ok(IbcBasicResponse::default()
         .add_attribute("method", "ibc_packet_ack")
@@ -120,18 +188,16 @@ pub fn ibc_packet_ack(
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn ibc_packet_timeout(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     msg: IbcPacketTimeoutMsg,
 ) -> Result<IbcBasicResponse, ContractError> {
-    let callback = callbacks::on_timeout(deps.storage, &msg).map(|cosmos_msg| {
-        SubMsg::reply_on_error(cosmos_msg, msg.packet.sequence).with_gas_limit(
-            BLOCK_MAX_GAS
-                .load(deps.storage)
-                .expect("set during instantiation")
-                - ERR_GAS_NEEDED,
-        )
-    });
+    let callback = callbacks::on_timeout(deps.storage, &msg, env.block.time)?
+        .map(|callback| callback_submsg(deps.storage, &env, callback));
 
+    PENDING_PACKETS.remove(
+        deps.storage,
+        (msg.packet.src.channel_id.clone(), msg.packet.sequence),
+    );
     accounts::on_timeout(deps.storage, msg.packet.src.channel_id, msg.packet.sequence);
 
     Ok(IbcBasicResponse::default()
@@ -140,11 +206,48 @@ pub fn ibc_packet_timeout(
         .add_submessages(callback))
 }
 
+/// Delivers a queued callback with the gas limit requested for it,
+/// clamped to the most that leaves enough gas to handle it failing.
+///
+/// The callback is delivered by the contract executing
+/// `ExecuteMsg::RetryCallback` on itself, so that if delivery fails
+/// the callback is kept for retrying without needing to be saved in
+/// the reply, which has little gas.
+pub(crate) fn callback_submsg(
+    storage: &dyn Storage,
+    env: &Env,
+    QueuedCallback {
+        channel_id,
+        sequence,
+        gas_limit,
+    }: QueuedCallback,
+) -> SubMsg {
+    let max_gas = BLOCK_MAX_GAS
+        .load(storage)
+        .expect("set during instantiation")
+        - ERR_GAS_NEEDED;
+    SubMsg::reply_on_error(
+        WasmMsg::Execute {
+            contract_addr: env.contract.address.to_string(),
+            msg: to_json_binary(&ExecuteMsg::RetryCallback {
+                channel_id,
+                sequence,
+            })
+            .expect("fields are known to be serializable"),
+            funds: vec![],
+        },
+        sequence,
+    )
+    .with_gas_limit(gas_limit.map_or(max_gas, |limit| limit.u64().min(max_gas)))
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn reply(_deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
     let sequence = msg.id;
+    let error = msg.result.unwrap_err();
+    callbacks::on_callback_error(deps.storage, sequence, error.clone())?;
     Ok(Response::default()
         .add_attribute("method", "reply_callback_error")
         .add_attribute("packet_sequence", sequence.to_string())
-        .add_attribute("callback_error", msg.result.unwrap_err()))
+        .add_attribute("callback_error", error))
 }
@@ -1,7 +1,12 @@
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::{CosmosMsg, Empty, QueryRequest, Uint64};
+use cosmwasm_std::{CosmosMsg, Empty, IbcTimeoutBlock, QueryRequest, Timestamp, Uint64};
 
-use polytone::callbacks::CallbackRequest;
+use polytone::callbacks::{CallbackRequest, CallbackRequestType, FailedCallback};
+use polytone::conditions::Condition;
+use polytone::ibc::ExecuteMode;
+use polytone::value::Value;
+
+use crate::state::PairChange;
 
 #[cw_serde]
 pub struct InstantiateMsg {
@@ -20,6 +25,104 @@ pub struct InstantiateMsg {
     ///
     /// The contract admin can update with `MigrateMsg::WithUpdate`.
     pub block_max_gas: Uint64,
+
+    /// If true, this contract pairs with one voice per connection
+    /// instead of with a single voice, and requests must specify
+    /// the destination they are sent to. If a `pair` is specified it
+    /// is the first of these, and the contract admin may add more
+    /// with `MigrateMsg::AddPair`. Handshakes on connections without
+    /// a pair are rejected. Defaults to false.
+    pub multi_pair: Option<bool>,
+
+    /// The default and bounds for request timeouts. If none,
+    /// `TimeoutConfig::default` is used.
+    ///
+    /// The contract admin can update with `MigrateMsg::SetTimeouts`.
+    pub timeouts: Option<TimeoutConfig>,
+}
+
+/// The default and bounds the note enforces on request timeouts.
+/// Block height timeouts are not bounded, as the note can not know
+/// the voice chain's current height.
+#[cw_serde]
+pub struct TimeoutConfig {
+    /// The timeout, in seconds, of requests that do not specify one.
+    /// If none, requests must specify a timeout.
+    pub default_seconds: Option<Uint64>,
+    /// The least number of seconds after being sent that a request
+    /// may time out. Must be at least one.
+    pub min_seconds: Uint64,
+    /// The most number of seconds after being sent that a request
+    /// may time out.
+    pub max_seconds: Uint64,
+}
+
+impl Default for TimeoutConfig {
+    /// No default timeout, and timeouts between one second and thirty
+    /// days. Used by notes that have not set their timeouts.
+    fn default() -> Self {
+        Self {
+            default_seconds: None,
+            min_seconds: Uint64::one(),
+            max_seconds: Uint64::new(30 * 24 * 60 * 60),
+        }
+    }
+}
+
+/// A request timeout. If both `timestamp` and `block` are set, the
+/// request times out at whichever is reached first.
+#[cw_serde]
+pub struct RequestTimeout {
+    /// The voice chain block time at which the request times out.
+    /// Must be within the note's timeout bounds.
+    pub timestamp: Option<Timestamp>,
+    /// The voice chain block height at which the request times out.
+    /// Not bounded by the note, as it can not know the voice chain's
+    /// current height.
+    pub block: Option<IbcTimeoutBlock>,
+}
+
+/// Options for a request sent to the voice. Every option is unset
+/// by default.
+#[cw_serde]
+#[derive(Default)]
+pub struct RequestOptions {
+    /// An absolute timestamp or remote block height timeout, used in
+    /// place of the request's `timeout_seconds`.
+    pub timeout: Option<RequestTimeout>,
+    /// The connection ID, or alias of one, of the voice to send the
+    /// request to. Required if the contract is in multi-pair mode.
+    pub destination: Option<String>,
+    /// The maximum amount of gas the voice may use executing the
+    /// request. Clamped to the most the voice chain allows, which is
+    /// also the default.
+    pub gas_limit: Option<Uint64>,
+    /// The earliest voice chain block time at which the voice may
+    /// execute the request. If received earlier, the request is not
+    /// executed and gets a `Callback::OutsideWindow`.
+    pub not_before: Option<Timestamp>,
+    /// The latest voice chain block time at which the voice may
+    /// execute the request, as for `not_before`.
+    pub not_after: Option<Timestamp>,
+    /// If set, the voice only executes the request if this is one
+    /// more than the nonce of the last request it executed from the
+    /// sender with a nonce, or one if there is none. Otherwise the
+    /// request gets a `Callback::OutOfOrder`.
+    pub nonce: Option<Uint64>,
+    /// If set, the voice does not execute the request's messages if
+    /// the sender's account has successfully executed messages for a
+    /// request with the same key recently, and the request gets an
+    /// execution error of `callbacks::DUPLICATE_IDEMPOTENCY_KEY`.
+    /// This makes it safe to resend a request that may or may not
+    /// have been executed, and a request that failed may be resent
+    /// with the same key. Only used by requests that execute
+    /// messages.
+    pub idempotency_key: Option<String>,
+    /// If set, the request is for the sender's remote account with
+    /// this ID instead of its default account, so that a sender can
+    /// keep many isolated remote accounts. Queryable via
+    /// `RemoteAddress { account_id, .. }`. Not used by queries.
+    pub account_id: Option<String>,
 }
 
 #[cw_serde]
@@ -32,7 +135,15 @@ pub enum ExecuteMsg {
     Query {
         msgs: Vec<QueryRequest<Empty>>,
         callback: CallbackRequest,
-        timeout_seconds: Uint64,
+        /// Seconds after which the request times out. Defaults to
+        /// the note's default timeout if neither this nor
+        /// `options.timeout` is set.
+        timeout_seconds: Option<Uint64>,
+        /// If true, every query is performed even if some fail and
+        /// the callback contains the result of each. Defaults to
+        /// false, where the first failing query fails the batch.
+        partial: Option<bool>,
+        options: Option<RequestOptions>,
     },
     /// Executes the requested messages on the voice chain on behalf
     /// of the note chain sender. Message receivers can return data in
@@ -44,13 +155,114 @@ pub enum ExecuteMsg {
     /// to execute by the local address. To create an account, but
     /// perform no additional actions, pass an empty list to
     /// `msgs`. Accounts are queryable via the `RemoteAddress {
-    /// local_address }` query after they have been created.
+    /// local_address, destination }` query after they have been
+    /// created.
     #[cfg_attr(feature = "interface", fn_name("ibc_execute"))]
     Execute {
         msgs: Vec<CosmosMsg<Empty>>,
         callback: Option<CallbackRequest>,
-        timeout_seconds: Uint64,
+        /// As for `Query`.
+        timeout_seconds: Option<Uint64>,
+        /// How the messages are executed. Defaults to
+        /// `ExecuteMode::Atomic`, where if any message fails all of
+        /// them are rolled back.
+        mode: Option<ExecuteMode>,
+        /// If set, the request is sent on behalf of this address,
+        /// which must have granted the sender permission to do so
+        /// with `ExecuteMsg::Grant`. The request is executed by
+        /// `on_behalf_of`'s remote account and the sender receives
+        /// its callback.
+        on_behalf_of: Option<String>,
+        options: Option<RequestOptions>,
+    },
+    /// Performs `queries` on the voice chain, and if every one of
+    /// `conditions` holds for their responses executes `msgs` on
+    /// behalf of the sender as `Execute` does. The callback contains
+    /// the query results, the first condition that did not hold if
+    /// any, and the result of executing the messages if they were.
+    ConditionalExecute {
+        queries: Vec<QueryRequest<Empty>>,
+        conditions: Vec<Condition>,
+        msgs: Vec<CosmosMsg<Empty>>,
+        callback: Option<CallbackRequest>,
+        timeout_seconds: Option<Uint64>,
+        /// As for `Execute`.
+        on_behalf_of: Option<String>,
+        options: Option<RequestOptions>,
+    },
+    /// Performs `queries` on the voice chain, fills the placeholders
+    /// in `msgs` with values from their responses, and executes the
+    /// resulting messages on behalf of the sender as `Execute` does.
+    /// For example, the template
+    ///
+    /// ```json
+    /// {"bank":{"send":{"to_address":"...","amount":[{"denom":"ucosm","amount":"$q0.amount.amount"}]}}}
+    /// ```
+    ///
+    /// sends the balance returned by query 0. See
+    /// `polytone::template::resolve_templates` for the placeholder
+    /// format.
+    TemplatedExecute {
+        queries: Vec<QueryRequest<Empty>>,
+        msgs: Vec<Value>,
+        callback: Option<CallbackRequest>,
+        timeout_seconds: Option<Uint64>,
+        /// As for `Execute`. Grants limited to message types do not
+        /// allow templated requests, as the types of their messages
+        /// are not known until they are resolved.
+        on_behalf_of: Option<String>,
+        options: Option<RequestOptions>,
+    },
+    /// Delivers a callback that failed to be delivered again. May be
+    /// called by anyone, and the callback is removed once it is
+    /// delivered successfully. See `QueryMsg::FailedCallbacks`.
+    RetryCallback { channel_id: String, sequence: u64 },
+    /// Delivers a `Callback::ChannelClosed` callback for up to `limit`
+    /// requests still in flight on `channel_id`, which must have
+    /// closed, and stops tracking them. May be called by anyone, and
+    /// repeatedly until `QueryMsg::PendingPackets` lists no packets
+    /// on the channel.
+    FlushClosedChannel {
+        channel_id: String,
+        limit: Option<u32>,
+    },
+    /// Makes `new_owner` the controller of the sender's remote
+    /// account, keeping its remote address. The account with
+    /// `options.account_id` is transferred, or the sender's default
+    /// account if it is `None`, and it keeps its account ID with its
+    /// new owner. The sender's next account with the ID gets a new
+    /// remote address. Nonces are per sender and are not
+    /// transferred, so `new_owner` keeps its own sequence.
+    ///
+    /// On success, returns a callback of `Callback::Execute` with no
+    /// results, and `RemoteAddress` returns the account for
+    /// `new_owner`. If the sender has no account with the ID, or
+    /// `new_owner` already has one, returns a `Callback::Execute`
+    /// error saying so.
+    TransferAccount {
+        new_owner: String,
+        callback: Option<CallbackRequest>,
+        timeout_seconds: Option<Uint64>,
+        options: Option<RequestOptions>,
     },
+    /// Sets whether the sender's remote account is skipped when the
+    /// voice's admin migrates accounts to new code. Accounts are
+    /// migrated unless opted out. The account with
+    /// `options.account_id` is updated, or the sender's default
+    /// account if it is `None`. On success, returns a callback of
+    /// `Callback::Execute` with no results.
+    SetMigrationOptOut {
+        opt_out: bool,
+        callback: Option<CallbackRequest>,
+        timeout_seconds: Option<Uint64>,
+        options: Option<RequestOptions>,
+    },
+    /// Allows `delegate` to send `ExecuteMsg::Execute` requests on
+    /// behalf of the sender, within the limits of `grant`. Replaces
+    /// any grant the sender has already given `delegate`.
+    Grant { delegate: String, grant: Grant },
+    /// Removes the grant the sender has given `delegate`, if any.
+    Revoke { delegate: String },
 }
 
 #[cw_serde]
@@ -58,21 +270,146 @@ pub enum ExecuteMsg {
 #[cfg_attr(feature = "interface", derive(cw_orch::QueryFns))] // cw-orch automatic
 pub enum QueryMsg {
     /// This channel this note is currently connected to, or none if
-    /// no channel is connected.
+    /// no channel is connected. Errors in multi-pair mode, see
+    /// `Destination`.
     #[returns(Option<String>)]
     ActiveChannel,
-    /// The contract's corresponding voice on a remote chain.
+    /// The contract's corresponding voice on a remote chain. Errors
+    /// in multi-pair mode, see `Destination`.
     #[returns(Option<Pair>)]
     Pair,
-    /// Returns the remote address for the provided local address. If
-    /// no account exists, returns `None`. An account can be created
-    /// by calling `ExecuteMsg::Execute` with the sender being
-    /// `local_address`.
+    /// Returns the remote address for the provided local address on
+    /// `destination`. If no account exists, returns `None`. An
+    /// account can be created by calling `ExecuteMsg::Execute` with
+    /// the sender being `local_address`. `destination` is ignored if
+    /// the contract is not in multi-pair mode. If `account_id` is
+    /// set, returns the remote address of the sub-account with that
+    /// ID instead.
+    #[returns(Option<String>)]
+    RemoteAddress {
+        local_address: String,
+        destination: Option<String>,
+        account_id: Option<String>,
+    },
+    /// Returns the local address that controls the provided remote
+    /// address on `destination`, or `None` if there is none.
+    /// `destination` is ignored if the contract is not in multi-pair
+    /// mode.
+    ///
+    /// Accounts last used before this query was added are not found
+    /// until they are used again or indexed with
+    /// `MigrateMsg::IndexAccounts`.
     #[returns(Option<String>)]
-    RemoteAddress { local_address: String },
+    LocalAddress {
+        remote_address: String,
+        destination: Option<String>,
+    },
+    /// Lists local addresses and their remote addresses on
+    /// `destination`, ordered by local address. `destination` is
+    /// ignored if the contract is not in multi-pair mode.
+    #[returns(Vec<RemoteAccount>)]
+    RemoteAccounts {
+        destination: Option<String>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Lists the sub-accounts `local_address` has on `destination`
+    /// and their remote addresses, ordered by account ID.
+    /// `destination` is ignored if the contract is not in multi-pair
+    /// mode.
+    #[returns(Vec<SubAccount>)]
+    SubAccounts {
+        local_address: String,
+        destination: Option<String>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// The pair and active channel for a connection ID, or alias of
+    /// one, or none if the contract has no pair there.
+    #[returns(Option<DestinationResponse>)]
+    Destination { destination: String },
+    /// Lists the pairs this contract has, ordered by connection ID.
+    #[returns(Vec<DestinationResponse>)]
+    Destinations {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Lists the changes the contract admin has made to this
+    /// contract's pairs with `MigrateMsg::ReplacePair`, oldest
+    /// first. Accounts created by a replaced pair's voice are not
+    /// controlled via the replacement.
+    #[returns(Vec<(u64, PairChange)>)]
+    PairHistory {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Lists packets that have been sent but not yet ACKed or timed
+    /// out, ordered by `(channel_id, sequence)`. Optionally, only
+    /// those sent by `sender` or sent on `channel_id`.
+    #[returns(Vec<PendingPacket>)]
+    PendingPackets {
+        sender: Option<String>,
+        channel_id: Option<String>,
+        start_after: Option<(String, u64)>,
+        limit: Option<u32>,
+    },
     /// Currently set gas limit
     #[returns(Uint64)]
     BlockMaxGas,
+    /// The default and bounds for request timeouts.
+    #[returns(TimeoutConfig)]
+    Timeouts,
+    /// Lists callbacks that failed to be delivered, ordered by
+    /// `(channel_id, sequence)`. These may be retried with
+    /// `ExecuteMsg::RetryCallback`.
+    #[returns(Vec<FailedCallback>)]
+    FailedCallbacks {
+        start_after: Option<(String, u64)>,
+        limit: Option<u32>,
+    },
+    /// The grant `grantor` has given `delegate`, or `None` if there
+    /// is none.
+    #[returns(Option<Grant>)]
+    Grant { grantor: String, delegate: String },
+    /// Lists the grants `grantor` has given, ordered by delegate.
+    #[returns(Vec<DelegateGrant>)]
+    Grants {
+        grantor: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+}
+
+/// Permission to send `ExecuteMsg::Execute`,
+/// `ExecuteMsg::ConditionalExecute`, and `ExecuteMsg::TemplatedExecute`
+/// requests on behalf of another address. See `ExecuteMsg::Grant`.
+#[cw_serde]
+pub struct Grant {
+    /// Type URLs of the messages the delegate may send, for example
+    /// `/cosmos.bank.v1beta1.MsgSend`. If `None`, any message may be
+    /// sent. If set, templated requests may not be sent.
+    pub msg_types: Option<Vec<String>>,
+    /// The block time at which the grant expires. If `None`, the
+    /// grant does not expire.
+    pub expires: Option<Timestamp>,
+    /// The most requests the delegate may send in a period. If
+    /// `None`, the number of requests is not limited.
+    pub rate_limit: Option<RateLimit>,
+}
+
+#[cw_serde]
+pub struct RateLimit {
+    /// The length of a period. A period starts with the first request
+    /// sent after the previous one ended.
+    pub period_seconds: Uint64,
+    /// The most packets that may be sent in a period.
+    pub max_packets: u32,
+}
+
+#[cw_serde]
+pub struct DelegateGrant {
+    pub delegate: String,
+    pub grant: Grant,
 }
 
 /// This contract's voice. There is one voice per note, and many notes
@@ -83,9 +420,106 @@ pub struct Pair {
     pub remote_port: String,
 }
 
+/// Set as the data of `ExecuteMsg::Execute` and `ExecuteMsg::Query`
+/// responses. Identifies the request's packet, and matches the
+/// `channel_id` and `sequence` of its callback.
+#[cw_serde]
+pub struct ExecuteResponse {
+    pub channel_id: String,
+    pub sequence: u64,
+}
+
+#[cw_serde]
+pub struct RemoteAccount {
+    pub local_address: String,
+    pub remote_address: String,
+}
+
+#[cw_serde]
+pub struct SubAccount {
+    pub account_id: String,
+    pub remote_address: String,
+}
+
+#[cw_serde]
+pub struct PendingPacket {
+    pub channel_id: String,
+    pub sequence: u64,
+    /// The address that sent the request.
+    pub initiator: String,
+    /// The address that will receive a callback for the request, if
+    /// one was requested.
+    pub callback_receiver: Option<String>,
+    /// The type of the request. `None` for packets sent before this
+    /// was recorded.
+    pub request_type: Option<CallbackRequestType>,
+    /// The block time the packet was sent at. `None` for packets sent
+    /// before this was recorded.
+    pub sent_at: Option<Timestamp>,
+}
+
+#[cw_serde]
+pub struct DestinationResponse {
+    pub pair: Pair,
+    /// The channel currently connected to the pair, if any.
+    pub active_channel: Option<String>,
+}
+
 #[cw_serde]
 pub enum MigrateMsg {
     /// Updates the contract's configuration. To update the config
-    /// without updating the code, migrate to the same code ID.
+    /// without updating the code, migrate to the same code ID. Notes
+    /// whose block max gas is too low for this version must migrate
+    /// with this to raise it, as other migrations are rejected.
     WithUpdate { block_max_gas: Uint64 },
+    /// Indexes up to `limit` accounts on `destination` after
+    /// `start_after`, ordered by local address, so that
+    /// `QueryMsg::LocalAddress` finds them. `destination` is ignored
+    /// if the contract is not in multi-pair mode. Only needed for
+    /// accounts last used before the note recorded remote addresses'
+    /// local addresses, and called repeatedly with the last account
+    /// indexed, which is returned as the `last_account` attribute,
+    /// until none are.
+    IndexAccounts {
+        destination: Option<String>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Sets the default and bounds for request timeouts, which apply
+    /// to requests' timestamp timeouts from then on.
+    SetTimeouts { timeouts: TimeoutConfig },
+    /// Registers `alias` as a name for `connection_id` that may be
+    /// used as a request's destination. If `connection_id` is none,
+    /// removes the alias.
+    SetAlias {
+        alias: String,
+        connection_id: Option<String>,
+    },
+    /// Adds `pair`, opening the note to handshakes from it. Only
+    /// allowed in multi-pair mode.
+    AddPair { pair: Pair },
+    /// Replaces the pair at `destination` with `pair`, closing the
+    /// note to handshakes from the old pair and opening it to ones
+    /// from the new. `destination` is ignored if the contract is not
+    /// in multi-pair mode, and aliases of the old pair are updated to
+    /// refer to the new one.
+    ///
+    /// This allows recovery when the connection to a pair can no
+    /// longer be used, for example because its light client has
+    /// expired. The voice on the new connection will not control the
+    /// remote accounts controlled by the old one unless it is
+    /// configured to. Requests in flight on the old pair's channel
+    /// are still tracked until they are acked or it closes.
+    ///
+    /// In single-pair mode, accounts are not stored by connection, so
+    /// `RemoteAddress` returns the remote addresses of accounts on
+    /// the old pair's voice, and acks on its channel still update
+    /// them, until each account is used over the new pair. These are
+    /// only valid if the new voice aliases the old pair with
+    /// `MigrateMsg::AliasCounterparty`. In multi-pair mode, accounts
+    /// on the new pair start empty.
+    ReplacePair {
+        destination: Option<String>,
+        pair: Pair,
+    },
 }
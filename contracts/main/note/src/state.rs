@@ -1,7 +1,12 @@
-use cosmwasm_std::Storage;
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Addr, StdResult, Storage, Timestamp};
 use cw_storage_plus::{Item, Map};
+use polytone::callbacks::CallbackRequestType;
 
-use crate::error::ContractError;
+use crate::{
+    error::ContractError,
+    msg::{Grant, Pair, TimeoutConfig},
+};
 
 /// (Connection-ID, Remote port) of this contract's pair.
 pub const CONNECTION_REMOTE_PORT: Item<(String, String)> = Item::new("a");
@@ -13,6 +18,82 @@ pub const CHANNEL: Item<String> = Item::new("b");
 /// Max gas usable in a single block.
 pub const BLOCK_MAX_GAS: Item<u64> = Item::new("bmg");
 
+/// The default and bounds for request timeouts. Holds no value if
+/// none have been set, in which case `TimeoutConfig::default` is
+/// used. See `timeouts`.
+pub const TIMEOUTS: Item<TimeoutConfig> = Item::new("to");
+
+/// Set to true if this contract pairs with one voice per connection.
+/// In this mode `CONNECTION_REMOTE_PORT` and `CHANNEL` are unused
+/// and pairs are stored in `PAIRS` and `CHANNELS`.
+pub const MULTI_PAIR: Item<bool> = Item::new("mp");
+
+/// (Connection-ID) -> Remote port, for each of this contract's pairs
+/// in multi-pair mode.
+pub const PAIRS: Map<String, String> = Map::new("pairs");
+
+/// (Connection-ID) -> Channel-ID of the channel currently connected
+/// to the pair on that connection, in multi-pair mode.
+pub const CHANNELS: Map<String, String> = Map::new("channels");
+
+/// (Channel-ID) -> Connection-ID, for each channel in `CHANNELS` and
+/// each channel replaced by `MigrateMsg::ReplacePair` that has not
+/// closed yet, so that acks on it still update accounts.
+pub const CHANNEL_TO_CONNECTION: Map<String, String> = Map::new("c2c");
+
+/// (Alias) -> Connection-ID. Aliases may be used in place of a
+/// connection ID when specifying a request's destination.
+pub const ALIASES: Map<String, String> = Map::new("aliases");
+
+/// (index) -> pair change, for each time the contract admin has
+/// replaced one of this contract's pairs.
+pub const PAIR_HISTORY: Map<u64, PairChange> = Map::new("ph");
+
+#[cw_serde]
+pub struct PairChange {
+    /// The pair that was replaced, or none if the contract had no
+    /// pair.
+    pub previous: Option<Pair>,
+    /// The pair that replaced it.
+    pub replacement: Pair,
+    /// The block height at which the pair was replaced.
+    pub block_height: u64,
+}
+
+/// (channel_id) -> block time at which the channel closed, for each
+/// channel that has closed. Packets still in flight on a closed
+/// channel are flushed with `ExecuteMsg::FlushClosedChannel`.
+pub const CLOSED_CHANNELS: Map<String, Timestamp> = Map::new("cc");
+
+/// (grantor, delegate) -> grant, for each delegate that may send
+/// requests on behalf of a grantor.
+pub const GRANTS: Map<(&Addr, &Addr), Grant> = Map::new("grants");
+
+/// (grantor, delegate) -> usage of the grant in its current period,
+/// for grants with a rate limit that have been used.
+pub const GRANT_USAGE: Map<(&Addr, &Addr), GrantUsage> = Map::new("gu");
+
+#[cw_serde]
+pub struct GrantUsage {
+    /// The block time the current period started at.
+    pub period_start: Timestamp,
+    /// The number of packets sent in the current period.
+    pub packets: u32,
+}
+
+/// (channel_id, sequence_number) -> packet info, for each packet that
+/// has been sent but not ACKed or timed out. Packets sent before this
+/// was added have no entry.
+pub const PENDING_PACKETS: Map<(String, u64), PacketInfo> = Map::new("pp");
+
+#[cw_serde]
+pub struct PacketInfo {
+    /// The type of request the packet contains.
+    pub request_type: CallbackRequestType,
+    /// The block time at which the packet was sent.
+    pub sent_at: Timestamp,
+}
+
 /// (channel_id) -> sequence number. `u64` is the type used in the
 /// Cosmos SDK for sequence numbers:
 ///
@@ -32,3 +113,47 @@ pub(crate) fn increment_sequence_number(
     SEQUENCE_NUMBER.save(storage, channel_id, &seq)?;
     Ok(seq)
 }
+
+pub(crate) fn is_multi_pair(storage: &dyn Storage) -> StdResult<bool> {
+    Ok(MULTI_PAIR.may_load(storage)?.unwrap_or_default())
+}
+
+/// Resolves a destination, either a connection ID or an alias of
+/// one, to a connection ID.
+pub(crate) fn resolve_destination(storage: &dyn Storage, destination: String) -> StdResult<String> {
+    Ok(ALIASES
+        .may_load(storage, destination.clone())?
+        .unwrap_or(destination))
+}
+
+/// Returns the channel a request to `destination` should be sent on.
+pub(crate) fn destination_channel(
+    storage: &dyn Storage,
+    destination: Option<String>,
+) -> Result<String, ContractError> {
+    if is_multi_pair(storage)? {
+        let destination = destination.ok_or(ContractError::NoDestination)?;
+        let connection_id = resolve_destination(storage, destination.clone())?;
+        Ok(CHANNELS
+            .may_load(storage, connection_id)?
+            .ok_or(ContractError::NoPairFor { destination })?)
+    } else {
+        if let Some(destination) = destination {
+            let connection_id = resolve_destination(storage, destination.clone())?;
+            if CONNECTION_REMOTE_PORT
+                .may_load(storage)?
+                .map_or(true, |(pair_connection, _)| {
+                    pair_connection != connection_id
+                })
+            {
+                return Err(ContractError::NoPairFor { destination });
+            }
+        }
+        Ok(CHANNEL.may_load(storage)?.ok_or(ContractError::NoPair)?)
+    }
+}
+
+/// The default and bounds for request timeouts the note enforces.
+pub(crate) fn timeouts(storage: &dyn Storage) -> StdResult<TimeoutConfig> {
+    Ok(TIMEOUTS.may_load(storage)?.unwrap_or_default())
+}
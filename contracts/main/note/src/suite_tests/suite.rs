@@ -1,8 +1,13 @@
-use cosmwasm_std::{Addr, Empty, Uint64};
+use cosmwasm_std::{Addr, CosmosMsg, Empty, StdResult, Uint64};
 use cw_multi_test::{App, AppResponse, Contract, ContractWrapper, Executor};
 
-use crate::msg::QueryMsg::{ActiveChannel, BlockMaxGas, Pair as PairQuery};
-use crate::msg::{InstantiateMsg, MigrateMsg, Pair};
+use crate::msg::QueryMsg::{
+    ActiveChannel, BlockMaxGas, Destination, Destinations, Pair as PairQuery, PairHistory,
+};
+use crate::msg::{
+    DestinationResponse, ExecuteMsg, InstantiateMsg, MigrateMsg, Pair, RequestOptions,
+};
+use crate::state::PairChange;
 
 pub const CREATOR_ADDR: &str = "creator";
 
@@ -33,6 +38,8 @@ impl Default for SuiteBuilder {
             instantiate: InstantiateMsg {
                 block_max_gas: Uint64::new(110_000),
                 pair: None,
+                multi_pair: None,
+                timeouts: None,
             },
         }
     }
@@ -72,6 +79,38 @@ impl SuiteBuilder {
         self.instantiate.pair = Some(pair);
         self
     }
+
+    pub fn with_multi_pair(mut self) -> Self {
+        self.instantiate.multi_pair = Some(true);
+        self
+    }
+}
+
+// execute
+impl Suite {
+    pub fn execute(
+        &mut self,
+        sender: Addr,
+        msgs: Vec<CosmosMsg>,
+        destination: Option<String>,
+    ) -> anyhow::Result<AppResponse> {
+        self.app.execute_contract(
+            sender,
+            self.note_address.clone(),
+            &ExecuteMsg::Execute {
+                msgs,
+                callback: None,
+                timeout_seconds: Some(Uint64::new(100)),
+                mode: None,
+                on_behalf_of: None,
+                options: Some(RequestOptions {
+                    destination,
+                    ..Default::default()
+                }),
+            },
+            &[],
+        )
+    }
 }
 
 // queries
@@ -83,11 +122,10 @@ impl Suite {
             .unwrap()
     }
 
-    pub fn query_pair(&self) -> Option<Pair> {
+    pub fn query_pair(&self) -> StdResult<Option<Pair>> {
         self.app
             .wrap()
             .query_wasm_smart(&self.note_address, &PairQuery)
-            .unwrap()
     }
 
     pub fn _query_active_channel(&self) -> String {
@@ -96,6 +134,44 @@ impl Suite {
             .query_wasm_smart(&self.note_address, &ActiveChannel)
             .unwrap()
     }
+
+    pub fn query_destination(&self, destination: &str) -> Option<DestinationResponse> {
+        self.app
+            .wrap()
+            .query_wasm_smart(
+                &self.note_address,
+                &Destination {
+                    destination: destination.to_string(),
+                },
+            )
+            .unwrap()
+    }
+
+    pub fn query_pair_history(&self) -> Vec<(u64, PairChange)> {
+        self.app
+            .wrap()
+            .query_wasm_smart(
+                &self.note_address,
+                &PairHistory {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap()
+    }
+
+    pub fn query_destinations(&self) -> Vec<DestinationResponse> {
+        self.app
+            .wrap()
+            .query_wasm_smart(
+                &self.note_address,
+                &Destinations {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap()
+    }
 }
 
 // migrate
@@ -110,6 +186,49 @@ impl Suite {
             self.note_code,
         )
     }
+
+    pub fn set_alias(
+        &mut self,
+        sender: Addr,
+        alias: &str,
+        connection_id: Option<&str>,
+    ) -> anyhow::Result<AppResponse> {
+        self.app.migrate_contract(
+            sender,
+            self.note_address.clone(),
+            &MigrateMsg::SetAlias {
+                alias: alias.to_string(),
+                connection_id: connection_id.map(str::to_string),
+            },
+            self.note_code,
+        )
+    }
+
+    pub fn add_pair(&mut self, sender: Addr, pair: Pair) -> anyhow::Result<AppResponse> {
+        self.app.migrate_contract(
+            sender,
+            self.note_address.clone(),
+            &MigrateMsg::AddPair { pair },
+            self.note_code,
+        )
+    }
+
+    pub fn replace_pair(
+        &mut self,
+        sender: Addr,
+        destination: Option<&str>,
+        pair: Pair,
+    ) -> anyhow::Result<AppResponse> {
+        self.app.migrate_contract(
+            sender,
+            self.note_address.clone(),
+            &MigrateMsg::ReplacePair {
+                destination: destination.map(str::to_string),
+                pair,
+            },
+            self.note_code,
+        )
+    }
 }
 
 // assertion helpers
@@ -120,7 +239,7 @@ impl Suite {
     }
 
     pub fn assert_pair(&self, val: Option<Pair>) {
-        let curr = self.query_pair();
+        let curr = self.query_pair().unwrap();
         assert_eq!(curr, val);
     }
 }
@@ -1,6 +1,10 @@
 use cosmwasm_std::{Addr, Uint64};
 
-use crate::{error::ContractError, msg::Pair};
+use crate::{
+    error::ContractError,
+    msg::{DestinationResponse, Pair},
+    state::PairChange,
+};
 
 use super::suite::{SuiteBuilder, CREATOR_ADDR};
 
@@ -71,3 +75,233 @@ fn test_migrate_validation() {
 
     assert_eq!(err, ContractError::GasLimitsMismatch);
 }
+
+#[test]
+fn test_instantiate_multi_pair() {
+    let pair = Pair {
+        connection_id: "connection-0".to_string(),
+        remote_port: "port".to_string(),
+    };
+    let mut suite = SuiteBuilder::default()
+        .with_pair(pair.clone())
+        .with_multi_pair()
+        .build();
+
+    // multi-pair mode does not have a single pair.
+    suite.query_pair().unwrap_err();
+
+    let destination = DestinationResponse {
+        pair,
+        active_channel: None,
+    };
+    assert_eq!(suite.query_destinations(), vec![destination.clone()]);
+    assert_eq!(suite.query_destination("osmosis"), None);
+
+    suite
+        .set_alias(
+            Addr::unchecked(CREATOR_ADDR),
+            "osmosis",
+            Some("connection-0"),
+        )
+        .unwrap();
+    assert_eq!(suite.query_destination("osmosis"), Some(destination));
+
+    suite
+        .set_alias(Addr::unchecked(CREATOR_ADDR), "osmosis", None)
+        .unwrap();
+    assert_eq!(suite.query_destination("osmosis"), None);
+}
+
+#[test]
+fn test_add_pair() {
+    let pair = Pair {
+        connection_id: "connection-0".to_string(),
+        remote_port: "port".to_string(),
+    };
+    let mut suite = SuiteBuilder::default().with_multi_pair().build();
+
+    suite
+        .add_pair(Addr::unchecked("sender"), pair.clone())
+        .unwrap_err();
+    suite
+        .add_pair(Addr::unchecked(CREATOR_ADDR), pair.clone())
+        .unwrap();
+    assert_eq!(
+        suite.query_destinations(),
+        vec![DestinationResponse {
+            pair: pair.clone(),
+            active_channel: None,
+        }]
+    );
+
+    let err = suite
+        .add_pair(Addr::unchecked(CREATOR_ADDR), pair.clone())
+        .unwrap_err()
+        .downcast::<ContractError>()
+        .unwrap();
+    assert_eq!(
+        err,
+        ContractError::ConnectionPaired {
+            connection_id: "connection-0".to_string()
+        }
+    );
+
+    // without multi-pair mode, there is only ever one pair.
+    let mut suite = SuiteBuilder::default().build();
+    let err = suite
+        .add_pair(Addr::unchecked(CREATOR_ADDR), pair)
+        .unwrap_err()
+        .downcast::<ContractError>()
+        .unwrap();
+    assert_eq!(err, ContractError::SinglePair);
+}
+
+#[test]
+fn test_execute_destination() {
+    let mut suite = SuiteBuilder::default().with_multi_pair().build();
+
+    let err = suite
+        .execute(Addr::unchecked("sender"), vec![], None)
+        .unwrap_err()
+        .downcast::<ContractError>()
+        .unwrap();
+    assert_eq!(err, ContractError::NoDestination);
+
+    let err = suite
+        .execute(
+            Addr::unchecked("sender"),
+            vec![],
+            Some("connection-0".to_string()),
+        )
+        .unwrap_err()
+        .downcast::<ContractError>()
+        .unwrap();
+    assert_eq!(
+        err,
+        ContractError::NoPairFor {
+            destination: "connection-0".to_string()
+        }
+    );
+
+    // without multi-pair mode, the destination must be the pair.
+    let mut suite = SuiteBuilder::default()
+        .with_pair(Pair {
+            connection_id: "connection-0".to_string(),
+            remote_port: "port".to_string(),
+        })
+        .build();
+
+    let err = suite
+        .execute(
+            Addr::unchecked("sender"),
+            vec![],
+            Some("connection-1".to_string()),
+        )
+        .unwrap_err()
+        .downcast::<ContractError>()
+        .unwrap();
+    assert_eq!(
+        err,
+        ContractError::NoPairFor {
+            destination: "connection-1".to_string()
+        }
+    );
+
+    let err = suite
+        .execute(
+            Addr::unchecked("sender"),
+            vec![],
+            Some("connection-0".to_string()),
+        )
+        .unwrap_err()
+        .downcast::<ContractError>()
+        .unwrap();
+    assert_eq!(err, ContractError::NoPair);
+}
+
+#[test]
+fn test_replace_pair() {
+    let pair = Pair {
+        connection_id: "connection-0".to_string(),
+        remote_port: "port".to_string(),
+    };
+    let replacement = Pair {
+        connection_id: "connection-1".to_string(),
+        remote_port: "port".to_string(),
+    };
+    let mut suite = SuiteBuilder::default().with_pair(pair.clone()).build();
+
+    suite
+        .replace_pair(Addr::unchecked(CREATOR_ADDR), None, replacement.clone())
+        .unwrap();
+
+    suite.assert_pair(Some(replacement.clone()));
+    let history = suite.query_pair_history();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].0, 0);
+    assert_eq!(history[0].1.previous, Some(pair));
+    assert_eq!(history[0].1.replacement, replacement);
+}
+
+#[test]
+fn test_replace_pair_multi_pair() {
+    let pair = Pair {
+        connection_id: "connection-0".to_string(),
+        remote_port: "port".to_string(),
+    };
+    let replacement = Pair {
+        connection_id: "connection-1".to_string(),
+        remote_port: "port".to_string(),
+    };
+    let mut suite = SuiteBuilder::default()
+        .with_pair(pair.clone())
+        .with_multi_pair()
+        .build();
+    suite
+        .set_alias(
+            Addr::unchecked(CREATOR_ADDR),
+            "osmosis",
+            Some("connection-0"),
+        )
+        .unwrap();
+
+    // can't replace a pair with a connection that is already paired.
+    let err = suite
+        .replace_pair(Addr::unchecked(CREATOR_ADDR), Some("osmosis"), pair.clone())
+        .unwrap_err()
+        .downcast::<ContractError>()
+        .unwrap();
+    assert_eq!(
+        err,
+        ContractError::ConnectionPaired {
+            connection_id: "connection-0".to_string()
+        }
+    );
+
+    suite
+        .replace_pair(
+            Addr::unchecked(CREATOR_ADDR),
+            Some("osmosis"),
+            replacement.clone(),
+        )
+        .unwrap();
+
+    let destination = DestinationResponse {
+        pair: replacement.clone(),
+        active_channel: None,
+    };
+    assert_eq!(suite.query_destinations(), vec![destination.clone()]);
+    assert_eq!(suite.query_destination("osmosis"), Some(destination));
+    assert_eq!(
+        suite
+            .query_pair_history()
+            .into_iter()
+            .map(|(_, change)| change)
+            .collect::<Vec<PairChange>>(),
+        vec![PairChange {
+            previous: Some(pair),
+            replacement,
+            block_height: 12345,
+        }]
+    );
+}
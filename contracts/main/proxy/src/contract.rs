@@ -4,11 +4,12 @@ use cosmwasm_std::{
     to_json_binary, Binary, Deps, DepsMut, Env, MessageInfo, Reply, Response, StdResult, SubMsg,
     SubMsgResponse, SubMsgResult,
 };
-use cw2::set_contract_version;
+use cw2::{get_contract_version, set_contract_version};
 use polytone::ack::ack_execute_success;
+use semver::Version;
 
 use crate::error::ContractError;
-use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
+use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg};
 use crate::state::{COLLECTOR, INSTANTIATOR};
 
 const CONTRACT_NAME: &str = "crates.io:polytone-proxy";
@@ -69,6 +70,21 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
     }
 }
 
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    // the voice migrates proxies in batches, so a migration to an
+    // older code ID would otherwise downgrade some of them.
+    let previous = get_contract_version(deps.storage)?.version;
+    if CONTRACT_VERSION.parse::<Version>()? < previous.parse::<Version>()? {
+        return Err(ContractError::Downgrade {
+            previous,
+            version: CONTRACT_VERSION.to_string(),
+        });
+    }
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    Ok(Response::default().add_attribute("method", "migrate"))
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
     let mut collector = COLLECTOR.load(deps.storage)?;
@@ -10,9 +10,15 @@ pub enum ContractError {
     #[error(transparent)]
     Parse(#[from] ParseReplyError),
 
+    #[error(transparent)]
+    Semver(#[from] semver::Error),
+
     #[error("caller must be the contract instantiator")]
     NotInstantiator,
 
     #[error("executing message {index}: {error}")]
     MsgError { index: u64, error: String },
+
+    #[error("can not migrate from version ({previous}) to the older version ({version})")]
+    Downgrade { previous: String, version: String },
 }
@@ -4,6 +4,9 @@ use cosmwasm_std::CosmosMsg;
 #[cw_serde]
 pub struct InstantiateMsg {}
 
+#[cw_serde]
+pub struct MigrateMsg {}
+
 #[cw_serde]
 #[cfg_attr(feature = "interface", derive(cw_orch::ExecuteFns))] // cw-orch automatic
 pub enum ExecuteMsg {
@@ -1 +1,31 @@
+use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+use cw2::{get_contract_version, set_contract_version};
 
+use crate::{
+    contract::{instantiate, migrate},
+    error::ContractError,
+    msg::{InstantiateMsg, MigrateMsg},
+};
+
+#[test]
+fn test_migrate_version() {
+    let mut deps = mock_dependencies();
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("voice", &[]),
+        InstantiateMsg {},
+    )
+    .unwrap();
+
+    set_contract_version(deps.as_mut().storage, "crates.io:polytone-proxy", "99.0.0").unwrap();
+    let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+    assert!(matches!(err, ContractError::Downgrade { previous, .. } if previous == "99.0.0"));
+
+    set_contract_version(deps.as_mut().storage, "crates.io:polytone-proxy", "0.1.0").unwrap();
+    migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+    assert_eq!(
+        get_contract_version(deps.as_ref().storage).unwrap().version,
+        env!("CARGO_PKG_VERSION")
+    );
+}
@@ -1,25 +1,49 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    from_json, instantiate2_address, to_json_binary, to_json_vec, Binary, CanonicalAddr,
-    CodeInfoResponse, ContractResult, Deps, DepsMut, Env, MessageInfo, Response, StdResult, SubMsg,
-    SystemResult, Uint64, WasmMsg,
+    from_json, instantiate2_address, to_json_binary, to_json_vec, Addr, Binary, CanonicalAddr,
+    CodeInfoResponse, ContractResult, CosmosMsg, Deps, DepsMut, Empty, Env, MessageInfo, Order,
+    QueryRequest, Response, StdResult, Storage, SubMsg, SystemResult, Uint64, WasmMsg,
 };
 use cw2::set_contract_version;
+use cw_storage_plus::Bound;
 
-use polytone::ack::{ack_query_fail, ack_query_success};
-use polytone::ibc::{Msg, Packet};
+use polytone::ack::{
+    ack_conditional_execute, ack_execute_best_effort, ack_execute_fail, ack_execute_success,
+    ack_out_of_order, ack_outside_window, ack_query_fail, ack_query_partial, ack_query_success,
+    ack_rejected, ack_templated_execute,
+};
+use polytone::callbacks::{
+    ConditionalExecutionResponse, ErrorResponse, TemplatedExecutionResponse,
+    DUPLICATE_IDEMPOTENCY_KEY,
+};
+use polytone::ibc::{ExecuteMode, Msg, Packet};
+use polytone::template::resolve_templates;
 
 use crate::error::ContractError;
-use crate::ibc::{ACK_GAS_NEEDED, REPLY_FORWARD_DATA};
-use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg};
+use crate::ibc::{
+    ACK_GAS_NEEDED, REPLY_BEST_EFFORT, REPLY_CONDITIONAL_EXECUTE, REPLY_FORWARD_DATA,
+    REPLY_MIGRATE_PROXY, REPLY_TEMPLATED_EXECUTE,
+};
+use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, ProxyResponse, QueryMsg};
+use crate::policy::check_policy;
 use crate::state::{
-    SenderInfo, BLOCK_MAX_GAS, CONTRACT_ADDR_LEN, PROXY_CODE_ID, PROXY_TO_SENDER, SENDER_TO_PROXY,
+    resolve_counterparty, BestEffortExecution, Counterparty, ProxyMigration, SenderInfo,
+    BEST_EFFORT, BLOCK_MAX_GAS, CONNECTION_PROXIES, CONTRACT_ADDR_LEN, COUNTERPARTY_ALIASES,
+    COUNTERPARTY_REPLACEMENTS, IDEMPOTENCY_EXPIRIES, IDEMPOTENCY_KEYS, IDEMPOTENCY_WINDOW,
+    MESSAGE_POLICY, MIGRATION_OPT_OUTS, NONCES, PENDING_IDEMPOTENCY_KEY, PROXY_CODE_ID,
+    PROXY_MIGRATION, PROXY_TO_SENDER, QUERY_RESULTS, SALT_GENERATIONS, SENDER_ACCOUNT_TO_PROXY,
+    SENDER_TO_PROXY,
 };
 
 const CONTRACT_NAME: &str = "crates.io:polytone-voice";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// Thirty days, the longest timeout the note allows by default.
+const DEFAULT_IDEMPOTENCY_WINDOW_SECONDS: u64 = 30 * 24 * 60 * 60;
+/// The most expired idempotency keys removed each time one is used.
+const IDEMPOTENCY_KEYS_PRUNED: usize = 2;
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     deps: DepsMut,
@@ -48,17 +72,22 @@ pub fn instantiate(
     PROXY_CODE_ID.save(deps.storage, &msg.proxy_code_id.u64())?;
     BLOCK_MAX_GAS.save(deps.storage, &msg.block_max_gas.u64())?;
     CONTRACT_ADDR_LEN.save(deps.storage, &contract_addr_len)?;
+    let idempotency_window = msg
+        .idempotency_window_seconds
+        .map_or(DEFAULT_IDEMPOTENCY_WINDOW_SECONDS, |seconds| seconds.u64());
+    IDEMPOTENCY_WINDOW.save(deps.storage, &idempotency_window)?;
 
     Ok(Response::default()
         .add_attribute("method", "instantiate")
         .add_attribute("proxy_code_id", msg.proxy_code_id)
         .add_attribute("block_max_gas", msg.block_max_gas)
-        .add_attribute("contract_addr_len", contract_addr_len.to_string()))
+        .add_attribute("contract_addr_len", contract_addr_len.to_string())
+        .add_attribute("idempotency_window", idempotency_window.to_string()))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
     msg: ExecuteMsg,
@@ -72,109 +101,509 @@ pub fn execute(
             if info.sender != env.contract.address {
                 Err(ContractError::NotSelf)
             } else {
-                let Packet { sender, msg } = from_json(data)?;
+                let Packet {
+                    sender,
+                    msg,
+                    not_before,
+                    not_after,
+                    nonce,
+                    idempotency_key,
+                    account_id,
+                    ..
+                } = from_json(data)?;
+                if not_before.map_or(false, |t| env.block.time < t)
+                    || not_after.map_or(false, |t| env.block.time > t)
+                {
+                    return Ok(Response::default()
+                        .add_attribute("method", "rx_outside_window")
+                        .set_data(ack_outside_window(env.block.time, not_before, not_after)));
+                }
+                // templated messages are checked once resolved, as
+                // they are not known until then.
+                if let Msg::Execute { msgs, .. } | Msg::ConditionalExecute { msgs, .. } = &msg {
+                    if let Some((index, error)) = check_policy(deps.storage, msgs)? {
+                        return Ok(rejected(
+                            Response::default().add_attribute("method", "rx_rejected"),
+                            index,
+                            error,
+                        ));
+                    }
+                }
+                if let Some(nonce) = nonce {
+                    let (connection_id, counterparty_port) = resolve_counterparty(
+                        deps.storage,
+                        connection_id.clone(),
+                        counterparty_port.clone(),
+                    )?;
+                    let key = (connection_id, counterparty_port, sender.clone());
+                    let last = NONCES
+                        .may_load(deps.storage, key.clone())?
+                        .unwrap_or_default();
+                    if last.checked_add(1) != Some(nonce.u64()) {
+                        return Ok(Response::default()
+                            .add_attribute("method", "rx_out_of_order")
+                            .add_attribute("out_of_order_nonce", nonce.to_string())
+                            .set_data(ack_out_of_order(nonce.u64(), last.saturating_add(1))));
+                    }
+                    NONCES.save(deps.storage, key, &nonce.u64())?;
+                }
                 match msg {
-                    Msg::Query { msgs } => {
+                    Msg::Query { msgs, partial } => {
+                        if partial.unwrap_or_default() {
+                            let results = msgs
+                                .iter()
+                                .map(|msg| query_raw(deps.as_ref(), msg))
+                                .collect::<StdResult<Vec<_>>>()?;
+                            let failed = results.iter().filter(|r| r.is_err()).count();
+                            return Ok(Response::default()
+                                .add_attribute("method", "rx_query_partial")
+                                .add_attribute("queries_executed", results.len().to_string())
+                                .add_attribute("queries_failed", failed.to_string())
+                                .set_data(ack_query_partial(results)));
+                        }
                         let mut results = Vec::with_capacity(msgs.len());
                         for msg in msgs {
-                            let query_result = deps.querier.raw_query(&to_json_vec(&msg)?);
-                            let error = match query_result {
-                                SystemResult::Ok(ContractResult::Err(error)) => {
-                                    format!("contract: {error}")
+                            match query_raw(deps.as_ref(), &msg)? {
+                                Ok(res) => results.push(res),
+                                Err(error) => {
+                                    return Ok(Response::default()
+                                        .add_attribute("method", "rx_query_fail")
+                                        .add_attribute("query_index", results.len().to_string())
+                                        .add_attribute("query_error", error.as_str())
+                                        .set_data(ack_query_fail(
+                                            Uint64::new(results.len() as u64),
+                                            error,
+                                        )))
                                 }
-                                SystemResult::Err(error) => format!("system: {error}"),
-                                SystemResult::Ok(ContractResult::Ok(res)) => {
-                                    results.push(res);
-                                    continue;
-                                }
-                            };
-                            return Ok(Response::default()
-                                .add_attribute("method", "rx_query_fail")
-                                .add_attribute("query_index", results.len().to_string())
-                                .add_attribute("query_error", error.as_str())
-                                .set_data(ack_query_fail(
-                                    Uint64::new(results.len() as u64),
-                                    error,
-                                )));
+                            }
                         }
                         Ok(Response::default()
                             .add_attribute("method", "rx_query_success")
                             .add_attribute("queries_executed", results.len().to_string())
                             .set_data(ack_query_success(results)))
                     }
-                    Msg::Execute { msgs } => {
-                        let (instantiate, proxy) = if let Some(proxy) = SENDER_TO_PROXY.may_load(
-                            deps.storage,
-                            (
-                                connection_id.clone(),
-                                counterparty_port.clone(),
-                                sender.clone(),
-                            ),
-                        )? {
-                            (None, proxy)
-                        } else {
-                            let contract =
-                                deps.api.addr_canonicalize(env.contract.address.as_str())?;
-                            let code_id = PROXY_CODE_ID.load(deps.storage)?;
-                            let addr_len = CONTRACT_ADDR_LEN.load(deps.storage)?;
-                            let CodeInfoResponse { checksum, .. } =
-                                deps.querier.query_wasm_code_info(code_id)?;
-                            let salt = salt(&connection_id, &counterparty_port, &sender);
-                            let init2_addr_data: CanonicalAddr =
-                                instantiate2_address(&checksum, &contract, &salt)?.to_vec()
-                                    [0..addr_len as usize]
-                                    .into();
-                            let proxy = deps.api.addr_humanize(&init2_addr_data)?;
-                            SENDER_TO_PROXY.save(
-                                deps.storage,
-                                (
-                                    connection_id.clone(),
-                                    counterparty_port.clone(),
-                                    sender.clone(),
-                                ),
-                                &proxy,
-                            )?;
-                            PROXY_TO_SENDER.save(
-                                deps.storage,
-                                proxy.clone(),
-                                &SenderInfo {
-                                    connection_id,
-                                    remote_port: counterparty_port,
-                                    remote_sender: sender.clone(),
-                                },
-                            )?;
-                            (
-                                Some(WasmMsg::Instantiate2 {
-                                    admin: None,
-                                    code_id,
-                                    label: format!("polytone-proxy {sender}"),
-                                    msg: to_json_binary(&polytone_proxy::msg::InstantiateMsg {})?,
-                                    funds: vec![],
-                                    salt,
+                    Msg::Execute { msgs, mode } => {
+                        let (connection_id, counterparty_port) =
+                            resolve_counterparty(deps.storage, connection_id, counterparty_port)?;
+                        let (instantiate, proxy) = get_or_create_proxy(
+                            deps.branch(),
+                            &env,
+                            connection_id,
+                            counterparty_port,
+                            sender,
+                            account_id,
+                        )?;
+                        let response = Response::default().add_attribute("method", "rx_execute");
+                        if !check_idempotency_key(deps.storage, &env, &proxy, idempotency_key)? {
+                            return Ok(duplicate_idempotency_key(response).set_data(
+                                ack_execute_fail(DUPLICATE_IDEMPOTENCY_KEY.to_string()),
+                            ));
+                        }
+                        let response = response.add_messages(instantiate);
+                        match mode.unwrap_or_default() {
+                            ExecuteMode::Atomic => {
+                                Ok(response.add_submessage(SubMsg::reply_always(
+                                    proxy_execute(proxy.into_string(), msgs)?,
+                                    REPLY_FORWARD_DATA,
+                                )))
+                            }
+                            ExecuteMode::BestEffort => {
+                                let response = response.add_attribute("mode", "best_effort");
+                                if msgs.is_empty() {
+                                    return Ok(response.set_data(ack_execute_best_effort(
+                                        vec![],
+                                        proxy.into_string(),
+                                    )));
+                                }
+                                // Each message is executed by the proxy
+                                // in its own submessage so that a failure
+                                // only reverts that message. Results are
+                                // collected in `BEST_EFFORT` as replies
+                                // arrive, and the last reply sets the ack.
+                                BEST_EFFORT.save(
+                                    deps.storage,
+                                    &BestEffortExecution {
+                                        executed_by: proxy.to_string(),
+                                        expected: msgs.len() as u64,
+                                        results: vec![],
+                                    },
+                                )?;
+                                let submsgs = msgs
+                                    .into_iter()
+                                    .map(|msg| {
+                                        Ok(SubMsg::reply_always(
+                                            proxy_execute(proxy.to_string(), vec![msg])?,
+                                            REPLY_BEST_EFFORT,
+                                        ))
+                                    })
+                                    .collect::<StdResult<Vec<_>>>()?;
+                                Ok(response.add_submessages(submsgs))
+                            }
+                        }
+                    }
+                    Msg::ConditionalExecute {
+                        queries,
+                        conditions,
+                        msgs,
+                    } => {
+                        let response =
+                            Response::default().add_attribute("method", "rx_conditional_execute");
+                        let results = match query_all(deps.as_ref(), &queries)? {
+                            Ok(results) => results,
+                            Err(error) => {
+                                return Ok(response
+                                    .add_attribute("query_index", error.message_index.to_string())
+                                    .add_attribute("query_error", error.error.as_str())
+                                    .set_data(ack_conditional_execute(
+                                        ConditionalExecutionResponse {
+                                            query: Err(error),
+                                            failed_condition: None,
+                                            execute: None,
+                                        },
+                                    )))
+                            }
+                        };
+                        for (index, condition) in conditions.iter().enumerate() {
+                            if let Err(error) = condition.check(&results) {
+                                return Ok(response
+                                    .add_attribute("condition_index", index.to_string())
+                                    .add_attribute("condition_error", error.as_str())
+                                    .set_data(ack_conditional_execute(
+                                        ConditionalExecutionResponse {
+                                            query: Ok(results),
+                                            failed_condition: Some(ErrorResponse {
+                                                message_index: Uint64::new(index as u64),
+                                                error,
+                                            }),
+                                            execute: None,
+                                        },
+                                    )));
+                            }
+                        }
+                        let (connection_id, counterparty_port) =
+                            resolve_counterparty(deps.storage, connection_id, counterparty_port)?;
+                        let (instantiate, proxy) = get_or_create_proxy(
+                            deps.branch(),
+                            &env,
+                            connection_id,
+                            counterparty_port,
+                            sender,
+                            account_id,
+                        )?;
+                        if !check_idempotency_key(deps.storage, &env, &proxy, idempotency_key)? {
+                            return Ok(duplicate_idempotency_key(response).set_data(
+                                ack_conditional_execute(ConditionalExecutionResponse {
+                                    query: Ok(results),
+                                    failed_condition: None,
+                                    execute: Some(Err(DUPLICATE_IDEMPOTENCY_KEY.to_string())),
                                 }),
-                                proxy,
-                            )
+                            ));
+                        }
+                        // the reply includes the query results in the
+                        // ack alongside the execution's result.
+                        QUERY_RESULTS.save(deps.storage, &results)?;
+                        Ok(response
+                            .add_messages(instantiate)
+                            .add_submessage(SubMsg::reply_always(
+                                proxy_execute(proxy.into_string(), msgs)?,
+                                REPLY_CONDITIONAL_EXECUTE,
+                            )))
+                    }
+                    Msg::TemplatedExecute { queries, msgs } => {
+                        let response =
+                            Response::default().add_attribute("method", "rx_templated_execute");
+                        let results = match query_all(deps.as_ref(), &queries)? {
+                            Ok(results) => results,
+                            Err(error) => {
+                                return Ok(response
+                                    .add_attribute("query_index", error.message_index.to_string())
+                                    .add_attribute("query_error", error.error.as_str())
+                                    .set_data(ack_templated_execute(TemplatedExecutionResponse {
+                                        query: Err(error),
+                                        failed_template: None,
+                                        execute: None,
+                                    })))
+                            }
                         };
-                        Ok(Response::default()
-                            .add_attribute("method", "rx_execute")
+                        let msgs = match resolve_templates(msgs, &results) {
+                            Ok(msgs) => msgs,
+                            Err(error) => {
+                                return Ok(response
+                                    .add_attribute(
+                                        "template_index",
+                                        error.message_index.to_string(),
+                                    )
+                                    .add_attribute("template_error", error.error.as_str())
+                                    .set_data(ack_templated_execute(TemplatedExecutionResponse {
+                                        query: Ok(results),
+                                        failed_template: Some(error),
+                                        execute: None,
+                                    })))
+                            }
+                        };
+                        if let Some((index, error)) = check_policy(deps.storage, &msgs)? {
+                            return Ok(rejected(response, index, error));
+                        }
+                        let (connection_id, counterparty_port) =
+                            resolve_counterparty(deps.storage, connection_id, counterparty_port)?;
+                        let (instantiate, proxy) = get_or_create_proxy(
+                            deps.branch(),
+                            &env,
+                            connection_id,
+                            counterparty_port,
+                            sender,
+                            account_id,
+                        )?;
+                        if !check_idempotency_key(deps.storage, &env, &proxy, idempotency_key)? {
+                            return Ok(duplicate_idempotency_key(response).set_data(
+                                ack_templated_execute(TemplatedExecutionResponse {
+                                    query: Ok(results),
+                                    failed_template: None,
+                                    execute: Some(Err(DUPLICATE_IDEMPOTENCY_KEY.to_string())),
+                                }),
+                            ));
+                        }
+                        QUERY_RESULTS.save(deps.storage, &results)?;
+                        Ok(response
                             .add_messages(instantiate)
                             .add_submessage(SubMsg::reply_always(
-                                WasmMsg::Execute {
-                                    contract_addr: proxy.into_string(),
-                                    msg: to_json_binary(&polytone_proxy::msg::ExecuteMsg::Proxy {
-                                        msgs,
-                                    })?,
-                                    funds: vec![],
-                                },
-                                REPLY_FORWARD_DATA,
+                                proxy_execute(proxy.into_string(), msgs)?,
+                                REPLY_TEMPLATED_EXECUTE,
                             )))
                     }
+                    Msg::TransferAccount { new_owner } => {
+                        let (connection_id, counterparty_port) =
+                            resolve_counterparty(deps.storage, connection_id, counterparty_port)?;
+                        // errors returned from `Rx` are redacted, so
+                        // transfers that can not be made are failed in
+                        // the callback instead.
+                        let proxy = match transfer_proxy(
+                            deps.storage,
+                            connection_id,
+                            counterparty_port,
+                            sender.clone(),
+                            account_id,
+                            new_owner.clone(),
+                        ) {
+                            Ok(proxy) => proxy,
+                            Err(
+                                error @ (ContractError::NoProxy | ContractError::NewOwnerHasProxy),
+                            ) => {
+                                return Ok(Response::default()
+                                    .add_attribute("method", "rx_transfer_account_fail")
+                                    .add_attribute("error", error.to_string())
+                                    .set_data(ack_execute_fail(error.to_string())))
+                            }
+                            Err(error) => return Err(error),
+                        };
+                        Ok(Response::default()
+                            .add_attribute("method", "rx_transfer_account")
+                            .add_attribute("proxy", proxy.as_str())
+                            .add_attribute("previous_owner", sender)
+                            .add_attribute("new_owner", new_owner)
+                            .set_data(ack_execute_success(vec![], proxy.into_string())))
+                    }
+                    Msg::SetMigrationOptOut { opt_out } => {
+                        let (connection_id, counterparty_port) =
+                            resolve_counterparty(deps.storage, connection_id, counterparty_port)?;
+                        let Some(proxy) = load_proxy(
+                            deps.storage,
+                            connection_id,
+                            counterparty_port,
+                            sender,
+                            account_id,
+                        )?
+                        else {
+                            return Ok(Response::default()
+                                .add_attribute("method", "rx_set_migration_opt_out_fail")
+                                .set_data(ack_execute_fail(ContractError::NoProxy.to_string())));
+                        };
+                        if opt_out {
+                            MIGRATION_OPT_OUTS.save(deps.storage, proxy.clone(), &Empty {})?;
+                        } else {
+                            MIGRATION_OPT_OUTS.remove(deps.storage, proxy.clone());
+                        }
+                        Ok(Response::default()
+                            .add_attribute("method", "rx_set_migration_opt_out")
+                            .add_attribute("proxy", proxy.as_str())
+                            .add_attribute("opt_out", opt_out.to_string())
+                            .set_data(ack_execute_success(vec![], proxy.into_string())))
+                    }
                 }
             }
         }
     }
 }
 
+/// Returns false if `proxy` has used `key`, if set, within the
+/// idempotency window. Otherwise holds the key in
+/// `PENDING_IDEMPOTENCY_KEY` for the reply to the packet to record,
+/// so that a key is only used by a request that succeeds.
+fn check_idempotency_key(
+    storage: &mut dyn Storage,
+    env: &Env,
+    proxy: &Addr,
+    key: Option<String>,
+) -> StdResult<bool> {
+    let Some(key) = key else {
+        return Ok(true);
+    };
+    if IDEMPOTENCY_KEYS
+        .may_load(storage, (proxy, &key))?
+        .map_or(false, |expires| env.block.time < expires)
+    {
+        return Ok(false);
+    }
+    PENDING_IDEMPOTENCY_KEY.save(storage, &(proxy.clone(), key))?;
+    Ok(true)
+}
+
+/// Records that `proxy` has used `key`, and removes up to
+/// `IDEMPOTENCY_KEYS_PRUNED` keys that have expired, so that keys are
+/// removed about as fast as they are added.
+pub(crate) fn record_idempotency_key(
+    storage: &mut dyn Storage,
+    env: &Env,
+    proxy: &Addr,
+    key: &str,
+) -> StdResult<()> {
+    if let Some(expires) = IDEMPOTENCY_KEYS.may_load(storage, (proxy, key))? {
+        IDEMPOTENCY_EXPIRIES.remove(storage, (expires.nanos(), proxy, key));
+    }
+    let window = IDEMPOTENCY_WINDOW
+        .may_load(storage)?
+        .unwrap_or(DEFAULT_IDEMPOTENCY_WINDOW_SECONDS);
+    let expires = env.block.time.plus_seconds(window);
+    IDEMPOTENCY_KEYS.save(storage, (proxy, key), &expires)?;
+    IDEMPOTENCY_EXPIRIES.save(storage, (expires.nanos(), proxy, key), &Empty {})?;
+
+    let expired = IDEMPOTENCY_EXPIRIES
+        .keys(storage, None, None, Order::Ascending)
+        .take(IDEMPOTENCY_KEYS_PRUNED)
+        .take_while(|item| {
+            item.as_ref()
+                .map_or(true, |(expires, _, _)| *expires <= env.block.time.nanos())
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    for (expires, proxy, key) in expired {
+        IDEMPOTENCY_EXPIRIES.remove(storage, (expires, &proxy, &key));
+        IDEMPOTENCY_KEYS.remove(storage, (&proxy, &key));
+    }
+    Ok(())
+}
+
+fn rejected(response: Response, index: usize, error: String) -> Response {
+    response
+        .add_attribute("rejected_index", index.to_string())
+        .add_attribute("rejected_error", error.as_str())
+        .set_data(ack_rejected(Uint64::new(index as u64), error))
+}
+
+fn duplicate_idempotency_key(response: Response) -> Response {
+    response.add_attribute("execute_error", DUPLICATE_IDEMPOTENCY_KEY)
+}
+
+/// Returns the proxy controlled by `sender` on the counterparty with
+/// `account_id`, and a message instantiating it if it does not exist
+/// yet.
+fn get_or_create_proxy(
+    deps: DepsMut,
+    env: &Env,
+    connection_id: String,
+    counterparty_port: String,
+    sender: String,
+    account_id: Option<String>,
+) -> Result<(Option<WasmMsg>, Addr), ContractError> {
+    Ok(
+        if let Some(proxy) = load_proxy(
+            deps.storage,
+            connection_id.clone(),
+            counterparty_port.clone(),
+            sender.clone(),
+            account_id.clone(),
+        )? {
+            (None, proxy)
+        } else {
+            let contract = deps.api.addr_canonicalize(env.contract.address.as_str())?;
+            let code_id = PROXY_CODE_ID.load(deps.storage)?;
+            let addr_len = CONTRACT_ADDR_LEN.load(deps.storage)?;
+            let CodeInfoResponse { checksum, .. } = deps.querier.query_wasm_code_info(code_id)?;
+            let salt = proxy_salt(
+                deps.storage,
+                &connection_id,
+                &counterparty_port,
+                &sender,
+                account_id.as_deref(),
+            )?;
+            let init2_addr_data: CanonicalAddr = instantiate2_address(&checksum, &contract, &salt)?
+                .to_vec()[0..addr_len as usize]
+                .into();
+            let proxy = deps.api.addr_humanize(&init2_addr_data)?;
+            save_proxy(
+                deps.storage,
+                &proxy,
+                SenderInfo {
+                    connection_id,
+                    remote_port: counterparty_port,
+                    remote_sender: sender.clone(),
+                    account_id,
+                },
+            )?;
+            (
+                Some(WasmMsg::Instantiate2 {
+                    // the voice is the admin so that proxies may be
+                    // migrated with `MigrateMsg::MigrateProxies`.
+                    admin: Some(env.contract.address.to_string()),
+                    code_id,
+                    label: format!("polytone-proxy {sender}"),
+                    msg: to_json_binary(&polytone_proxy::msg::InstantiateMsg {})?,
+                    funds: vec![],
+                    salt,
+                }),
+                proxy,
+            )
+        },
+    )
+}
+
+/// Performs `msg`, returning its response or the error it failed
+/// with.
+fn query_raw(deps: Deps, msg: &QueryRequest<Empty>) -> StdResult<Result<Binary, String>> {
+    Ok(match deps.querier.raw_query(&to_json_vec(msg)?) {
+        SystemResult::Ok(ContractResult::Err(error)) => Err(format!("contract: {error}")),
+        SystemResult::Err(error) => Err(format!("system: {error}")),
+        SystemResult::Ok(ContractResult::Ok(res)) => Ok(res),
+    })
+}
+
+/// Performs `queries`, returning their responses or the first error.
+fn query_all(
+    deps: Deps,
+    queries: &[QueryRequest<Empty>],
+) -> StdResult<Result<Vec<Binary>, ErrorResponse>> {
+    let mut results = Vec::with_capacity(queries.len());
+    for query in queries {
+        match query_raw(deps, query)? {
+            Ok(res) => results.push(res),
+            Err(error) => {
+                return Ok(Err(ErrorResponse {
+                    message_index: Uint64::new(results.len() as u64),
+                    error,
+                }))
+            }
+        }
+    }
+    Ok(Ok(results))
+}
+
+fn proxy_execute(proxy: String, msgs: Vec<CosmosMsg>) -> StdResult<WasmMsg> {
+    Ok(WasmMsg::Execute {
+        contract_addr: proxy,
+        msg: to_json_binary(&polytone_proxy::msg::ExecuteMsg::Proxy { msgs })?,
+        funds: vec![],
+    })
+}
+
 /// Generates the salt used to generate an address for a user's
 /// account.
 ///
@@ -183,15 +612,195 @@ pub fn execute(
 /// chain. `remote_port` ensures that two different modules on the
 /// same chain produce different addresses for the same
 /// `remote_sender`.
-fn salt(local_connection: &str, counterparty_port: &str, remote_sender: &str) -> Binary {
+///
+/// `account_id` is appended after a null byte. Senders are addresses,
+/// which never contain one, so the first null byte marks where the
+/// sender ends: a sender's sub-account can not have the salt of
+/// another sender or of another of its sub-accounts, and salts of
+/// senders without an account ID are unchanged.
+fn salt(
+    local_connection: &str,
+    counterparty_port: &str,
+    remote_sender: &str,
+    account_id: Option<&str>,
+) -> Binary {
     use sha2::{Digest, Sha512};
     // the salt can be a max of 64 bytes (512 bits).
-    let hash = Sha512::default()
+    let mut hasher = Sha512::default()
         .chain_update(local_connection.as_bytes())
         .chain_update(counterparty_port.as_bytes())
-        .chain_update(remote_sender.as_bytes())
-        .finalize();
-    Binary::from(hash.as_slice())
+        .chain_update(remote_sender.as_bytes());
+    // addresses never contain a null byte, so this can not collide
+    // with the salt of a sender without an account ID.
+    if let Some(account_id) = account_id {
+        hasher.update([0]);
+        hasher.update(account_id.as_bytes());
+    }
+    Binary::from(hasher.finalize().as_slice())
+}
+
+/// The salt of the next proxy created for the sender, which differs
+/// from `salt` once a proxy with it has been transferred away.
+fn proxy_salt(
+    storage: &dyn Storage,
+    local_connection: &str,
+    counterparty_port: &str,
+    remote_sender: &str,
+    account_id: Option<&str>,
+) -> StdResult<Binary> {
+    use sha2::{Digest, Sha512};
+    let salt = salt(
+        local_connection,
+        counterparty_port,
+        remote_sender,
+        account_id,
+    );
+    Ok(match SALT_GENERATIONS.may_load(storage, salt.as_slice())? {
+        Some(generation) => Binary::from(
+            Sha512::default()
+                .chain_update(salt.as_slice())
+                .chain_update(generation.to_be_bytes())
+                .finalize()
+                .as_slice(),
+        ),
+        None => salt,
+    })
+}
+
+/// Records that `proxy` is controlled by `owner`.
+fn save_proxy(storage: &mut dyn Storage, proxy: &Addr, owner: SenderInfo) -> StdResult<()> {
+    let SenderInfo {
+        connection_id,
+        remote_port,
+        remote_sender,
+        account_id,
+    } = owner.clone();
+    match account_id {
+        Some(account_id) => SENDER_ACCOUNT_TO_PROXY.save(
+            storage,
+            (
+                connection_id.clone(),
+                remote_port,
+                (remote_sender, account_id),
+            ),
+            proxy,
+        )?,
+        None => SENDER_TO_PROXY.save(
+            storage,
+            (connection_id.clone(), remote_port, remote_sender),
+            proxy,
+        )?,
+    }
+    CONNECTION_PROXIES.save(storage, (connection_id, proxy.clone()), &Empty {})?;
+    PROXY_TO_SENDER.save(storage, proxy.clone(), &owner)
+}
+
+/// Makes `new_owner` the controller of `sender`'s proxy with
+/// `account_id`, returning the proxy.
+fn transfer_proxy(
+    storage: &mut dyn Storage,
+    connection_id: String,
+    counterparty_port: String,
+    sender: String,
+    account_id: Option<String>,
+    new_owner: String,
+) -> Result<Addr, ContractError> {
+    let proxy = load_proxy(
+        storage,
+        connection_id.clone(),
+        counterparty_port.clone(),
+        sender.clone(),
+        account_id.clone(),
+    )?
+    .ok_or(ContractError::NoProxy)?;
+    if load_proxy(
+        storage,
+        connection_id.clone(),
+        counterparty_port.clone(),
+        new_owner.clone(),
+        account_id.clone(),
+    )?
+    .is_some()
+    {
+        return Err(ContractError::NewOwnerHasProxy);
+    }
+    // the previous owner's next proxy gets a new address, as the
+    // transferred proxy may have the one it would otherwise get.
+    let base = salt(
+        &connection_id,
+        &counterparty_port,
+        &sender,
+        account_id.as_deref(),
+    );
+    SALT_GENERATIONS.update(storage, base.as_slice(), |generation| -> StdResult<_> {
+        Ok(generation.unwrap_or_default() + 1)
+    })?;
+    match account_id.clone() {
+        Some(account_id) => SENDER_ACCOUNT_TO_PROXY.remove(
+            storage,
+            (
+                connection_id.clone(),
+                counterparty_port.clone(),
+                (sender, account_id),
+            ),
+        ),
+        None => SENDER_TO_PROXY.remove(
+            storage,
+            (connection_id.clone(), counterparty_port.clone(), sender),
+        ),
+    }
+    save_proxy(
+        storage,
+        &proxy,
+        SenderInfo {
+            connection_id,
+            remote_port: counterparty_port,
+            remote_sender: new_owner,
+            account_id,
+        },
+    )?;
+    Ok(proxy)
+}
+
+/// Loads the proxy of `sender` on the counterparty with `account_id`,
+/// if it exists.
+fn load_proxy(
+    storage: &dyn Storage,
+    connection_id: String,
+    counterparty_port: String,
+    sender: String,
+    account_id: Option<String>,
+) -> StdResult<Option<Addr>> {
+    match account_id {
+        Some(account_id) => SENDER_ACCOUNT_TO_PROXY.may_load(
+            storage,
+            (connection_id, counterparty_port, (sender, account_id)),
+        ),
+        None => SENDER_TO_PROXY.may_load(storage, (connection_id, counterparty_port, sender)),
+    }
+}
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
+
+/// Replaces the connection and port in `info` with that of the most
+/// recent alias of its counterparty, if any.
+fn current_sender_info(storage: &dyn Storage, info: SenderInfo) -> StdResult<SenderInfo> {
+    let SenderInfo {
+        connection_id,
+        remote_port,
+        remote_sender,
+        account_id,
+    } = info;
+    let (connection_id, remote_port) = COUNTERPARTY_REPLACEMENTS
+        .may_load(storage, (connection_id.clone(), remote_port.clone()))?
+        .unwrap_or((connection_id, remote_port));
+    Ok(SenderInfo {
+        connection_id,
+        remote_port,
+        remote_sender,
+        account_id,
+    })
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
@@ -200,14 +809,131 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
         QueryMsg::BlockMaxGas => to_json_binary(&BLOCK_MAX_GAS.load(deps.storage)?),
         QueryMsg::ProxyCodeId => to_json_binary(&PROXY_CODE_ID.load(deps.storage)?),
         QueryMsg::ContractAddrLen => to_json_binary(&CONTRACT_ADDR_LEN.load(deps.storage)?),
-        QueryMsg::SenderInfoForProxy { proxy } => {
-            to_json_binary(&PROXY_TO_SENDER.load(deps.storage, deps.api.addr_validate(&proxy)?)?)
+        QueryMsg::IdempotencyWindow => to_json_binary(&Uint64::new(
+            IDEMPOTENCY_WINDOW
+                .may_load(deps.storage)?
+                .unwrap_or(DEFAULT_IDEMPOTENCY_WINDOW_SECONDS),
+        )),
+        QueryMsg::SenderInfoForProxy { proxy } => to_json_binary(&current_sender_info(
+            deps.storage,
+            PROXY_TO_SENDER.load(deps.storage, deps.api.addr_validate(&proxy)?)?,
+        )?),
+        QueryMsg::ProxyForSender {
+            connection_id,
+            remote_port,
+            sender,
+            account_id,
+        } => {
+            let (connection_id, remote_port) =
+                resolve_counterparty(deps.storage, connection_id, remote_port)?;
+            to_json_binary(&load_proxy(
+                deps.storage,
+                connection_id,
+                remote_port,
+                sender,
+                account_id,
+            )?)
+        }
+        QueryMsg::MessagePolicy => to_json_binary(&MESSAGE_POLICY.may_load(deps.storage)?),
+        QueryMsg::ProxyMigration => to_json_binary(&PROXY_MIGRATION.may_load(deps.storage)?),
+        QueryMsg::MigrationOptOut { proxy } => {
+            to_json_binary(&MIGRATION_OPT_OUTS.has(deps.storage, deps.api.addr_validate(&proxy)?))
+        }
+        QueryMsg::NextNonce {
+            connection_id,
+            remote_port,
+            sender,
+        } => {
+            let (connection_id, remote_port) =
+                resolve_counterparty(deps.storage, connection_id, remote_port)?;
+            let last = NONCES
+                .may_load(deps.storage, (connection_id, remote_port, sender))?
+                .unwrap_or_default();
+            to_json_binary(&Uint64::new(last.saturating_add(1)))
+        }
+        QueryMsg::Proxies {
+            connection_id,
+            start_after,
+            limit,
+        } => {
+            let start_after = start_after
+                .map(|start_after| deps.api.addr_validate(&start_after))
+                .transpose()?;
+            let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+            let proxy_response = |proxy: Addr| -> StdResult<ProxyResponse> {
+                let info = PROXY_TO_SENDER.load(deps.storage, proxy.clone())?;
+                Ok(ProxyResponse {
+                    proxy: proxy.into_string(),
+                    sender_info: current_sender_info(deps.storage, info)?,
+                })
+            };
+            let Some(connection_id) = connection_id else {
+                return to_json_binary(
+                    &PROXY_TO_SENDER
+                        .keys(
+                            deps.storage,
+                            start_after.map(Bound::exclusive),
+                            None,
+                            Order::Ascending,
+                        )
+                        .take(limit)
+                        .map(|proxy| proxy_response(proxy?))
+                        .collect::<StdResult<Vec<_>>>()?,
+                );
+            };
+            // proxies are stored under their original counterparty,
+            // so those of counterparties replaced by an alias on
+            // `connection_id` are listed too.
+            let mut connections = COUNTERPARTY_REPLACEMENTS
+                .range(deps.storage, None, None, Order::Ascending)
+                .filter_map(|item| match item {
+                    Ok(((original, _), (replacement, _))) => {
+                        (replacement == connection_id).then_some(Ok(original))
+                    }
+                    Err(e) => Some(Err(e)),
+                })
+                .collect::<StdResult<Vec<String>>>()?;
+            connections.push(connection_id.clone());
+            connections.sort();
+            connections.dedup();
+            let mut proxies = vec![];
+            for connection in connections {
+                proxies.extend(
+                    CONNECTION_PROXIES
+                        .prefix(connection)
+                        .keys(
+                            deps.storage,
+                            start_after.clone().map(Bound::exclusive),
+                            None,
+                            Order::Ascending,
+                        )
+                        .map(|proxy| proxy_response(proxy?))
+                        .filter(|item| {
+                            item.as_ref().map_or(true, |item| {
+                                item.sender_info.connection_id == connection_id
+                            })
+                        })
+                        .take(limit)
+                        .collect::<StdResult<Vec<_>>>()?,
+                );
+            }
+            proxies.sort_by(|a, b| a.proxy.cmp(&b.proxy));
+            proxies.truncate(limit);
+            to_json_binary(&proxies)
         }
     }
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn migrate(deps: DepsMut, _env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+pub fn migrate(deps: DepsMut, env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    // voices migrated from versions that needed less gas to write
+    // ACKs may have a block max gas too low to receive packets with,
+    // which must be raised before anything else.
+    if !matches!(msg, MigrateMsg::WithUpdate { .. })
+        && BLOCK_MAX_GAS.load(deps.storage)? <= ACK_GAS_NEEDED
+    {
+        return Err(ContractError::GasLimitsMismatch);
+    }
     match msg {
         MigrateMsg::WithUpdate {
             proxy_code_id,
@@ -240,14 +966,224 @@ pub fn migrate(deps: DepsMut, _env: Env, msg: MigrateMsg) -> Result<Response, Co
                 .add_attribute("block_max_gas", block_max_gas)
                 .add_attribute("contract_addr_len", contract_addr_len.to_string()))
         }
+        MigrateMsg::SetIdempotencyWindow { seconds } => {
+            IDEMPOTENCY_WINDOW.save(deps.storage, &seconds.u64())?;
+            Ok(Response::default()
+                .add_attribute("method", "migrate_set_idempotency_window")
+                .add_attribute("idempotency_window", seconds))
+        }
+        MigrateMsg::SetMessagePolicy { policy } => {
+            match &policy {
+                Some(policy) => MESSAGE_POLICY.save(deps.storage, policy)?,
+                None => MESSAGE_POLICY.remove(deps.storage),
+            }
+            Ok(Response::default()
+                .add_attribute("method", "migrate_set_message_policy")
+                .add_attribute("policy", policy.is_some().to_string()))
+        }
+        MigrateMsg::MigrateProxies { code_id, limit } => {
+            if code_id.is_zero() {
+                return Err(ContractError::CodeIdCantBeZero);
+            }
+            let mut migration = match PROXY_MIGRATION
+                .may_load(deps.storage)?
+                .filter(|migration| migration.code_id == code_id)
+            {
+                Some(migration) => migration,
+                None => {
+                    // proxies created from here on use the new code,
+                    // so those the migration passes over need not be
+                    // migrated.
+                    PROXY_CODE_ID.save(deps.storage, &code_id.u64())?;
+                    ProxyMigration {
+                        code_id,
+                        start_after: None,
+                        migrated: 0,
+                        skipped: 0,
+                        not_admin: 0,
+                        failed: 0,
+                        done: false,
+                    }
+                }
+            };
+            let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+            let proxies = PROXY_TO_SENDER
+                .keys(
+                    deps.storage,
+                    migration.start_after.clone().map(Bound::exclusive),
+                    None,
+                    Order::Ascending,
+                )
+                .take(limit)
+                .collect::<StdResult<Vec<Addr>>>()?;
+            let mut submsgs = Vec::with_capacity(proxies.len());
+            for proxy in &proxies {
+                if MIGRATION_OPT_OUTS.has(deps.storage, proxy.clone()) {
+                    migration.skipped += 1;
+                } else if deps
+                    .querier
+                    .query_wasm_contract_info(proxy)?
+                    .admin
+                    .as_deref()
+                    != Some(env.contract.address.as_str())
+                {
+                    migration.not_admin += 1;
+                } else {
+                    // results are counted by the reply, so that
+                    // failures do not fail the batch.
+                    submsgs.push(SubMsg::reply_always(
+                        WasmMsg::Migrate {
+                            contract_addr: proxy.to_string(),
+                            new_code_id: code_id.u64(),
+                            msg: to_json_binary(&polytone_proxy::msg::MigrateMsg {})?,
+                        },
+                        REPLY_MIGRATE_PROXY,
+                    ));
+                }
+            }
+            migration.done = proxies.len() < limit;
+            if let Some(last) = proxies.last() {
+                migration.start_after = Some(last.clone());
+            }
+            PROXY_MIGRATION.save(deps.storage, &migration)?;
+            Ok(Response::default()
+                .add_attribute("method", "migrate_proxies")
+                .add_attribute("code_id", code_id)
+                .add_attribute("proxies", proxies.len().to_string())
+                .add_attribute("done", migration.done.to_string())
+                .add_submessages(submsgs))
+        }
+        MigrateMsg::IndexProxies { start_after, limit } => {
+            let start_after = start_after
+                .map(|start_after| deps.api.addr_validate(&start_after))
+                .transpose()?;
+            let proxies = PROXY_TO_SENDER
+                .range(
+                    deps.storage,
+                    start_after.map(Bound::exclusive),
+                    None,
+                    Order::Ascending,
+                )
+                .take(limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize)
+                .collect::<StdResult<Vec<_>>>()?;
+            for (proxy, info) in &proxies {
+                CONNECTION_PROXIES.save(
+                    deps.storage,
+                    (info.connection_id.clone(), proxy.clone()),
+                    &Empty {},
+                )?;
+            }
+            let mut response = Response::default()
+                .add_attribute("method", "migrate_index_proxies")
+                .add_attribute("proxies", proxies.len().to_string());
+            if let Some((last, _)) = proxies.last() {
+                response = response.add_attribute("last_proxy", last.as_str());
+            }
+            Ok(response)
+        }
+        MigrateMsg::AliasCounterparty { alias, original } => {
+            let alias = (alias.connection_id, alias.remote_port);
+            let response = Response::default()
+                .add_attribute("method", "migrate_alias_counterparty")
+                .add_attribute("alias_connection_id", alias.0.as_str())
+                .add_attribute("alias_remote_port", alias.1.as_str());
+            match original {
+                Some(Counterparty {
+                    connection_id,
+                    remote_port,
+                }) => {
+                    let original = resolve_counterparty(deps.storage, connection_id, remote_port)?;
+                    if original == alias {
+                        return Err(ContractError::AliasCycle);
+                    }
+                    if SENDER_TO_PROXY
+                        .prefix(alias.clone())
+                        .range(deps.storage, None, None, Order::Ascending)
+                        .next()
+                        .is_some()
+                        || SENDER_ACCOUNT_TO_PROXY
+                            .prefix(alias.clone())
+                            .range(deps.storage, None, None, Order::Ascending)
+                            .next()
+                            .is_some()
+                    {
+                        return Err(ContractError::CounterpartyHasProxies);
+                    }
+                    COUNTERPARTY_ALIASES.save(deps.storage, alias.clone(), &original)?;
+                    COUNTERPARTY_REPLACEMENTS.save(deps.storage, original.clone(), &alias)?;
+                    Ok(response
+                        .add_attribute("original_connection_id", original.0)
+                        .add_attribute("original_remote_port", original.1))
+                }
+                None => {
+                    let original =
+                        resolve_counterparty(deps.storage, alias.0.clone(), alias.1.clone())?;
+                    if COUNTERPARTY_REPLACEMENTS.may_load(deps.storage, original.clone())?
+                        == Some(alias.clone())
+                    {
+                        COUNTERPARTY_REPLACEMENTS.remove(deps.storage, original);
+                    }
+                    COUNTERPARTY_ALIASES.remove(deps.storage, alias);
+                    Ok(response)
+                }
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use cosmwasm_std::{instantiate2_address, CanonicalAddr, HexBinary};
+    use cosmwasm_std::{
+        coin, from_json, instantiate2_address,
+        testing::{mock_dependencies, mock_env, mock_ibc_packet_recv, mock_info},
+        to_json_binary, Addr, BalanceResponse, BankMsg, BankQuery, Binary, CanonicalAddr,
+        ContractInfoResponse, ContractResult, CosmosMsg, Decimal, Deps, GovMsg, HexBinary, Reply,
+        Response, Storage, SubMsgResponse, SubMsgResult, SystemResult, Timestamp, Uint64,
+        VoteOption, WasmMsg, WasmQuery, WeightedVoteOption,
+    };
+    use polytone::{
+        ack::{ack_execute_best_effort, ack_execute_fail, ack_execute_success},
+        callbacks::{
+            BestEffortExecutionResponse, Callback, ConditionalExecutionResponse, ErrorResponse,
+            ExecutionResponse, TemplatedExecutionResponse, DUPLICATE_IDEMPOTENCY_KEY,
+        },
+        conditions::{Comparison, Condition},
+        ibc::{ExecuteMode, Msg, Packet},
+        value::Value,
+    };
+
+    use crate::{
+        error::ContractError,
+        ibc::{
+            ibc_packet_receive, reply, ACK_GAS_NEEDED, REPLY_BEST_EFFORT,
+            REPLY_CONDITIONAL_EXECUTE, REPLY_MIGRATE_PROXY, REPLY_TEMPLATED_EXECUTE,
+        },
+        msg::{ExecuteMsg, MigrateMsg, ProxyResponse, QueryMsg},
+        policy::{Filter, MessagePolicy},
+        state::{
+            resolve_counterparty, BestEffortExecution, Counterparty, ProxyMigration, SenderInfo,
+            BEST_EFFORT, BLOCK_MAX_GAS, CHANNEL_TO_CONNECTION, COUNTERPARTY_REPLACEMENTS,
+            IDEMPOTENCY_EXPIRIES, IDEMPOTENCY_KEYS, MESSAGE_POLICY, NONCES, PROXY_CODE_ID,
+            PROXY_TO_SENDER, QUERY_RESULTS, REQUESTED_GAS_LIMIT, SALT_GENERATIONS,
+            SENDER_ACCOUNT_TO_PROXY, SENDER_TO_PROXY,
+        },
+    };
+
+    use super::{execute, migrate, proxy_execute, proxy_salt, query, salt, save_proxy};
 
-    use super::salt;
+    /// Wraps `data` in a MsgExecuteContractResponse, as wasmd does
+    /// for the data of a reply to an execution.
+    fn execute_response(data: &Binary) -> Binary {
+        let mut response = vec![0x0a];
+        let mut len = data.len();
+        while len >= 0x80 {
+            response.push((len as u8) | 0x80);
+            len >>= 7;
+        }
+        response.push(len as u8);
+        response.extend_from_slice(data.as_slice());
+        Binary::from(response)
+    }
 
     fn gen_address(
         local_connection: &str,
@@ -259,7 +1195,7 @@ mod tests {
                 .unwrap();
         let creator = CanonicalAddr::from((0..90).map(|_| 9).collect::<Vec<u8>>().as_slice());
 
-        let salt = salt(local_connection, counterparty_port, remote_sender);
+        let salt = salt(local_connection, counterparty_port, remote_sender, None);
         assert!(salt.len() <= 64);
         instantiate2_address(checksum.as_slice(), &creator, &salt).unwrap()
     }
@@ -274,4 +1210,1559 @@ mod tests {
         let four = gen_address("c1", "c1", "c2");
         assert!(one != two && two != three && three != four)
     }
+
+    fn counterparty(connection_id: &str) -> Counterparty {
+        Counterparty {
+            connection_id: connection_id.to_string(),
+            remote_port: "wasm.note".to_string(),
+        }
+    }
+
+    /// Senders from an alias control proxies of the original, and
+    /// queries show the alias as the proxy's counterparty.
+    #[test]
+    fn test_alias_counterparty() {
+        let mut deps = mock_dependencies();
+        BLOCK_MAX_GAS
+            .save(deps.as_mut().storage, &1_000_000)
+            .unwrap();
+        let proxy = Addr::unchecked("proxy");
+        let original = ("connection-0".to_string(), "wasm.note".to_string());
+
+        SENDER_TO_PROXY
+            .save(
+                deps.as_mut().storage,
+                (original.0.clone(), original.1.clone(), "sender".to_string()),
+                &proxy,
+            )
+            .unwrap();
+        PROXY_TO_SENDER
+            .save(
+                deps.as_mut().storage,
+                proxy.clone(),
+                &SenderInfo {
+                    connection_id: original.0.clone(),
+                    remote_port: original.1.clone(),
+                    remote_sender: "sender".to_string(),
+                    account_id: None,
+                },
+            )
+            .unwrap();
+
+        migrate(
+            deps.as_mut(),
+            mock_env(),
+            MigrateMsg::AliasCounterparty {
+                alias: counterparty("connection-1"),
+                original: Some(counterparty("connection-0")),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            resolve_counterparty(
+                deps.as_ref().storage,
+                "connection-1".to_string(),
+                "wasm.note".to_string()
+            )
+            .unwrap(),
+            original
+        );
+        let info: SenderInfo = from_json(
+            query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::SenderInfoForProxy {
+                    proxy: proxy.into_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(info.connection_id, "connection-1");
+
+        // the original can't become an alias of its alias.
+        let err = migrate(
+            deps.as_mut(),
+            mock_env(),
+            MigrateMsg::AliasCounterparty {
+                alias: counterparty("connection-0"),
+                original: Some(counterparty("connection-1")),
+            },
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::AliasCycle);
+
+        // a counterparty with proxies can't become an alias.
+        let err = migrate(
+            deps.as_mut(),
+            mock_env(),
+            MigrateMsg::AliasCounterparty {
+                alias: counterparty("connection-0"),
+                original: Some(counterparty("connection-2")),
+            },
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::CounterpartyHasProxies);
+    }
+
+    #[test]
+    fn test_proxy_queries() {
+        let mut deps = mock_dependencies();
+        BLOCK_MAX_GAS
+            .save(deps.as_mut().storage, &1_000_000)
+            .unwrap();
+
+        let proxies = [
+            ("connection-0", "alice", "proxy-a"),
+            ("connection-1", "bob", "proxy-b"),
+            ("connection-0", "carol", "proxy-c"),
+        ];
+        for (connection_id, sender, proxy) in proxies {
+            save_proxy(
+                deps.as_mut().storage,
+                &Addr::unchecked(proxy),
+                SenderInfo {
+                    connection_id: connection_id.to_string(),
+                    remote_port: "wasm.note".to_string(),
+                    remote_sender: sender.to_string(),
+                    account_id: None,
+                },
+            )
+            .unwrap();
+        }
+        // a proxy created before proxies were indexed by connection.
+        PROXY_TO_SENDER
+            .save(
+                deps.as_mut().storage,
+                Addr::unchecked("proxy-d"),
+                &SenderInfo {
+                    connection_id: "connection-0".to_string(),
+                    remote_port: "wasm.note".to_string(),
+                    remote_sender: "dave".to_string(),
+                    account_id: None,
+                },
+            )
+            .unwrap();
+        let connection_proxies = |deps: Deps, connection_id: &str| -> Vec<String> {
+            from_json::<Vec<ProxyResponse>>(
+                query(
+                    deps,
+                    mock_env(),
+                    QueryMsg::Proxies {
+                        connection_id: Some(connection_id.to_string()),
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap(),
+            )
+            .unwrap()
+            .into_iter()
+            .map(|p| p.proxy)
+            .collect()
+        };
+
+        let proxy: Option<String> = from_json(
+            query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::ProxyForSender {
+                    connection_id: "connection-1".to_string(),
+                    remote_port: "wasm.note".to_string(),
+                    sender: "bob".to_string(),
+                    account_id: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(proxy, Some("proxy-b".to_string()));
+
+        assert_eq!(
+            connection_proxies(deps.as_ref(), "connection-0"),
+            vec!["proxy-a".to_string(), "proxy-c".to_string()]
+        );
+
+        // once indexed, older proxies are listed too.
+        migrate(
+            deps.as_mut(),
+            mock_env(),
+            MigrateMsg::IndexProxies {
+                start_after: Some("proxy-c".to_string()),
+                limit: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            connection_proxies(deps.as_ref(), "connection-0"),
+            vec![
+                "proxy-a".to_string(),
+                "proxy-c".to_string(),
+                "proxy-d".to_string()
+            ]
+        );
+
+        // proxies of a replaced counterparty are listed on the
+        // connection of its replacement.
+        COUNTERPARTY_REPLACEMENTS
+            .save(
+                deps.as_mut().storage,
+                ("connection-1".to_string(), "wasm.note".to_string()),
+                &("connection-2".to_string(), "wasm.note".to_string()),
+            )
+            .unwrap();
+        assert!(connection_proxies(deps.as_ref(), "connection-1").is_empty());
+        assert_eq!(
+            connection_proxies(deps.as_ref(), "connection-2"),
+            vec!["proxy-b".to_string()]
+        );
+
+        let proxies: Vec<ProxyResponse> = from_json(
+            query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::Proxies {
+                    connection_id: None,
+                    start_after: Some("proxy-a".to_string()),
+                    limit: Some(1),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(proxies.len(), 1);
+        assert_eq!(proxies[0].proxy, "proxy-b");
+        assert_eq!(proxies[0].sender_info.remote_sender, "bob");
+    }
+
+    #[test]
+    fn test_best_effort_replies() {
+        let mut deps = mock_dependencies();
+
+        BEST_EFFORT
+            .save(
+                deps.as_mut().storage,
+                &BestEffortExecution {
+                    executed_by: "proxy".to_string(),
+                    expected: 2,
+                    results: vec![],
+                },
+            )
+            .unwrap();
+
+        let res = reply(
+            deps.as_mut(),
+            mock_env(),
+            Reply {
+                id: REPLY_BEST_EFFORT,
+                result: SubMsgResult::Err("insufficient funds".to_string()),
+            },
+        )
+        .unwrap();
+        assert_eq!(res.data, None);
+
+        // the proxy returns its result wrapped in a
+        // MsgExecuteContractResponse.
+        let success = SubMsgResponse {
+            events: vec![],
+            data: Some(Binary::from(b"hello".to_vec())),
+        };
+        let callback = ack_execute_success(vec![success.clone()], "proxy".to_string());
+
+        let res = reply(
+            deps.as_mut(),
+            mock_env(),
+            Reply {
+                id: REPLY_BEST_EFFORT,
+                result: SubMsgResult::Ok(SubMsgResponse {
+                    events: vec![],
+                    data: Some(execute_response(&callback)),
+                }),
+            },
+        )
+        .unwrap();
+        let callback: Callback = from_json(res.data.unwrap()).unwrap();
+        assert_eq!(
+            callback,
+            Callback::ExecuteBestEffort(BestEffortExecutionResponse {
+                executed_by: "proxy".to_string(),
+                result: vec![Err("insufficient funds".to_string()), Ok(success)],
+            })
+        );
+        assert!(BEST_EFFORT
+            .may_load(deps.as_ref().storage)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_execution_window() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let now = env.block.time;
+
+        let rx = |not_before, not_after| ExecuteMsg::Rx {
+            connection_id: "connection-0".to_string(),
+            counterparty_port: "wasm.note".to_string(),
+            data: to_json_binary(&Packet {
+                sender: "sender".to_string(),
+                msg: Msg::Query {
+                    msgs: vec![],
+                    partial: None,
+                },
+                gas_limit: None,
+                not_before,
+                not_after,
+                nonce: None,
+                idempotency_key: None,
+                account_id: None,
+            })
+            .unwrap(),
+        };
+
+        for (not_before, not_after, executed) in [
+            (None, None, true),
+            (Some(now), Some(now), true),
+            (Some(now.plus_seconds(1)), None, false),
+            (None, Some(now.minus_seconds(1)), false),
+        ] {
+            let res = execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info(env.contract.address.as_str(), &[]),
+                rx(not_before, not_after),
+            )
+            .unwrap();
+            let callback: Callback = from_json(res.data.unwrap()).unwrap();
+            if executed {
+                assert_eq!(callback, Callback::Query(Ok(vec![])));
+            } else {
+                assert_eq!(
+                    callback,
+                    Callback::OutsideWindow {
+                        received_at: now,
+                        not_before,
+                        not_after,
+                    }
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_partial_query() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        let packet = |partial| Packet {
+            sender: "sender".to_string(),
+            gas_limit: None,
+            not_before: None,
+            not_after: None,
+            nonce: None,
+            idempotency_key: None,
+            account_id: None,
+            msg: Msg::Query {
+                msgs: vec![
+                    WasmQuery::Smart {
+                        contract_addr: "missing".to_string(),
+                        msg: Binary::default(),
+                    }
+                    .into(),
+                    BankQuery::Balance {
+                        address: "sender".to_string(),
+                        denom: "ucosm".to_string(),
+                    }
+                    .into(),
+                ],
+                partial,
+            },
+        };
+        let rx = |partial| ExecuteMsg::Rx {
+            connection_id: "connection-0".to_string(),
+            counterparty_port: "wasm.note".to_string(),
+            data: to_json_binary(&packet(partial)).unwrap(),
+        };
+
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(env.contract.address.as_str(), &[]),
+            rx(None),
+        )
+        .unwrap();
+        let callback: Callback = from_json(res.data.unwrap()).unwrap();
+        assert!(
+            matches!(callback, Callback::Query(Err(ErrorResponse { message_index, .. })) if message_index.is_zero())
+        );
+
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(env.contract.address.as_str(), &[]),
+            rx(Some(true)),
+        )
+        .unwrap();
+        let callback: Callback = from_json(res.data.unwrap()).unwrap();
+        let Callback::QueryPartial(results) = callback else {
+            panic!("expected partial query callback, got {callback:?}")
+        };
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_err());
+        assert_eq!(
+            results[1],
+            Ok(to_json_binary(&BalanceResponse {
+                amount: coin(0, "ucosm"),
+            })
+            .unwrap())
+        );
+    }
+
+    #[test]
+    fn test_conditional_execute() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        SENDER_TO_PROXY
+            .save(
+                deps.as_mut().storage,
+                (
+                    "connection-0".to_string(),
+                    "wasm.note".to_string(),
+                    "sender".to_string(),
+                ),
+                &Addr::unchecked("proxy"),
+            )
+            .unwrap();
+
+        let rx = |amount: i128| ExecuteMsg::Rx {
+            connection_id: "connection-0".to_string(),
+            counterparty_port: "wasm.note".to_string(),
+            data: to_json_binary(&Packet {
+                sender: "sender".to_string(),
+                gas_limit: None,
+                not_before: None,
+                not_after: None,
+                nonce: None,
+                idempotency_key: None,
+                account_id: None,
+                msg: Msg::ConditionalExecute {
+                    queries: vec![BankQuery::Balance {
+                        address: "proxy".to_string(),
+                        denom: "ucosm".to_string(),
+                    }
+                    .into()],
+                    conditions: vec![Condition::Path {
+                        query_index: 0,
+                        path: "amount.amount".to_string(),
+                        comparison: Comparison::GreaterThanOrEqual,
+                        value: Value::Number(amount),
+                    }],
+                    msgs: vec![],
+                },
+            })
+            .unwrap(),
+        };
+
+        // the proxy has no balance, so the condition does not hold.
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(env.contract.address.as_str(), &[]),
+            rx(1),
+        )
+        .unwrap();
+        assert!(res.messages.is_empty());
+        let callback: Callback = from_json(res.data.unwrap()).unwrap();
+        let Callback::ConditionalExecute(ConditionalExecutionResponse {
+            query: Ok(results),
+            failed_condition: Some(ErrorResponse { message_index, .. }),
+            execute: None,
+        }) = callback
+        else {
+            panic!("expected failed condition, got {callback:?}")
+        };
+        assert_eq!(results.len(), 1);
+        assert!(message_index.is_zero());
+
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(env.contract.address.as_str(), &[]),
+            rx(0),
+        )
+        .unwrap();
+        assert_eq!(res.data, None);
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(res.messages[0].id, REPLY_CONDITIONAL_EXECUTE);
+        assert_eq!(QUERY_RESULTS.load(deps.as_ref().storage).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_templated_execute() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        SENDER_TO_PROXY
+            .save(
+                deps.as_mut().storage,
+                (
+                    "connection-0".to_string(),
+                    "wasm.note".to_string(),
+                    "sender".to_string(),
+                ),
+                &Addr::unchecked("proxy"),
+            )
+            .unwrap();
+
+        let rx = |template: &str| ExecuteMsg::Rx {
+            connection_id: "connection-0".to_string(),
+            counterparty_port: "wasm.note".to_string(),
+            data: to_json_binary(&Packet {
+                sender: "sender".to_string(),
+                gas_limit: None,
+                not_before: None,
+                not_after: None,
+                nonce: None,
+                idempotency_key: None,
+                account_id: None,
+                msg: Msg::TemplatedExecute {
+                    queries: vec![BankQuery::Balance {
+                        address: "proxy".to_string(),
+                        denom: "ucosm".to_string(),
+                    }
+                    .into()],
+                    msgs: vec![from_json(template).unwrap()],
+                },
+            })
+            .unwrap(),
+        };
+
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(env.contract.address.as_str(), &[]),
+            rx(r#"{"bank":{"burn":{"amount":"$q1.amount"}}}"#),
+        )
+        .unwrap();
+        assert!(res.messages.is_empty());
+        let callback: Callback = from_json(res.data.unwrap()).unwrap();
+        let Callback::TemplatedExecute(TemplatedExecutionResponse {
+            query: Ok(_),
+            failed_template: Some(ErrorResponse { message_index, .. }),
+            execute: None,
+        }) = callback
+        else {
+            panic!("expected failed template, got {callback:?}")
+        };
+        assert!(message_index.is_zero());
+
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(env.contract.address.as_str(), &[]),
+            rx(r#"{"bank":{"burn":{"amount":["$q0.amount"]}}}"#),
+        )
+        .unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(res.messages[0].id, REPLY_TEMPLATED_EXECUTE);
+        assert_eq!(
+            res.messages[0].msg,
+            proxy_execute(
+                "proxy".to_string(),
+                vec![BankMsg::Burn {
+                    amount: vec![coin(0, "ucosm")],
+                }
+                .into()]
+            )
+            .unwrap()
+            .into()
+        );
+    }
+
+    #[test]
+    fn test_requested_gas_limit() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        BLOCK_MAX_GAS
+            .save(deps.as_mut().storage, &1_000_000)
+            .unwrap();
+        CHANNEL_TO_CONNECTION
+            .save(
+                deps.as_mut().storage,
+                "channel-0".to_string(),
+                &"connection-0".to_string(),
+            )
+            .unwrap();
+
+        let packet = |gas_limit| Packet {
+            sender: "sender".to_string(),
+            gas_limit,
+            not_before: None,
+            not_after: None,
+            nonce: None,
+            idempotency_key: None,
+            account_id: None,
+            msg: Msg::Execute {
+                msgs: vec![],
+                mode: None,
+            },
+        };
+
+        let res = ibc_packet_receive(
+            deps.as_mut(),
+            env.clone(),
+            mock_ibc_packet_recv("channel-0", &packet(None)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(res.messages[0].gas_limit, Some(1_000_000 - ACK_GAS_NEEDED));
+
+        let res = ibc_packet_receive(
+            deps.as_mut(),
+            env.clone(),
+            mock_ibc_packet_recv("channel-0", &packet(Some(Uint64::new(2_000_000)))).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(res.messages[0].gas_limit, Some(1_000_000 - ACK_GAS_NEEDED));
+
+        let res = ibc_packet_receive(
+            deps.as_mut(),
+            env.clone(),
+            mock_ibc_packet_recv("channel-0", &packet(Some(Uint64::new(300_000)))).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(res.messages[0].gas_limit, Some(300_000));
+
+        // the error wasmd replies with when a submessage runs out of
+        // gas, written out rather than using `OUT_OF_GAS_ERROR` so
+        // that changing that breaks this test.
+        let res = reply(
+            deps.as_mut(),
+            env.clone(),
+            Reply {
+                id: 0,
+                result: SubMsgResult::Err("codespace: sdk, code: 11".to_string()),
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            from_json::<Callback>(res.data.unwrap()).unwrap(),
+            Callback::GasLimitExceeded(Uint64::new(300_000))
+        );
+        assert!(REQUESTED_GAS_LIMIT
+            .may_load(deps.as_ref().storage)
+            .unwrap()
+            .is_none());
+
+        // other errors are fatal even if a limit was requested.
+        ibc_packet_receive(
+            deps.as_mut(),
+            env.clone(),
+            mock_ibc_packet_recv("channel-0", &packet(Some(Uint64::new(300_000)))).unwrap(),
+        )
+        .unwrap();
+        let res = reply(
+            deps.as_mut(),
+            env,
+            Reply {
+                id: 0,
+                result: SubMsgResult::Err("codespace: wasm, code: 5".to_string()),
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            from_json::<Callback>(res.data.unwrap()).unwrap(),
+            Callback::FatalError("codespace: wasm, code: 5".to_string())
+        );
+    }
+
+    #[test]
+    fn test_nonces() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        SENDER_TO_PROXY
+            .save(
+                deps.as_mut().storage,
+                (
+                    "connection-0".to_string(),
+                    "wasm.note".to_string(),
+                    "sender".to_string(),
+                ),
+                &Addr::unchecked("proxy"),
+            )
+            .unwrap();
+        MESSAGE_POLICY
+            .save(
+                deps.as_mut().storage,
+                &MessagePolicy {
+                    msg_types: Some(Filter::Deny(vec!["bank".to_string()])),
+                    contracts: None,
+                    stargate_type_urls: None,
+                },
+            )
+            .unwrap();
+
+        let mut rx = |nonce: Option<u64>, not_after: Option<Timestamp>, msgs: Vec<CosmosMsg>| {
+            execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info(env.contract.address.as_str(), &[]),
+                ExecuteMsg::Rx {
+                    connection_id: "connection-0".to_string(),
+                    counterparty_port: "wasm.note".to_string(),
+                    data: to_json_binary(&Packet {
+                        sender: "sender".to_string(),
+                        gas_limit: None,
+                        not_before: None,
+                        not_after,
+                        nonce: nonce.map(Uint64::new),
+                        idempotency_key: None,
+                        account_id: None,
+                        msg: Msg::Execute { msgs, mode: None },
+                    })
+                    .unwrap(),
+                },
+            )
+            .unwrap()
+        };
+
+        // packets without a nonce are always handled.
+        assert_eq!(rx(None, None, vec![]).messages.len(), 1);
+        assert_eq!(rx(Some(1), None, vec![]).messages.len(), 1);
+        assert_eq!(rx(None, None, vec![]).messages.len(), 1);
+
+        for (nonce, expected) in [(1, 2), (3, 2)] {
+            let res = rx(Some(nonce), None, vec![]);
+            assert!(res.messages.is_empty());
+            assert_eq!(
+                from_json::<Callback>(res.data.unwrap()).unwrap(),
+                Callback::OutOfOrder {
+                    nonce: Uint64::new(nonce),
+                    expected: Uint64::new(expected),
+                }
+            );
+        }
+
+        // packets received outside of their window or rejected by the
+        // message policy do not use their nonce.
+        let res = rx(Some(2), Some(env.block.time.minus_seconds(1)), vec![]);
+        assert!(matches!(
+            from_json::<Callback>(res.data.unwrap()).unwrap(),
+            Callback::OutsideWindow { .. }
+        ));
+        let bank_send: CosmosMsg = BankMsg::Send {
+            to_address: "someone".to_string(),
+            amount: vec![coin(1, "ucoin")],
+        }
+        .into();
+        let res = rx(Some(2), None, vec![bank_send]);
+        assert!(matches!(
+            from_json::<Callback>(res.data.unwrap()).unwrap(),
+            Callback::Rejected(_)
+        ));
+
+        assert_eq!(rx(Some(2), None, vec![]).messages.len(), 1);
+
+        let next: Uint64 = from_json(
+            query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::NextNonce {
+                    connection_id: "connection-0".to_string(),
+                    remote_port: "wasm.note".to_string(),
+                    sender: "sender".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(next, Uint64::new(3));
+    }
+
+    #[test]
+    fn test_idempotency_keys() {
+        let mut deps = mock_dependencies();
+        BLOCK_MAX_GAS
+            .save(deps.as_mut().storage, &1_000_000)
+            .unwrap();
+        let mut env = mock_env();
+
+        SENDER_TO_PROXY
+            .save(
+                deps.as_mut().storage,
+                (
+                    "connection-0".to_string(),
+                    "wasm.note".to_string(),
+                    "sender".to_string(),
+                ),
+                &Addr::unchecked("proxy"),
+            )
+            .unwrap();
+        migrate(
+            deps.as_mut(),
+            env.clone(),
+            MigrateMsg::SetIdempotencyWindow {
+                seconds: Uint64::new(100),
+            },
+        )
+        .unwrap();
+
+        let rx = |idempotency_key: Option<&str>, msg: Msg| ExecuteMsg::Rx {
+            connection_id: "connection-0".to_string(),
+            counterparty_port: "wasm.note".to_string(),
+            data: to_json_binary(&Packet {
+                sender: "sender".to_string(),
+                gas_limit: None,
+                not_before: None,
+                not_after: None,
+                nonce: None,
+                idempotency_key: idempotency_key.map(str::to_string),
+                account_id: None,
+                msg,
+            })
+            .unwrap(),
+        };
+        let execute_msg = Msg::Execute {
+            msgs: vec![],
+            mode: None,
+        };
+
+        // keys are only used by requests that succeed, so "b" may be
+        // retried after failing.
+        for (key, seconds_later, executed, succeeds) in [
+            (None, 0, true, true),
+            (None, 0, true, true),
+            (Some("a"), 0, true, true),
+            (Some("b"), 0, true, false),
+            (Some("b"), 0, true, true),
+            (Some("a"), 99, false, false),
+            (Some("a"), 1, true, true),
+        ] {
+            env.block.time = env.block.time.plus_seconds(seconds_later);
+            let res = execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info(env.contract.address.as_str(), &[]),
+                rx(key, execute_msg.clone()),
+            )
+            .unwrap();
+            if !executed {
+                assert!(res.messages.is_empty());
+                assert_eq!(
+                    from_json::<Callback>(res.data.unwrap()).unwrap(),
+                    Callback::Execute(Err(DUPLICATE_IDEMPOTENCY_KEY.to_string()))
+                );
+                continue;
+            }
+            assert_eq!(res.messages.len(), 1, "{key:?}");
+            let ack = if succeeds {
+                ack_execute_success(vec![], "proxy".to_string())
+            } else {
+                ack_execute_fail("err".to_string())
+            };
+            reply(
+                deps.as_mut(),
+                env.clone(),
+                Reply {
+                    id: 0,
+                    result: SubMsgResult::Ok(SubMsgResponse {
+                        events: vec![],
+                        data: Some(execute_response(&ack)),
+                    }),
+                },
+            )
+            .unwrap();
+        }
+
+        // reusing "a" expired and pruned "b", which was used at the
+        // same time as "a" was first.
+        let proxy = Addr::unchecked("proxy");
+        assert!(IDEMPOTENCY_KEYS.has(deps.as_ref().storage, (&proxy, "a")));
+        assert!(!IDEMPOTENCY_KEYS.has(deps.as_ref().storage, (&proxy, "b")));
+        assert_eq!(
+            IDEMPOTENCY_EXPIRIES
+                .keys(
+                    deps.as_ref().storage,
+                    None,
+                    None,
+                    cosmwasm_std::Order::Ascending
+                )
+                .count(),
+            1
+        );
+
+        // best-effort executions whose messages all failed do not use
+        // their key.
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(env.contract.address.as_str(), &[]),
+            rx(
+                Some("c"),
+                Msg::Execute {
+                    msgs: vec![BankMsg::Burn { amount: vec![] }.into()],
+                    mode: Some(ExecuteMode::BestEffort),
+                },
+            ),
+        )
+        .unwrap();
+        assert_eq!(res.messages.len(), 1);
+        reply(
+            deps.as_mut(),
+            env.clone(),
+            Reply {
+                id: 0,
+                result: SubMsgResult::Ok(SubMsgResponse {
+                    events: vec![],
+                    data: Some(execute_response(&ack_execute_best_effort(
+                        vec![Err("err".to_string())],
+                        "proxy".to_string(),
+                    ))),
+                }),
+            },
+        )
+        .unwrap();
+        assert!(!IDEMPOTENCY_KEYS.has(deps.as_ref().storage, (&proxy, "c")));
+
+        // duplicate conditional executions keep their query results.
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(env.contract.address.as_str(), &[]),
+            rx(
+                Some("a"),
+                Msg::ConditionalExecute {
+                    queries: vec![],
+                    conditions: vec![],
+                    msgs: vec![],
+                },
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            from_json::<Callback>(res.data.unwrap()).unwrap(),
+            Callback::ConditionalExecute(ConditionalExecutionResponse {
+                query: Ok(vec![]),
+                failed_condition: None,
+                execute: Some(Err(DUPLICATE_IDEMPOTENCY_KEY.to_string())),
+            })
+        );
+    }
+
+    /// Sub-accounts get their own addresses and proxies, distinct
+    /// from the sender's default proxy and from each other.
+    #[test]
+    fn test_sub_accounts() {
+        let salts = [
+            salt("c1", "c1", "c1", None),
+            salt("c1", "c1", "c1", Some("")),
+            salt("c1", "c1", "c1", Some("a")),
+            salt("c1", "c1", "c1", Some("b")),
+            salt("c1", "c1", "c1a", None),
+        ];
+        for (i, a) in salts.iter().enumerate() {
+            assert!(a.len() <= 64);
+            assert!(salts[i + 1..].iter().all(|b| a != b));
+        }
+
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let key = |account_id: Option<&str>| {
+            (
+                "connection-0".to_string(),
+                "wasm.note".to_string(),
+                (
+                    "sender".to_string(),
+                    account_id.unwrap_or_default().to_string(),
+                ),
+            )
+        };
+        SENDER_TO_PROXY
+            .save(
+                deps.as_mut().storage,
+                (key(None).0, key(None).1, "sender".to_string()),
+                &Addr::unchecked("default"),
+            )
+            .unwrap();
+        SENDER_ACCOUNT_TO_PROXY
+            .save(deps.as_mut().storage, key(Some("a")), &Addr::unchecked("a"))
+            .unwrap();
+
+        for (account_id, proxy) in [(None, "default"), (Some("a"), "a")] {
+            let res = execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info(env.contract.address.as_str(), &[]),
+                ExecuteMsg::Rx {
+                    connection_id: "connection-0".to_string(),
+                    counterparty_port: "wasm.note".to_string(),
+                    data: to_json_binary(&Packet {
+                        sender: "sender".to_string(),
+                        gas_limit: None,
+                        not_before: None,
+                        not_after: None,
+                        nonce: None,
+                        idempotency_key: None,
+                        account_id: account_id.map(str::to_string),
+                        msg: Msg::Execute {
+                            msgs: vec![],
+                            mode: None,
+                        },
+                    })
+                    .unwrap(),
+                },
+            )
+            .unwrap();
+            assert!(matches!(
+                &res.messages[0].msg,
+                CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, .. }) if contract_addr == proxy
+            ));
+
+            let queried: Option<String> = from_json(
+                query(
+                    deps.as_ref(),
+                    env.clone(),
+                    QueryMsg::ProxyForSender {
+                        connection_id: "connection-0".to_string(),
+                        remote_port: "wasm.note".to_string(),
+                        sender: "sender".to_string(),
+                        account_id: account_id.map(str::to_string),
+                    },
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            assert_eq!(queried.as_deref(), Some(proxy));
+        }
+    }
+
+    /// Transferring an account rebinds its proxy to the new owner.
+    #[test]
+    fn test_transfer_account() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        for (owner, proxy) in [("old", "proxy"), ("taken", "taken-proxy")] {
+            PROXY_TO_SENDER
+                .save(
+                    deps.as_mut().storage,
+                    Addr::unchecked(proxy),
+                    &SenderInfo {
+                        connection_id: "connection-0".to_string(),
+                        remote_port: "wasm.note".to_string(),
+                        remote_sender: owner.to_string(),
+                        account_id: None,
+                    },
+                )
+                .unwrap();
+            SENDER_TO_PROXY
+                .save(
+                    deps.as_mut().storage,
+                    (
+                        "connection-0".to_string(),
+                        "wasm.note".to_string(),
+                        owner.to_string(),
+                    ),
+                    &Addr::unchecked(proxy),
+                )
+                .unwrap();
+        }
+
+        NONCES
+            .save(
+                deps.as_mut().storage,
+                (
+                    "connection-0".to_string(),
+                    "wasm.note".to_string(),
+                    "old".to_string(),
+                ),
+                &4,
+            )
+            .unwrap();
+
+        let mut transfer = |sender: &str, new_owner: &str| {
+            execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info(env.contract.address.as_str(), &[]),
+                ExecuteMsg::Rx {
+                    connection_id: "connection-0".to_string(),
+                    counterparty_port: "wasm.note".to_string(),
+                    data: to_json_binary(&Packet {
+                        sender: sender.to_string(),
+                        gas_limit: None,
+                        not_before: None,
+                        not_after: None,
+                        nonce: None,
+                        idempotency_key: None,
+                        account_id: None,
+                        msg: Msg::TransferAccount {
+                            new_owner: new_owner.to_string(),
+                        },
+                    })
+                    .unwrap(),
+                },
+            )
+        };
+
+        for (sender, new_owner, error) in [
+            ("nobody", "new", "sender has no proxy"),
+            ("old", "taken", "new owner already has a proxy"),
+        ] {
+            let res = transfer(sender, new_owner).unwrap();
+            assert_eq!(
+                from_json::<Callback>(res.data.unwrap()).unwrap(),
+                Callback::Execute(Err(error.to_string()))
+            );
+        }
+        let res = transfer("old", "new").unwrap();
+        assert_eq!(
+            from_json::<Callback>(res.data.unwrap()).unwrap(),
+            Callback::Execute(Ok(ExecutionResponse {
+                executed_by: "proxy".to_string(),
+                result: vec![],
+            }))
+        );
+
+        let proxy_for = |sender: &str| -> Option<String> {
+            from_json(
+                query(
+                    deps.as_ref(),
+                    env.clone(),
+                    QueryMsg::ProxyForSender {
+                        connection_id: "connection-0".to_string(),
+                        remote_port: "wasm.note".to_string(),
+                        sender: sender.to_string(),
+                        account_id: None,
+                    },
+                )
+                .unwrap(),
+            )
+            .unwrap()
+        };
+        assert_eq!(proxy_for("old"), None);
+        assert_eq!(proxy_for("new"), Some("proxy".to_string()));
+        let info: SenderInfo = from_json(
+            query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::SenderInfoForProxy {
+                    proxy: "proxy".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(info.remote_sender, "new");
+
+        // nonces stay with their senders.
+        let next_nonce = |sender: &str| -> Uint64 {
+            from_json(
+                query(
+                    deps.as_ref(),
+                    env.clone(),
+                    QueryMsg::NextNonce {
+                        connection_id: "connection-0".to_string(),
+                        remote_port: "wasm.note".to_string(),
+                        sender: sender.to_string(),
+                    },
+                )
+                .unwrap(),
+            )
+            .unwrap()
+        };
+        assert_eq!(next_nonce("old"), Uint64::new(5));
+        assert_eq!(next_nonce("new"), Uint64::new(1));
+
+        // the previous owner's next proxy does not have the
+        // transferred proxy's address, nor does the one after that.
+        let next_salt = |storage: &dyn Storage, sender: &str| {
+            proxy_salt(storage, "connection-0", "wasm.note", sender, None).unwrap()
+        };
+        let base = salt("connection-0", "wasm.note", "old", None);
+        let first = next_salt(deps.as_ref().storage, "old");
+        assert_ne!(first, base);
+        assert_eq!(first.len(), 64);
+        assert_eq!(
+            next_salt(deps.as_ref().storage, "new"),
+            salt("connection-0", "wasm.note", "new", None)
+        );
+        SALT_GENERATIONS
+            .save(deps.as_mut().storage, base.as_slice(), &2)
+            .unwrap();
+        let second = next_salt(deps.as_ref().storage, "old");
+        assert!(second != base && second != first);
+    }
+
+    /// Voices migrated with a block max gas too low to receive
+    /// packets with may only be migrated to raise it.
+    #[test]
+    fn test_migrate_low_block_max_gas() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        BLOCK_MAX_GAS
+            .save(deps.as_mut().storage, &(ACK_GAS_NEEDED - 1))
+            .unwrap();
+
+        let set_window = MigrateMsg::SetIdempotencyWindow {
+            seconds: Uint64::new(60),
+        };
+        let err = migrate(deps.as_mut(), env.clone(), set_window.clone()).unwrap_err();
+        assert_eq!(err, ContractError::GasLimitsMismatch);
+        let with_update = |block_max_gas| MigrateMsg::WithUpdate {
+            proxy_code_id: Uint64::one(),
+            block_max_gas: Uint64::new(block_max_gas),
+            contract_addr_len: 32,
+        };
+        let err = migrate(deps.as_mut(), env.clone(), with_update(ACK_GAS_NEEDED)).unwrap_err();
+        assert_eq!(err, ContractError::GasLimitsMismatch);
+
+        migrate(deps.as_mut(), env.clone(), with_update(ACK_GAS_NEEDED + 1)).unwrap();
+        migrate(deps.as_mut(), env, set_window).unwrap();
+    }
+
+    /// Proxies are migrated in batches, skipping those that opted
+    /// out, and failures are counted without failing the batch.
+    #[test]
+    fn test_migrate_proxies() {
+        let mut deps = mock_dependencies();
+        BLOCK_MAX_GAS
+            .save(deps.as_mut().storage, &1_000_000)
+            .unwrap();
+        let env = mock_env();
+
+        // the voice is the admin of every proxy but "proxy-d".
+        let voice = env.contract.address.to_string();
+        deps.querier.update_wasm(move |query| match query {
+            WasmQuery::ContractInfo { contract_addr } => {
+                let mut info = ContractInfoResponse::default();
+                info.admin = (contract_addr != "proxy-d").then(|| voice.clone());
+                SystemResult::Ok(ContractResult::Ok(to_json_binary(&info).unwrap()))
+            }
+            _ => unimplemented!(),
+        });
+        for owner in ["a", "b", "c", "d"] {
+            let proxy = Addr::unchecked(format!("proxy-{owner}"));
+            PROXY_TO_SENDER
+                .save(
+                    deps.as_mut().storage,
+                    proxy.clone(),
+                    &SenderInfo {
+                        connection_id: "connection-0".to_string(),
+                        remote_port: "wasm.note".to_string(),
+                        remote_sender: owner.to_string(),
+                        account_id: None,
+                    },
+                )
+                .unwrap();
+            SENDER_TO_PROXY
+                .save(
+                    deps.as_mut().storage,
+                    (
+                        "connection-0".to_string(),
+                        "wasm.note".to_string(),
+                        owner.to_string(),
+                    ),
+                    &proxy,
+                )
+                .unwrap();
+        }
+
+        let mut opt_out = |sender: &str| -> Callback {
+            let res = execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info(env.contract.address.as_str(), &[]),
+                ExecuteMsg::Rx {
+                    connection_id: "connection-0".to_string(),
+                    counterparty_port: "wasm.note".to_string(),
+                    data: to_json_binary(&Packet {
+                        sender: sender.to_string(),
+                        gas_limit: None,
+                        not_before: None,
+                        not_after: None,
+                        nonce: None,
+                        idempotency_key: None,
+                        account_id: None,
+                        msg: Msg::SetMigrationOptOut { opt_out: true },
+                    })
+                    .unwrap(),
+                },
+            )
+            .unwrap();
+            from_json(res.data.unwrap()).unwrap()
+        };
+        assert_eq!(
+            opt_out("nobody"),
+            Callback::Execute(Err("sender has no proxy".to_string()))
+        );
+        assert_eq!(
+            opt_out("b"),
+            Callback::Execute(Ok(ExecutionResponse {
+                executed_by: "proxy-b".to_string(),
+                result: vec![],
+            }))
+        );
+        let opted_out: bool = from_json(
+            query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::MigrationOptOut {
+                    proxy: "proxy-b".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert!(opted_out);
+
+        let migrate_proxies = |code_id: u64| MigrateMsg::MigrateProxies {
+            code_id: Uint64::new(code_id),
+            limit: Some(2),
+        };
+        let migrated = |res: Response| -> Vec<String> {
+            res.messages
+                .into_iter()
+                .map(|submsg| match submsg.msg {
+                    CosmosMsg::Wasm(WasmMsg::Migrate { contract_addr, .. }) => contract_addr,
+                    _ => panic!("expected a migration"),
+                })
+                .collect()
+        };
+        let progress = |deps: Deps| -> ProxyMigration {
+            from_json::<Option<ProxyMigration>>(
+                query(deps, mock_env(), QueryMsg::ProxyMigration).unwrap(),
+            )
+            .unwrap()
+            .unwrap()
+        };
+
+        let res = migrate(deps.as_mut(), env.clone(), migrate_proxies(2)).unwrap();
+        assert_eq!(migrated(res), vec!["proxy-a".to_string()]);
+        // proxies created from the start of the migration use the
+        // new code.
+        assert_eq!(PROXY_CODE_ID.load(deps.as_ref().storage).unwrap(), 2);
+        reply(
+            deps.as_mut(),
+            env.clone(),
+            Reply {
+                id: REPLY_MIGRATE_PROXY,
+                result: SubMsgResult::Err("no admin".to_string()),
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            progress(deps.as_ref()),
+            ProxyMigration {
+                code_id: Uint64::new(2),
+                start_after: Some(Addr::unchecked("proxy-b")),
+                migrated: 0,
+                skipped: 1,
+                not_admin: 0,
+                failed: 1,
+                done: false,
+            }
+        );
+
+        let res = migrate(deps.as_mut(), env.clone(), migrate_proxies(2)).unwrap();
+        assert_eq!(migrated(res), vec!["proxy-c".to_string()]);
+        // migrations are counted once they succeed.
+        assert_eq!(progress(deps.as_ref()).migrated, 0);
+        reply(
+            deps.as_mut(),
+            env.clone(),
+            Reply {
+                id: REPLY_MIGRATE_PROXY,
+                result: SubMsgResult::Ok(SubMsgResponse {
+                    events: vec![],
+                    data: None,
+                }),
+            },
+        )
+        .unwrap();
+        let migration = progress(deps.as_ref());
+        assert_eq!((migration.migrated, migration.not_admin), (1, 1));
+        assert!(!migration.done);
+
+        let res = migrate(deps.as_mut(), env.clone(), migrate_proxies(2)).unwrap();
+        assert!(migrated(res).is_empty());
+        assert!(progress(deps.as_ref()).done);
+
+        // a new code ID starts over.
+        let res = migrate(deps.as_mut(), env, migrate_proxies(3)).unwrap();
+        assert_eq!(migrated(res), vec!["proxy-a".to_string()]);
+        assert_eq!(
+            progress(deps.as_ref()).start_after,
+            Some(Addr::unchecked("proxy-b"))
+        );
+    }
+
+    /// Requests containing a message the message policy does not
+    /// allow are rejected with the index of the message.
+    #[test]
+    fn test_message_policy() {
+        let mut deps = mock_dependencies();
+        BLOCK_MAX_GAS
+            .save(deps.as_mut().storage, &1_000_000)
+            .unwrap();
+        let env = mock_env();
+
+        SENDER_TO_PROXY
+            .save(
+                deps.as_mut().storage,
+                (
+                    "connection-0".to_string(),
+                    "wasm.note".to_string(),
+                    "sender".to_string(),
+                ),
+                &Addr::unchecked("proxy"),
+            )
+            .unwrap();
+        let policy = MessagePolicy {
+            msg_types: Some(Filter::Deny(vec![
+                "gov".to_string(),
+                "wasm.migrate".to_string(),
+                "wasm.instantiate2".to_string(),
+            ])),
+            contracts: Some(Filter::Allow(vec!["allowed".to_string()])),
+            stargate_type_urls: Some(Filter::Allow(vec![
+                "/cosmos.bank.v1beta1.MsgSend".to_string()
+            ])),
+        };
+        migrate(
+            deps.as_mut(),
+            env.clone(),
+            MigrateMsg::SetMessagePolicy {
+                policy: Some(policy.clone()),
+            },
+        )
+        .unwrap();
+        let queried: Option<MessagePolicy> =
+            from_json(query(deps.as_ref(), env.clone(), QueryMsg::MessagePolicy).unwrap()).unwrap();
+        assert_eq!(queried, Some(policy));
+
+        let mut rx = |msgs: Vec<CosmosMsg>| {
+            execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info(env.contract.address.as_str(), &[]),
+                ExecuteMsg::Rx {
+                    connection_id: "connection-0".to_string(),
+                    counterparty_port: "wasm.note".to_string(),
+                    data: to_json_binary(&Packet {
+                        sender: "sender".to_string(),
+                        gas_limit: None,
+                        not_before: None,
+                        not_after: None,
+                        nonce: None,
+                        idempotency_key: None,
+                        account_id: None,
+                        msg: Msg::Execute { msgs, mode: None },
+                    })
+                    .unwrap(),
+                },
+            )
+            .unwrap()
+        };
+        let wasm_execute = |contract: &str| -> CosmosMsg {
+            WasmMsg::Execute {
+                contract_addr: contract.to_string(),
+                msg: Binary::default(),
+                funds: vec![],
+            }
+            .into()
+        };
+        let stargate = |type_url: &str| CosmosMsg::Stargate {
+            type_url: type_url.to_string(),
+            value: Binary::default(),
+        };
+        let bank_send: CosmosMsg = BankMsg::Send {
+            to_address: "someone".to_string(),
+            amount: vec![coin(1, "ucoin")],
+        }
+        .into();
+
+        for (msgs, index, error) in [
+            (
+                vec![
+                    bank_send.clone(),
+                    WasmMsg::Migrate {
+                        contract_addr: "allowed".to_string(),
+                        new_code_id: 2,
+                        msg: Binary::default(),
+                    }
+                    .into(),
+                ],
+                1,
+                "message type (wasm.migrate) is not allowed",
+            ),
+            (
+                vec![wasm_execute("allowed"), wasm_execute("other")],
+                1,
+                "contract (other) is not allowed",
+            ),
+            (
+                vec![WasmMsg::Instantiate2 {
+                    admin: None,
+                    code_id: 1,
+                    label: "label".to_string(),
+                    msg: Binary::default(),
+                    funds: vec![],
+                    salt: Binary::default(),
+                }
+                .into()],
+                0,
+                "message type (wasm.instantiate2) is not allowed",
+            ),
+            (
+                vec![GovMsg::VoteWeighted {
+                    proposal_id: 1,
+                    options: vec![WeightedVoteOption {
+                        option: VoteOption::Yes,
+                        weight: Decimal::one(),
+                    }],
+                }
+                .into()],
+                0,
+                "message type (gov.vote_weighted) is not allowed",
+            ),
+            (
+                vec![WasmMsg::Instantiate {
+                    admin: Some("other".to_string()),
+                    code_id: 1,
+                    msg: Binary::default(),
+                    funds: vec![],
+                    label: "label".to_string(),
+                }
+                .into()],
+                0,
+                "contract (other) is not allowed",
+            ),
+            (
+                vec![stargate("/cosmos.authz.v1beta1.MsgExec")],
+                0,
+                "type URL (/cosmos.authz.v1beta1.MsgExec) is not allowed",
+            ),
+        ] {
+            let res = rx(msgs);
+            assert!(res.messages.is_empty());
+            assert_eq!(
+                from_json::<Callback>(res.data.unwrap()).unwrap(),
+                Callback::Rejected(ErrorResponse {
+                    message_index: Uint64::new(index),
+                    error: error.to_string(),
+                })
+            );
+        }
+
+        let res = rx(vec![
+            bank_send,
+            wasm_execute("allowed"),
+            stargate("/cosmos.bank.v1beta1.MsgSend"),
+        ]);
+        assert_eq!(res.messages.len(), 1);
+    }
 }
@@ -30,4 +30,16 @@ pub enum ContractError {
 
     #[error("Contract address length can't be greater than 32")]
     ContractAddrLenCantBeGreaterThan32,
+
+    #[error("counterparty can't be an alias of itself")]
+    AliasCycle,
+
+    #[error("counterparty already has proxies and can't be made an alias")]
+    CounterpartyHasProxies,
+
+    #[error("sender has no proxy")]
+    NoProxy,
+
+    #[error("new owner already has a proxy")]
+    NewOwnerHasProxy,
 }
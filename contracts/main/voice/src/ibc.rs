@@ -9,28 +9,48 @@ use cosmwasm_std::{
 
 use cw_utils::{parse_reply_execute_data, MsgExecuteContractResponse};
 use polytone::{
-    ack::{ack_execute_fail, ack_fail},
-    callbacks::Callback,
+    ack::{
+        ack_conditional_execute, ack_execute_best_effort, ack_execute_fail, ack_fail,
+        ack_gas_limit_exceeded, ack_templated_execute,
+    },
+    callbacks::{
+        Callback, ConditionalExecutionResponse, ExecutionResponse, TemplatedExecutionResponse,
+        OUT_OF_GAS_ERROR,
+    },
     handshake::voice,
+    ibc::Packet,
 };
 
 use crate::{
+    contract::record_idempotency_key,
     error::ContractError,
     msg::ExecuteMsg,
-    state::{BLOCK_MAX_GAS, CHANNEL_TO_CONNECTION},
+    state::{
+        BEST_EFFORT, BLOCK_MAX_GAS, CHANNEL_TO_CONNECTION, PENDING_IDEMPOTENCY_KEY,
+        PROXY_MIGRATION, QUERY_RESULTS, REQUESTED_GAS_LIMIT,
+    },
 };
 
 const REPLY_ACK: u64 = 0;
 pub(crate) const REPLY_FORWARD_DATA: u64 = 1;
+pub(crate) const REPLY_BEST_EFFORT: u64 = 2;
+pub(crate) const REPLY_CONDITIONAL_EXECUTE: u64 = 3;
+pub(crate) const REPLY_TEMPLATED_EXECUTE: u64 = 4;
+pub(crate) const REPLY_MIGRATE_PROXY: u64 = 5;
 
 /// The amount of gas that needs to be reserved for the reply method
 /// to return an ACK for a submessage that runs out of gas.
 ///
-/// Use `TestVoiceOutOfGas` in `tests/simtests/functionality_test.go`
-/// to tune this. Note that it is best to give this a lot of headroom
-/// as gas usage is non-deterministic in the SDK and a limit tuned
-/// within 50 gas is liable to fail non-deterministically.
-pub(crate) const ACK_GAS_NEEDED: u64 = 101_000;
+/// Use `TestVoiceOutOfGas` and `TestVoiceGasLimitExceeded` in
+/// `tests/simtests/functionality_test.go` to tune this. Note that it
+/// is best to give this a lot of headroom as gas usage is
+/// non-deterministic in the SDK and a limit tuned within 50 gas is
+/// liable to fail non-deterministically. The reply reads and removes
+/// `REQUESTED_GAS_LIMIT`, which costs about 2,000 gas at the SDK's
+/// default storage gas costs, and records a successful request's
+/// idempotency key and prunes expired ones, which costs about 15,000
+/// more.
+pub(crate) const ACK_GAS_NEEDED: u64 = 120_000;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn ibc_channel_open(
@@ -84,6 +104,21 @@ pub fn ibc_packet_receive(
     let connection_id = CHANNEL_TO_CONNECTION
         .load(deps.storage, msg.packet.dest.channel_id.clone())
         .expect("handshake sets mapping");
+    let max_gas = BLOCK_MAX_GAS
+        .load(deps.storage)
+        .expect("set during instantiation")
+        - ACK_GAS_NEEDED;
+    // packets that fail to parse are rejected in `Rx`, where the
+    // error can be returned in the ACK.
+    let requested_gas_limit = from_json::<Packet>(&msg.packet.data)
+        .ok()
+        .and_then(|packet| packet.gas_limit)
+        .map(|limit| limit.u64().min(max_gas));
+    if let Some(limit) = requested_gas_limit {
+        REQUESTED_GAS_LIMIT
+            .save(deps.storage, &limit)
+            .expect("storage is writable");
+    }
     Ok(IbcReceiveResponse::default()
         .add_attribute("method", "ibc_packet_receive")
         .add_attribute("connection_id", connection_id.as_str())
@@ -103,35 +138,52 @@ pub fn ibc_packet_receive(
                 funds: vec![],
             }
             .into(),
-            gas_limit: Some(
-                BLOCK_MAX_GAS
-                    .load(deps.storage)
-                    .expect("set during instantiation")
-                    - ACK_GAS_NEEDED,
-            ),
+            gas_limit: Some(requested_gas_limit.unwrap_or(max_gas)),
             reply_on: cosmwasm_std::ReplyOn::Always,
         }))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn reply(_deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
     match msg.id {
-        REPLY_ACK => Ok(match msg.result {
-            SubMsgResult::Err(e) => Response::default()
-                .add_attribute("ack_error", &e)
-                .set_data(ack_fail(e)),
-            SubMsgResult::Ok(_) => {
-                let data = parse_reply_execute_data(msg.clone())
-                    .expect("execution succeeded")
-                    .data
-                    .expect("reply_forward_data sets data");
-                match from_json::<Callback>(&data) {
-                    Ok(_) => Response::default().set_data(data),
-                    Err(e) => Response::default()
-                        .set_data(ack_fail(format!("unmarshalling callback data: ({e})"))),
+        REPLY_ACK => {
+            let requested_gas_limit = REQUESTED_GAS_LIMIT.may_load(deps.storage)?;
+            REQUESTED_GAS_LIMIT.remove(deps.storage);
+            Ok(match msg.result {
+                SubMsgResult::Err(e) => {
+                    let response = Response::default().add_attribute("ack_error", &e);
+                    match requested_gas_limit {
+                        Some(limit) if e == OUT_OF_GAS_ERROR => response
+                            .add_attribute("gas_limit", limit.to_string())
+                            .set_data(ack_gas_limit_exceeded(limit)),
+                        _ => response.set_data(ack_fail(e)),
+                    }
                 }
-            }
-        }),
+                SubMsgResult::Ok(_) => {
+                    let data = parse_reply_execute_data(msg.clone())
+                        .expect("execution succeeded")
+                        .data
+                        .expect("reply_forward_data sets data");
+                    let idempotency_key = PENDING_IDEMPOTENCY_KEY.may_load(deps.storage)?;
+                    PENDING_IDEMPOTENCY_KEY.remove(deps.storage);
+                    match from_json::<Callback>(&data) {
+                        Ok(callback) => {
+                            // a request's idempotency key is only used
+                            // if it succeeds, so that failures may be
+                            // retried with the same key.
+                            if let Some((proxy, key)) = idempotency_key {
+                                if callback.is_success() {
+                                    record_idempotency_key(deps.storage, &env, &proxy, &key)?;
+                                }
+                            }
+                            Response::default().set_data(data)
+                        }
+                        Err(e) => Response::default()
+                            .set_data(ack_fail(format!("unmarshalling callback data: ({e})"))),
+                    }
+                }
+            })
+        }
         REPLY_FORWARD_DATA => match msg.result {
             // Executing the requested messages succeeded. Because more
             // than one message can be dispatched (instantiate proxy &
@@ -152,10 +204,87 @@ pub fn reply(_deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, Contract
                 .add_attribute("method", "reply_forward_data_error")
                 .set_data(ack_execute_fail(err))),
         },
+        REPLY_BEST_EFFORT => {
+            // One of the messages being executed in best-effort mode
+            // completed. Record its result and, if it was the last
+            // one, return all of the results as the ack.
+            let result =
+                proxy_execution_result(msg)?.map(|ExecutionResponse { result, .. }| {
+                    result
+                        .into_iter()
+                        .next()
+                        .expect("proxy executed one message")
+                });
+            let mut execution = BEST_EFFORT.load(deps.storage)?;
+            execution.results.push(result);
+            let response = Response::default().add_attribute("method", "reply_best_effort");
+            if execution.results.len() as u64 == execution.expected {
+                BEST_EFFORT.remove(deps.storage);
+                Ok(response.set_data(ack_execute_best_effort(
+                    execution.results,
+                    execution.executed_by,
+                )))
+            } else {
+                BEST_EFFORT.save(deps.storage, &execution)?;
+                Ok(response)
+            }
+        }
+        REPLY_CONDITIONAL_EXECUTE => {
+            let query = QUERY_RESULTS.load(deps.storage)?;
+            QUERY_RESULTS.remove(deps.storage);
+            Ok(Response::default()
+                .add_attribute("method", "reply_conditional_execute")
+                .set_data(ack_conditional_execute(ConditionalExecutionResponse {
+                    query: Ok(query),
+                    failed_condition: None,
+                    execute: Some(proxy_execution_result(msg)?),
+                })))
+        }
+        REPLY_TEMPLATED_EXECUTE => {
+            let query = QUERY_RESULTS.load(deps.storage)?;
+            QUERY_RESULTS.remove(deps.storage);
+            Ok(Response::default()
+                .add_attribute("method", "reply_templated_execute")
+                .set_data(ack_templated_execute(TemplatedExecutionResponse {
+                    query: Ok(query),
+                    failed_template: None,
+                    execute: Some(proxy_execution_result(msg)?),
+                })))
+        }
+        REPLY_MIGRATE_PROXY => {
+            let mut migration = PROXY_MIGRATION.load(deps.storage)?;
+            let response = Response::default().add_attribute("method", "reply_migrate_proxy");
+            let response = match msg.result {
+                SubMsgResult::Ok(_) => {
+                    migration.migrated += 1;
+                    response
+                }
+                SubMsgResult::Err(error) => {
+                    migration.failed += 1;
+                    response.add_attribute("migrate_error", error)
+                }
+            };
+            PROXY_MIGRATION.save(deps.storage, &migration)?;
+            Ok(response)
+        }
         _ => unreachable!("unknown reply ID"),
     }
 }
 
+/// Returns the result of a proxy executing messages from its reply.
+fn proxy_execution_result(msg: Reply) -> Result<Result<ExecutionResponse, String>, ContractError> {
+    match msg.result {
+        SubMsgResult::Ok(_) => {
+            let MsgExecuteContractResponse { data } = parse_reply_execute_data(msg)?;
+            match from_json(data.expect("proxy will always set data"))? {
+                Callback::Execute(result) => Ok(result),
+                _ => unreachable!("proxy returns an execution response"),
+            }
+        }
+        SubMsgResult::Err(err) => Ok(Err(err)),
+    }
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn ibc_packet_ack(
     _deps: DepsMut,
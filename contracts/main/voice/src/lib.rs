@@ -2,6 +2,7 @@ pub mod contract;
 pub mod error;
 pub mod ibc;
 pub mod msg;
+pub mod policy;
 pub mod state;
 
 #[cfg(test)]
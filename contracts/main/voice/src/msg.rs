@@ -1,7 +1,8 @@
 use cosmwasm_schema::{cw_serde, QueryResponses};
 use cosmwasm_std::{Binary, Uint64};
 
-use crate::state::SenderInfo;
+use crate::policy::MessagePolicy;
+use crate::state::{Counterparty, ProxyMigration, SenderInfo};
 
 #[cw_serde]
 pub struct InstantiateMsg {
@@ -12,6 +13,9 @@ pub struct InstantiateMsg {
     /// The contract address length used by the chain. Defaults to 32. Some
     /// chains use other lengths, such as Injective which uses 20.
     pub contract_addr_len: Option<u8>,
+    /// The number of seconds an idempotency key may not be reused
+    /// for after it is used. Defaults to thirty days.
+    pub idempotency_window_seconds: Option<Uint64>,
 }
 
 #[cw_serde]
@@ -43,14 +47,70 @@ pub enum QueryMsg {
     /// Queries the configured contract address length.
     #[returns(u8)]
     ContractAddrLen,
-    /// Queries the sender information for a given proxy.
+    /// Queries the number of seconds an idempotency key may not be
+    /// reused for after it is used.
+    #[returns(Uint64)]
+    IdempotencyWindow,
+    /// Queries the sender information for a given proxy. If the
+    /// proxy's counterparty has been aliased, the most recent alias
+    /// is returned as its connection and port.
     #[returns(SenderInfo)]
     SenderInfoForProxy { proxy: String },
+    /// Queries the proxy of `sender` from the note on
+    /// `(connection_id, remote_port)` with `account_id`, or `None` if
+    /// the sender has no such proxy. If `account_id` is `None`,
+    /// queries the sender's default proxy.
+    #[returns(Option<String>)]
+    ProxyForSender {
+        connection_id: String,
+        remote_port: String,
+        sender: String,
+        account_id: Option<String>,
+    },
+    /// Queries the nonce the next packet from `sender` from the note
+    /// on `(connection_id, remote_port)` must have if it sets one.
+    #[returns(Uint64)]
+    NextNonce {
+        connection_id: String,
+        remote_port: String,
+        sender: String,
+    },
+    /// Lists proxies and their sender information ordered by proxy
+    /// address, optionally only those of senders on `connection_id`.
+    /// Proxies created before the voice indexed proxies by connection
+    /// are only listed for a connection once they have been indexed
+    /// with `MigrateMsg::IndexProxies`.
+    #[returns(Vec<ProxyResponse>)]
+    Proxies {
+        connection_id: Option<String>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Queries the progress of the latest proxy migration, or `None`
+    /// if proxies have never been migrated.
+    #[returns(Option<ProxyMigration>)]
+    ProxyMigration,
+    /// Queries whether `proxy`'s owner has opted out of proxy
+    /// migrations.
+    #[returns(bool)]
+    MigrationOptOut { proxy: String },
+    /// Queries the policy messages executed by proxies must satisfy,
+    /// or `None` if every message is allowed.
+    #[returns(Option<MessagePolicy>)]
+    MessagePolicy,
+}
+
+#[cw_serde]
+pub struct ProxyResponse {
+    pub proxy: String,
+    pub sender_info: SenderInfo,
 }
 
 #[cw_serde]
 pub enum MigrateMsg {
-    /// Updates the module's configuration.
+    /// Updates the module's configuration. Voices whose block max gas
+    /// is too low for this version must migrate with this to raise
+    /// it, as other migrations are rejected.
     WithUpdate {
         /// Code ID to use for instantiating proxy contracts.
         proxy_code_id: Uint64,
@@ -59,4 +119,45 @@ pub enum MigrateMsg {
         /// The contract address length used by the chain.
         contract_addr_len: u8,
     },
+    /// Sets the number of seconds an idempotency key may not be reused
+    /// for after it is used. Only applies to keys used after it is
+    /// set.
+    SetIdempotencyWindow { seconds: Uint64 },
+    /// Makes `alias` an alias of `original`, so senders from `alias`
+    /// control the proxies of the same senders from `original`. If
+    /// `original` is none, removes the alias.
+    ///
+    /// This allows accounts to be kept when a note is moved to a new
+    /// connection, for example because the light client of its
+    /// previous connection expired. `alias` must not have any
+    /// proxies of its own.
+    AliasCounterparty {
+        alias: Counterparty,
+        original: Option<Counterparty>,
+    },
+    /// Migrates up to `limit` proxies, defaulting to 10 and at most
+    /// 30, to `code_id`, skipping those whose owners have opted out
+    /// with `Msg::SetMigrationOptOut`. Call repeatedly with the same
+    /// `code_id` until `QueryMsg::ProxyMigration` is done; a
+    /// different `code_id` starts a new migration. Proxies the voice
+    /// is not the admin of, such as those created before it was
+    /// made their admin, can't be migrated and are skipped and
+    /// counted separately. Starting a migration sets the code ID new
+    /// proxies are created with to `code_id`.
+    MigrateProxies { code_id: Uint64, limit: Option<u32> },
+    /// Indexes up to `limit` proxies after `start_after`, ordered by
+    /// address, by the connection of their senders, so that
+    /// `QueryMsg::Proxies` lists them for that connection. Only
+    /// needed for proxies created before the voice indexed them, and
+    /// called repeatedly with the last proxy indexed, which is
+    /// returned as the `last_proxy` attribute, until none are.
+    IndexProxies {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Sets the policy messages executed by proxies must satisfy.
+    /// Requests containing a message the policy does not allow are
+    /// not executed and get a `Callback::Rejected`. If `policy` is
+    /// none, every message is allowed.
+    SetMessagePolicy { policy: Option<MessagePolicy> },
 }
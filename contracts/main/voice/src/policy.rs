@@ -0,0 +1,176 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    BankMsg, CosmosMsg, DistributionMsg, Empty, GovMsg, IbcMsg, StakingMsg, StdResult, Storage,
+    WasmMsg,
+};
+
+use crate::state::MESSAGE_POLICY;
+
+/// Restricts the messages proxies may execute. A message must pass
+/// every filter that is set. See `MigrateMsg::SetMessagePolicy`.
+#[cw_serde]
+#[derive(Default)]
+pub struct MessagePolicy {
+    /// Filters messages by type. A message's type is its `CosmosMsg`
+    /// variant, for example `"wasm"`, and a filter may also name a
+    /// variant of that, for example `"wasm.migrate"`. Messages of a
+    /// type the voice does not recognize are never allowed while this
+    /// is set.
+    pub msg_types: Option<Filter>,
+    /// Filters Wasm messages by the address of the contract they
+    /// target, and by the admin they give a contract when
+    /// instantiating it or updating its admin.
+    pub contracts: Option<Filter>,
+    /// Filters Stargate messages by their type URL.
+    pub stargate_type_urls: Option<Filter>,
+}
+
+#[cw_serde]
+pub enum Filter {
+    /// Only the listed values are allowed.
+    Allow(Vec<String>),
+    /// Every value except the listed ones is allowed.
+    Deny(Vec<String>),
+}
+
+impl Filter {
+    /// True if the filter allows a value known by any of `values`.
+    pub fn allows(&self, values: &[&str]) -> bool {
+        let listed = |list: &[String]| list.iter().any(|item| values.contains(&item.as_str()));
+        match self {
+            Filter::Allow(list) => listed(list),
+            Filter::Deny(list) => !listed(list),
+        }
+    }
+}
+
+impl MessagePolicy {
+    /// Checks `msg` against the policy, returning an error describing
+    /// why it is not allowed if it is not.
+    pub fn check(&self, msg: &CosmosMsg<Empty>) -> Result<(), String> {
+        if let Some(filter) = &self.msg_types {
+            let Some((kind, variant)) = msg_type(msg) else {
+                return Err("message type (unknown) is not allowed".to_string());
+            };
+            let full = variant.map(|variant| format!("{kind}.{variant}"));
+            let values: Vec<&str> = std::iter::once(kind).chain(full.as_deref()).collect();
+            if !filter.allows(&values) {
+                return Err(format!(
+                    "message type ({}) is not allowed",
+                    values.last().unwrap()
+                ));
+            }
+        }
+        match msg {
+            CosmosMsg::Wasm(msg) => {
+                if let Some(filter) = &self.contracts {
+                    let addresses = match msg {
+                        WasmMsg::Execute { contract_addr, .. }
+                        | WasmMsg::Migrate { contract_addr, .. }
+                        | WasmMsg::ClearAdmin { contract_addr } => vec![contract_addr],
+                        WasmMsg::UpdateAdmin {
+                            contract_addr,
+                            admin,
+                        } => vec![contract_addr, admin],
+                        WasmMsg::Instantiate { admin, .. }
+                        | WasmMsg::Instantiate2 { admin, .. } => admin.iter().collect(),
+                        _ => return Err("message type (unknown) is not allowed".to_string()),
+                    };
+                    if let Some(address) = addresses
+                        .into_iter()
+                        .find(|address| !filter.allows(&[address]))
+                    {
+                        return Err(format!("contract ({address}) is not allowed"));
+                    }
+                }
+            }
+            CosmosMsg::Stargate { type_url, .. } => {
+                if let Some(filter) = &self.stargate_type_urls {
+                    if !filter.allows(&[type_url]) {
+                        return Err(format!("type URL ({type_url}) is not allowed"));
+                    }
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+/// Returns the index of the first of `msgs` the message policy does
+/// not allow, and why, if there is one.
+pub(crate) fn check_policy(
+    storage: &dyn Storage,
+    msgs: &[CosmosMsg<Empty>],
+) -> StdResult<Option<(usize, String)>> {
+    let Some(policy) = MESSAGE_POLICY.may_load(storage)? else {
+        return Ok(None);
+    };
+    Ok(msgs
+        .iter()
+        .enumerate()
+        .find_map(|(index, msg)| policy.check(msg).err().map(|error| (index, error))))
+}
+
+/// The `CosmosMsg` variant of `msg` and the variant of that, as named
+/// in JSON, or `None` if the voice does not recognize the message.
+fn msg_type(msg: &CosmosMsg<Empty>) -> Option<(&'static str, Option<&'static str>)> {
+    Some(match msg {
+        CosmosMsg::Bank(msg) => (
+            "bank",
+            Some(match msg {
+                BankMsg::Send { .. } => "send",
+                BankMsg::Burn { .. } => "burn",
+                _ => return None,
+            }),
+        ),
+        CosmosMsg::Custom(_) => ("custom", None),
+        CosmosMsg::Staking(msg) => (
+            "staking",
+            Some(match msg {
+                StakingMsg::Delegate { .. } => "delegate",
+                StakingMsg::Undelegate { .. } => "undelegate",
+                StakingMsg::Redelegate { .. } => "redelegate",
+                _ => return None,
+            }),
+        ),
+        CosmosMsg::Distribution(msg) => (
+            "distribution",
+            Some(match msg {
+                DistributionMsg::SetWithdrawAddress { .. } => "set_withdraw_address",
+                DistributionMsg::WithdrawDelegatorReward { .. } => "withdraw_delegator_reward",
+                _ => return None,
+            }),
+        ),
+        CosmosMsg::Stargate { .. } => ("stargate", None),
+        CosmosMsg::Ibc(msg) => (
+            "ibc",
+            Some(match msg {
+                IbcMsg::Transfer { .. } => "transfer",
+                IbcMsg::SendPacket { .. } => "send_packet",
+                IbcMsg::CloseChannel { .. } => "close_channel",
+                _ => return None,
+            }),
+        ),
+        CosmosMsg::Wasm(msg) => (
+            "wasm",
+            Some(match msg {
+                WasmMsg::Execute { .. } => "execute",
+                WasmMsg::Instantiate { .. } => "instantiate",
+                WasmMsg::Instantiate2 { .. } => "instantiate2",
+                WasmMsg::Migrate { .. } => "migrate",
+                WasmMsg::UpdateAdmin { .. } => "update_admin",
+                WasmMsg::ClearAdmin { .. } => "clear_admin",
+                _ => return None,
+            }),
+        ),
+        CosmosMsg::Gov(msg) => (
+            "gov",
+            Some(match msg {
+                GovMsg::Vote { .. } => "vote",
+                GovMsg::VoteWeighted { .. } => "vote_weighted",
+            }),
+        ),
+        _ => return None,
+    })
+}
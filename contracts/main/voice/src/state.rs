@@ -1,16 +1,122 @@
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::Addr;
+use cosmwasm_std::{Addr, Binary, Empty, StdResult, Storage, SubMsgResponse, Timestamp, Uint64};
 use cw_storage_plus::{Item, Map};
 
+use crate::policy::MessagePolicy;
+
 /// (connection_id, remote_port, remote_sender) -> proxy
 pub(crate) const SENDER_TO_PROXY: Map<(String, String, String), Addr> = Map::new("c2p");
 
-/// proxy -> { connection_id, remote_port, remote_sender }
+/// (connection_id, remote_port, (remote_sender, account_id)) -> proxy
+///
+/// Proxies of senders with an account ID. Senders without one use
+/// `SENDER_TO_PROXY`.
+pub(crate) const SENDER_ACCOUNT_TO_PROXY: Map<(String, String, (String, String)), Addr> =
+    Map::new("sa2p");
+
+/// proxy -> { connection_id, remote_port, remote_sender, account_id }
 pub(crate) const PROXY_TO_SENDER: Map<Addr, SenderInfo> = Map::new("p2c");
 
+/// (connection_id, proxy) -> (), an index of `PROXY_TO_SENDER` by the
+/// connection proxies' senders are stored under. Proxies created
+/// before this index was added are added by
+/// `MigrateMsg::IndexProxies`.
+pub(crate) const CONNECTION_PROXIES: Map<(String, Addr), Empty> = Map::new("cp");
+
+/// proxy -> (), for each proxy whose owner has opted out of proxy
+/// migrations.
+pub(crate) const MIGRATION_OPT_OUTS: Map<Addr, Empty> = Map::new("mo");
+
+/// Progress of the latest migration of proxies to new code. See
+/// `MigrateMsg::MigrateProxies`.
+pub(crate) const PROXY_MIGRATION: Item<ProxyMigration> = Item::new("pm");
+
+#[cw_serde]
+pub struct ProxyMigration {
+    /// The code ID proxies are being migrated to. New proxies are
+    /// created with it from the start of the migration, so proxies
+    /// created during it are on the new code whether or not the
+    /// migration passes over them.
+    pub code_id: Uint64,
+    /// The last proxy handled, in order of address. The next batch
+    /// starts after it.
+    pub start_after: Option<Addr>,
+    /// The number of proxies that migrated successfully.
+    pub migrated: u64,
+    /// The number of proxies skipped because their owner opted out.
+    pub skipped: u64,
+    /// The number of proxies skipped because the voice is not their
+    /// admin, for example because they were created before it was.
+    pub not_admin: u64,
+    /// The number of proxies that failed to migrate.
+    pub failed: u64,
+    /// Set once every proxy has been handled.
+    pub done: bool,
+}
+
+/// The policy messages executed by proxies must satisfy. Holds no
+/// value if none is set, in which case every message is allowed.
+pub(crate) const MESSAGE_POLICY: Item<MessagePolicy> = Item::new("mpol");
+
+/// (connection_id, remote_port, remote_sender) -> nonce of the last
+/// packet handled from the sender that had one. Keyed by the original
+/// counterparty if it has been aliased.
+pub(crate) const NONCES: Map<(String, String, String), u64> = Map::new("nonces");
+
+/// salt -> number of times a proxy with the salt has been
+/// transferred away. Proxies are created with the salt hashed with
+/// this once it has one, so that a sender whose proxy was
+/// transferred gets a new address for its next one.
+pub(crate) const SALT_GENERATIONS: Map<&[u8], u64> = Map::new("sg");
+
+/// (proxy, idempotency_key) -> time after which the key may be used
+/// again by the proxy.
+pub(crate) const IDEMPOTENCY_KEYS: Map<(&Addr, &str), Timestamp> = Map::new("ik");
+
+/// (expiry_nanos, proxy, idempotency_key) -> (), an index of
+/// `IDEMPOTENCY_KEYS` by when they expire so that expired keys can be
+/// removed oldest first.
+pub(crate) const IDEMPOTENCY_EXPIRIES: Map<(u64, &Addr, &str), Empty> = Map::new("ike");
+
+/// The proxy and idempotency key of the packet being handled, held
+/// from `Rx` until the reply to it, which records the key if the
+/// packet's messages were executed successfully.
+pub(crate) const PENDING_IDEMPOTENCY_KEY: Item<(Addr, String)> = Item::new("pik");
+
+/// The number of seconds an idempotency key is kept for after it is
+/// used.
+pub(crate) const IDEMPOTENCY_WINDOW: Item<u64> = Item::new("iw");
+
 /// (channel_id) -> connection_id
 pub(crate) const CHANNEL_TO_CONNECTION: Map<String, String> = Map::new("c2c");
 
+/// (connection_id, remote_port) -> (connection_id, remote_port)
+///
+/// Maps a counterparty to the counterparty it is an alias of. Senders
+/// from an alias control the proxies of the same senders from the
+/// original, and state is stored under the original.
+pub(crate) const COUNTERPARTY_ALIASES: Map<(String, String), (String, String)> = Map::new("ca");
+
+/// (connection_id, remote_port) -> (connection_id, remote_port)
+///
+/// Maps an original counterparty to the most recent alias of it, so
+/// that queries show the counterparty senders currently arrive from.
+pub(crate) const COUNTERPARTY_REPLACEMENTS: Map<(String, String), (String, String)> =
+    Map::new("cr");
+
+/// Results collected while executing messages in best-effort
+/// mode. Has no value when no best-effort execution is in progress.
+pub(crate) const BEST_EFFORT: Item<BestEffortExecution> = Item::new("be");
+
+/// Query results of a conditional or templated execution whose
+/// messages are being executed. Has no value when none are.
+pub(crate) const QUERY_RESULTS: Item<Vec<Binary>> = Item::new("cqr");
+
+/// The gas limit requested by the sender of the packet being handled,
+/// clamped to the block maximum. Has no value if no packet is being
+/// handled or none was requested.
+pub(crate) const REQUESTED_GAS_LIMIT: Item<u64> = Item::new("rgl");
+
 /// Code ID of the proxy contract being used.
 pub(crate) const PROXY_CODE_ID: Item<u64> = Item::new("pci");
 
@@ -25,4 +131,39 @@ pub struct SenderInfo {
     pub connection_id: String,
     pub remote_port: String,
     pub remote_sender: String,
+    /// The ID of the sender's account this proxy is, or `None` if it
+    /// is the sender's default proxy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<String>,
+}
+
+#[cw_serde]
+pub(crate) struct BestEffortExecution {
+    /// The proxy executing the messages.
+    pub executed_by: String,
+    /// The number of messages dispatched.
+    pub expected: u64,
+    /// The result of each message that has completed so far.
+    pub results: Vec<Result<SubMsgResponse, String>>,
+}
+
+#[cw_serde]
+pub struct Counterparty {
+    pub connection_id: String,
+    pub remote_port: String,
+}
+
+/// Returns the counterparty whose proxies senders from
+/// `(connection_id, remote_port)` control.
+pub(crate) fn resolve_counterparty(
+    storage: &dyn Storage,
+    connection_id: String,
+    remote_port: String,
+) -> StdResult<(String, String)> {
+    let mut counterparty = (connection_id, remote_port);
+    // cycles are rejected when aliases are created.
+    while let Some(original) = COUNTERPARTY_ALIASES.may_load(storage, counterparty.clone())? {
+        counterparty = original;
+    }
+    Ok(counterparty)
 }
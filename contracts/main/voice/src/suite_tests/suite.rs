@@ -32,8 +32,9 @@ impl Default for SuiteBuilder {
         Self {
             instantiate: InstantiateMsg {
                 proxy_code_id: Uint64::new(9999),
-                block_max_gas: Uint64::new(110_000),
+                block_max_gas: Uint64::new(130_000),
                 contract_addr_len: None,
+                idempotency_window_seconds: None,
             },
         }
     }
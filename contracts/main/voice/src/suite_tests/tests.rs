@@ -7,20 +7,20 @@ use super::suite::SuiteBuilder;
 #[test]
 fn test_update() {
     let mut suite = SuiteBuilder::default()
-        .with_block_max_gas(Uint64::new(111_000))
+        .with_block_max_gas(Uint64::new(131_000))
         .build();
 
-    suite.assert_block_max_gas(111_000);
+    suite.assert_block_max_gas(131_000);
     suite.assert_proxy_code(9999);
 
     let proxy_code_new = suite.store_voice_contract();
 
     suite
-        .update(Addr::unchecked(CREATOR_ADDR), proxy_code_new, 111_000, 32)
+        .update(Addr::unchecked(CREATOR_ADDR), proxy_code_new, 131_000, 32)
         .unwrap();
 
     // assert that both fields updated succesfully
-    suite.assert_block_max_gas(111_000);
+    suite.assert_block_max_gas(131_000);
     suite.assert_proxy_code(proxy_code_new);
 }
 
@@ -28,13 +28,13 @@ fn test_update() {
 fn test_query_block_max_gas() {
     let mut suite = SuiteBuilder::default().build();
 
-    suite.assert_block_max_gas(110_000);
+    suite.assert_block_max_gas(130_000);
 
     suite
-        .update(Addr::unchecked(CREATOR_ADDR), suite.voice_code, 111_000, 32)
+        .update(Addr::unchecked(CREATOR_ADDR), suite.voice_code, 131_000, 32)
         .unwrap();
 
-    suite.assert_block_max_gas(111_000);
+    suite.assert_block_max_gas(131_000);
 }
 
 #[test]
@@ -44,7 +44,7 @@ fn test_query_proxy_code_id() {
     suite.assert_proxy_code(9999);
 
     suite
-        .update(Addr::unchecked(CREATOR_ADDR), 1, 110_000, 32)
+        .update(Addr::unchecked(CREATOR_ADDR), 1, 130_000, 32)
         .unwrap();
 
     suite.assert_proxy_code(1);
@@ -57,7 +57,7 @@ fn test_query_contract_addr_len() {
     suite.assert_contract_addr_len(32);
 
     suite
-        .update(Addr::unchecked(CREATOR_ADDR), 1, 110_000, 20)
+        .update(Addr::unchecked(CREATOR_ADDR), 1, 130_000, 20)
         .unwrap();
 
     suite.assert_contract_addr_len(20);
@@ -100,7 +100,7 @@ fn test_migrate_validation() {
     let mut suite = SuiteBuilder::default().build();
 
     let err = suite
-        .update(Addr::unchecked(CREATOR_ADDR), 0, 110_000, 32)
+        .update(Addr::unchecked(CREATOR_ADDR), 0, 130_000, 32)
         .unwrap_err()
         .downcast::<ContractError>()
         .unwrap();
@@ -116,7 +116,7 @@ fn test_migrate_validation() {
     assert_eq!(err, ContractError::GasLimitsMismatch);
 
     let err = suite
-        .update(Addr::unchecked(CREATOR_ADDR), 1, 110_000, 0)
+        .update(Addr::unchecked(CREATOR_ADDR), 1, 130_000, 0)
         .unwrap_err()
         .downcast::<ContractError>()
         .unwrap();
@@ -124,7 +124,7 @@ fn test_migrate_validation() {
     assert_eq!(err, ContractError::ContractAddrLenCantBeZero);
 
     let err = suite
-        .update(Addr::unchecked(CREATOR_ADDR), 1, 110_000, 33)
+        .update(Addr::unchecked(CREATOR_ADDR), 1, 130_000, 33)
         .unwrap_err()
         .downcast::<ContractError>()
         .unwrap();
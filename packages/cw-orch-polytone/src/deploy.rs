@@ -37,6 +37,8 @@ impl<Chain: CwEnv> Deploy<Chain> for Polytone<Chain> {
             &polytone_note::msg::InstantiateMsg {
                 pair: None,
                 block_max_gas: MAX_BLOCK_GAS.into(),
+                multi_pair: None,
+                timeouts: None,
             },
             None,
             None,
@@ -47,6 +49,7 @@ impl<Chain: CwEnv> Deploy<Chain> for Polytone<Chain> {
                 proxy_code_id: deployment.proxy.code_id()?.into(),
                 block_max_gas: MAX_BLOCK_GAS.into(),
                 contract_addr_len: None,
+                idempotency_window_seconds: None,
             },
             None,
             None,
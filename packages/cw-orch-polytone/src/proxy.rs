@@ -4,7 +4,7 @@ use cw_orch::{interface, prelude::*};
     polytone_proxy::msg::InstantiateMsg,
     polytone_proxy::msg::ExecuteMsg,
     polytone_proxy::msg::QueryMsg,
-    Empty
+    polytone_proxy::msg::MigrateMsg
 )]
 pub struct PolytoneProxy<Chain>;
 
@@ -16,7 +16,8 @@ impl<Chain: CwEnv> Uploadable for PolytoneProxy<Chain> {
                 polytone_proxy::contract::instantiate,
                 polytone_proxy::contract::query,
             )
-            .with_reply(polytone_proxy::contract::reply),
+            .with_reply(polytone_proxy::contract::reply)
+            .with_migrate(polytone_proxy::contract::migrate),
         )
     }
     fn wasm(_chain_info: &ChainInfoOwned) -> WasmPath {
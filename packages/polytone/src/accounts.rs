@@ -1,50 +1,370 @@
-use cosmwasm_std::{Addr, StdResult, Storage};
-use cw_storage_plus::Map;
+use cosmwasm_std::{Addr, Order, StdResult, Storage};
+use cw_storage_plus::{Bound, Map};
 
 /// (channel_id, sequence_number) -> sender
 ///
 /// Maps packets to the address that sent them.
 const PENDING: Map<(String, u64), Addr> = Map::new("polytone-accounts-pending");
 
+/// (channel_id, sequence_number) -> account_id
+///
+/// Account IDs of pending packets sent for one of their sender's
+/// sub-accounts.
+const PENDING_ACCOUNT_IDS: Map<(String, u64), String> =
+    Map::new("polytone-accounts-pending-account-ids");
+
+/// (channel_id, sequence_number) -> new_owner
+///
+/// New owners of the accounts pending packets transfer.
+const PENDING_TRANSFERS: Map<(String, u64), Addr> = Map::new("polytone-accounts-pending-transfers");
+
 /// (local_account) -> remote_account
 ///
 /// Maps local addresses to their remote counterparts.
 const LOCAL_TO_REMOTE_ACCOUNT: Map<Addr, String> = Map::new("polytone-account-map");
 
+/// (connection_id, local_account) -> remote_account
+///
+/// Maps local addresses to their remote counterparts for modules
+/// that pair with more than one voice, where a local address has a
+/// different remote account on each connection.
+const CONNECTION_LOCAL_TO_REMOTE_ACCOUNT: Map<(String, Addr), String> =
+    Map::new("polytone-connection-account-map");
+
+/// (local_account, account_id) -> remote_account
+///
+/// Like `LOCAL_TO_REMOTE_ACCOUNT`, but for sub-accounts: the
+/// additional remote accounts a local address may control, each
+/// identified by an account ID.
+const LOCAL_TO_REMOTE_SUB_ACCOUNT: Map<(Addr, String), String> =
+    Map::new("polytone-sub-account-map");
+
+/// (connection_id, local_account, account_id) -> remote_account
+///
+/// Like `CONNECTION_LOCAL_TO_REMOTE_ACCOUNT`, but for sub-accounts.
+const CONNECTION_LOCAL_TO_REMOTE_SUB_ACCOUNT: Map<(String, Addr, String), String> =
+    Map::new("polytone-connection-sub-account-map");
+
+/// (remote_account) -> local_account
+///
+/// Reverse of `LOCAL_TO_REMOTE_ACCOUNT`. Only holds accounts whose
+/// remote account was last reported by this version of the package
+/// or later, or that have been added with `index_accounts`.
+const REMOTE_TO_LOCAL_ACCOUNT: Map<String, Addr> = Map::new("polytone-account-reverse-map");
+
+/// (connection_id, remote_account) -> local_account
+///
+/// Reverse of `CONNECTION_LOCAL_TO_REMOTE_ACCOUNT`. Like
+/// `REMOTE_TO_LOCAL_ACCOUNT`, may need filling with `index_accounts`.
+const CONNECTION_REMOTE_TO_LOCAL_ACCOUNT: Map<(String, String), Addr> =
+    Map::new("polytone-connection-account-reverse-map");
+
+/// Call on every packet sent. `account_id` is the ID of the sender's
+/// sub-account the packet is for, or `None` if it is for the
+/// sender's default account.
 pub fn on_send_packet(
     storage: &mut dyn Storage,
     channel_id: String,
     sequence_number: u64,
     sender: &Addr,
+    account_id: Option<String>,
 ) -> StdResult<()> {
+    if let Some(account_id) = account_id {
+        PENDING_ACCOUNT_IDS.save(storage, (channel_id.clone(), sequence_number), &account_id)?;
+    }
     PENDING.save(storage, (channel_id, sequence_number), sender)
 }
 
+/// Call, in addition to `on_send_packet`, when sending a packet that
+/// transfers the sender's account to `new_owner`.
+pub fn on_send_transfer(
+    storage: &mut dyn Storage,
+    channel_id: String,
+    sequence_number: u64,
+    new_owner: &Addr,
+) -> StdResult<()> {
+    PENDING_TRANSFERS.save(storage, (channel_id, sequence_number), new_owner)
+}
+
+/// Call on every packet ACK. `connection_id` is the connection the
+/// packet was sent over if accounts should be tracked per-connection,
+/// or `None` if the caller only ever pairs with one voice.
 pub fn on_ack(
     storage: &mut dyn Storage,
     channel_id: String,
     sequence_number: u64,
+    connection_id: Option<String>,
     executor: Option<String>,
 ) {
     let local_account = PENDING
         .load(storage, (channel_id.clone(), sequence_number))
         .expect("pending was set when sending packet");
 
-    PENDING.remove(storage, (channel_id, sequence_number));
+    let account_id = PENDING_ACCOUNT_IDS
+        .may_load(storage, (channel_id.clone(), sequence_number))
+        .expect("account IDs are stored as strings, so should deserialize");
+
+    let new_owner = PENDING_TRANSFERS
+        .may_load(storage, (channel_id.clone(), sequence_number))
+        .expect("new owners are stored as addresses, so should deserialize");
+
+    PENDING.remove(storage, (channel_id.clone(), sequence_number));
+    PENDING_ACCOUNT_IDS.remove(storage, (channel_id.clone(), sequence_number));
+    PENDING_TRANSFERS.remove(storage, (channel_id, sequence_number));
 
     if let Some(executor) = executor {
-        LOCAL_TO_REMOTE_ACCOUNT
-            .save(storage, local_account, &executor)
+        let owner = match new_owner {
+            Some(new_owner) => {
+                remove_account(
+                    storage,
+                    connection_id.clone(),
+                    local_account,
+                    account_id.clone(),
+                );
+                new_owner
+            }
+            None => local_account,
+        };
+        save_account(storage, connection_id, owner, account_id, executor)
             .expect("strings were loaded from storage, so should serialize");
     }
 }
 
+/// Removes `local_account`'s remote account, leaving the reverse
+/// mapping to be replaced by the account's new owner.
+fn remove_account(
+    storage: &mut dyn Storage,
+    connection_id: Option<String>,
+    local_account: Addr,
+    account_id: Option<String>,
+) {
+    match (connection_id, account_id) {
+        (Some(connection_id), Some(account_id)) => CONNECTION_LOCAL_TO_REMOTE_SUB_ACCOUNT
+            .remove(storage, (connection_id, local_account, account_id)),
+        (None, Some(account_id)) => {
+            LOCAL_TO_REMOTE_SUB_ACCOUNT.remove(storage, (local_account, account_id))
+        }
+        (Some(connection_id), None) => {
+            CONNECTION_LOCAL_TO_REMOTE_ACCOUNT.remove(storage, (connection_id, local_account))
+        }
+        (None, None) => LOCAL_TO_REMOTE_ACCOUNT.remove(storage, local_account),
+    }
+}
+
+fn save_account(
+    storage: &mut dyn Storage,
+    connection_id: Option<String>,
+    local_account: Addr,
+    account_id: Option<String>,
+    remote_account: String,
+) -> StdResult<()> {
+    match (connection_id, account_id) {
+        (Some(connection_id), Some(account_id)) => {
+            let key = (connection_id.clone(), local_account.clone(), account_id);
+            if let Some(previous) =
+                CONNECTION_LOCAL_TO_REMOTE_SUB_ACCOUNT.may_load(storage, key.clone())?
+            {
+                CONNECTION_REMOTE_TO_LOCAL_ACCOUNT
+                    .remove(storage, (connection_id.clone(), previous));
+            }
+            CONNECTION_LOCAL_TO_REMOTE_SUB_ACCOUNT.save(storage, key, &remote_account)?;
+            CONNECTION_REMOTE_TO_LOCAL_ACCOUNT.save(
+                storage,
+                (connection_id, remote_account),
+                &local_account,
+            )
+        }
+        (None, Some(account_id)) => {
+            let key = (local_account.clone(), account_id);
+            if let Some(previous) = LOCAL_TO_REMOTE_SUB_ACCOUNT.may_load(storage, key.clone())? {
+                REMOTE_TO_LOCAL_ACCOUNT.remove(storage, previous);
+            }
+            LOCAL_TO_REMOTE_SUB_ACCOUNT.save(storage, key, &remote_account)?;
+            REMOTE_TO_LOCAL_ACCOUNT.save(storage, remote_account, &local_account)
+        }
+        (Some(connection_id), None) => {
+            let key = (connection_id.clone(), local_account.clone());
+            if let Some(previous) =
+                CONNECTION_LOCAL_TO_REMOTE_ACCOUNT.may_load(storage, key.clone())?
+            {
+                CONNECTION_REMOTE_TO_LOCAL_ACCOUNT
+                    .remove(storage, (connection_id.clone(), previous));
+            }
+            CONNECTION_LOCAL_TO_REMOTE_ACCOUNT.save(storage, key, &remote_account)?;
+            CONNECTION_REMOTE_TO_LOCAL_ACCOUNT.save(
+                storage,
+                (connection_id, remote_account),
+                &local_account,
+            )
+        }
+        (None, None) => {
+            if let Some(previous) =
+                LOCAL_TO_REMOTE_ACCOUNT.may_load(storage, local_account.clone())?
+            {
+                REMOTE_TO_LOCAL_ACCOUNT.remove(storage, previous);
+            }
+            LOCAL_TO_REMOTE_ACCOUNT.save(storage, local_account.clone(), &remote_account)?;
+            REMOTE_TO_LOCAL_ACCOUNT.save(storage, remote_account, &local_account)
+        }
+    }
+}
+
 pub fn on_timeout(storage: &mut dyn Storage, channel_id: String, sequence_number: u64) {
-    PENDING.remove(storage, (channel_id, sequence_number))
+    PENDING.remove(storage, (channel_id.clone(), sequence_number));
+    PENDING_ACCOUNT_IDS.remove(storage, (channel_id.clone(), sequence_number));
+    PENDING_TRANSFERS.remove(storage, (channel_id, sequence_number))
+}
+
+type PendingPackets<'a> = Box<dyn Iterator<Item = StdResult<((String, u64), Addr)>> + 'a>;
+
+/// Iterates over packets that have been sent but not ACKed or timed
+/// out, and their senders, ordered by `(channel_id,
+/// sequence_number)`. If `channel_id` is set, only packets sent on
+/// that channel are returned.
+pub fn pending_packets<'a>(
+    storage: &'a dyn Storage,
+    channel_id: Option<String>,
+    start_after: Option<(String, u64)>,
+) -> PendingPackets<'a> {
+    match channel_id {
+        Some(channel_id) => {
+            let start = start_after
+                .filter(|(start_channel, _)| *start_channel == channel_id)
+                .map(|(_, sequence_number)| Bound::exclusive(sequence_number));
+            Box::new(
+                PENDING
+                    .prefix(channel_id.clone())
+                    .range(storage, start, None, Order::Ascending)
+                    .map(move |item| {
+                        item.map(|(sequence_number, sender)| {
+                            ((channel_id.clone(), sequence_number), sender)
+                        })
+                    }),
+            )
+        }
+        None => Box::new(PENDING.range(
+            storage,
+            start_after.map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )),
+    }
 }
 
-pub fn query_account(storage: &dyn Storage, local_address: Addr) -> StdResult<Option<String>> {
-    LOCAL_TO_REMOTE_ACCOUNT.may_load(storage, local_address)
+/// Returns the remote account of `local_address` with `account_id`,
+/// or its default remote account if `account_id` is `None`.
+pub fn query_account(
+    storage: &dyn Storage,
+    connection_id: Option<String>,
+    local_address: Addr,
+    account_id: Option<String>,
+) -> StdResult<Option<String>> {
+    match (connection_id, account_id) {
+        (Some(connection_id), Some(account_id)) => CONNECTION_LOCAL_TO_REMOTE_SUB_ACCOUNT
+            .may_load(storage, (connection_id, local_address, account_id)),
+        (None, Some(account_id)) => {
+            LOCAL_TO_REMOTE_SUB_ACCOUNT.may_load(storage, (local_address, account_id))
+        }
+        (Some(connection_id), None) => {
+            CONNECTION_LOCAL_TO_REMOTE_ACCOUNT.may_load(storage, (connection_id, local_address))
+        }
+        (None, None) => LOCAL_TO_REMOTE_ACCOUNT.may_load(storage, local_address),
+    }
+}
+
+/// Returns the local address that controls `remote_address`, if any.
+///
+/// Accounts are only found once their remote address has been
+/// reported by this version of the package or later, so accounts
+/// last used before upgrading to it are not found until they are
+/// used again or indexed with `index_accounts`.
+pub fn query_local_account(
+    storage: &dyn Storage,
+    connection_id: Option<String>,
+    remote_address: String,
+) -> StdResult<Option<Addr>> {
+    match connection_id {
+        Some(connection_id) => {
+            CONNECTION_REMOTE_TO_LOCAL_ACCOUNT.may_load(storage, (connection_id, remote_address))
+        }
+        None => REMOTE_TO_LOCAL_ACCOUNT.may_load(storage, remote_address),
+    }
+}
+
+/// Lists up to `limit` (local_address, remote_address) pairs ordered
+/// by local address, starting after `start_after`. Only default
+/// accounts are listed, not sub-accounts.
+pub fn query_accounts(
+    storage: &dyn Storage,
+    connection_id: Option<String>,
+    start_after: Option<Addr>,
+    limit: usize,
+) -> StdResult<Vec<(Addr, String)>> {
+    let start = start_after.map(Bound::exclusive);
+    match connection_id {
+        Some(connection_id) => CONNECTION_LOCAL_TO_REMOTE_ACCOUNT
+            .prefix(connection_id)
+            .range(storage, start, None, Order::Ascending)
+            .take(limit)
+            .collect(),
+        None => LOCAL_TO_REMOTE_ACCOUNT
+            .range(storage, start, None, Order::Ascending)
+            .take(limit)
+            .collect(),
+    }
+}
+
+/// Lists up to `limit` (account_id, remote_address) pairs of
+/// `local_address`'s sub-accounts ordered by account ID, starting
+/// after `start_after`.
+pub fn query_sub_accounts(
+    storage: &dyn Storage,
+    connection_id: Option<String>,
+    local_address: Addr,
+    start_after: Option<String>,
+    limit: usize,
+) -> StdResult<Vec<(String, String)>> {
+    let start = start_after.map(Bound::exclusive);
+    match connection_id {
+        Some(connection_id) => CONNECTION_LOCAL_TO_REMOTE_SUB_ACCOUNT
+            .prefix((connection_id, local_address))
+            .range(storage, start, None, Order::Ascending)
+            .take(limit)
+            .collect(),
+        None => LOCAL_TO_REMOTE_SUB_ACCOUNT
+            .prefix(local_address)
+            .range(storage, start, None, Order::Ascending)
+            .take(limit)
+            .collect(),
+    }
+}
+
+/// Adds up to `limit` default accounts after `start_after`, ordered
+/// by local address, to the index used by `query_local_account`,
+/// returning the local addresses of the accounts indexed. Only needed
+/// for accounts last used before this version of the package, and
+/// called repeatedly with the last address returned until none are.
+pub fn index_accounts(
+    storage: &mut dyn Storage,
+    connection_id: Option<String>,
+    start_after: Option<Addr>,
+    limit: usize,
+) -> StdResult<Vec<Addr>> {
+    let accounts = query_accounts(storage, connection_id.clone(), start_after, limit)?;
+    for (local_account, remote_account) in &accounts {
+        match &connection_id {
+            Some(connection_id) => CONNECTION_REMOTE_TO_LOCAL_ACCOUNT.save(
+                storage,
+                (connection_id.clone(), remote_account.clone()),
+                local_account,
+            )?,
+            None => REMOTE_TO_LOCAL_ACCOUNT.save(storage, remote_account.clone(), local_account)?,
+        }
+    }
+    Ok(accounts
+        .into_iter()
+        .map(|(local_account, _)| local_account)
+        .collect())
 }
 
 #[cfg(test)]
@@ -65,21 +385,348 @@ mod tests {
         let sender = Addr::unchecked("sender");
 
         // send first packet to create account on remote chain.
-        on_send_packet(storage, channel_id.clone(), 1, &sender).unwrap();
-        on_ack(storage, channel_id, 1, Some("remote".to_string()));
+        on_send_packet(storage, channel_id.clone(), 1, &sender, None).unwrap();
+        on_ack(storage, channel_id, 1, None, Some("remote".to_string()));
 
-        let remote_account = query_account(storage, sender.clone());
+        let remote_account = query_account(storage, None, sender.clone(), None);
 
         let channel_id = "channel-1".to_string();
 
         // send first packet to create account on remote chain.
-        on_send_packet(storage, channel_id.clone(), 1, &sender).unwrap();
-        on_ack(storage, channel_id, 1, Some("remote".to_string()));
+        on_send_packet(storage, channel_id.clone(), 1, &sender, None).unwrap();
+        on_ack(storage, channel_id, 1, None, Some("remote".to_string()));
 
-        let new_remote_account = query_account(storage, sender);
+        let new_remote_account = query_account(storage, None, sender, None);
         assert_eq!(
             new_remote_account, remote_account,
             "changing the channel shouldn't change the account"
         )
     }
+
+    /// Accounts tracked per-connection are independent of each other
+    /// and of accounts tracked without a connection.
+    #[test]
+    fn test_connection_accounts() {
+        let mut deps = mock_dependencies();
+        let storage = deps.as_mut().storage;
+
+        let sender = Addr::unchecked("sender");
+
+        on_send_packet(storage, "channel-0".to_string(), 1, &sender, None).unwrap();
+        on_ack(
+            storage,
+            "channel-0".to_string(),
+            1,
+            Some("connection-0".to_string()),
+            Some("remote-0".to_string()),
+        );
+        on_send_packet(storage, "channel-1".to_string(), 1, &sender, None).unwrap();
+        on_ack(
+            storage,
+            "channel-1".to_string(),
+            1,
+            Some("connection-1".to_string()),
+            Some("remote-1".to_string()),
+        );
+
+        assert_eq!(
+            query_account(
+                storage,
+                Some("connection-0".to_string()),
+                sender.clone(),
+                None
+            )
+            .unwrap(),
+            Some("remote-0".to_string())
+        );
+        assert_eq!(
+            query_account(
+                storage,
+                Some("connection-1".to_string()),
+                sender.clone(),
+                None
+            )
+            .unwrap(),
+            Some("remote-1".to_string())
+        );
+        assert_eq!(query_account(storage, None, sender, None).unwrap(), None);
+    }
+
+    /// The reverse index follows a local account's remote account
+    /// when it changes.
+    #[test]
+    fn test_reverse_lookup() {
+        let mut deps = mock_dependencies();
+        let storage = deps.as_mut().storage;
+
+        let sender = Addr::unchecked("sender");
+        let other = Addr::unchecked("other");
+
+        on_send_packet(storage, "channel-0".to_string(), 1, &sender, None).unwrap();
+        on_ack(
+            storage,
+            "channel-0".to_string(),
+            1,
+            None,
+            Some("one".to_string()),
+        );
+        on_send_packet(storage, "channel-0".to_string(), 2, &other, None).unwrap();
+        on_ack(
+            storage,
+            "channel-0".to_string(),
+            2,
+            None,
+            Some("two".to_string()),
+        );
+
+        assert_eq!(
+            query_local_account(storage, None, "one".to_string()).unwrap(),
+            Some(sender.clone())
+        );
+        assert_eq!(
+            query_accounts(storage, None, None, 10).unwrap(),
+            vec![
+                (other.clone(), "two".to_string()),
+                (sender.clone(), "one".to_string())
+            ]
+        );
+        assert_eq!(
+            query_accounts(storage, None, Some(other), 10).unwrap(),
+            vec![(sender.clone(), "one".to_string())]
+        );
+
+        on_send_packet(storage, "channel-0".to_string(), 3, &sender, None).unwrap();
+        on_ack(
+            storage,
+            "channel-0".to_string(),
+            3,
+            None,
+            Some("three".to_string()),
+        );
+
+        assert_eq!(
+            query_local_account(storage, None, "one".to_string()).unwrap(),
+            None
+        );
+        assert_eq!(
+            query_local_account(storage, None, "three".to_string()).unwrap(),
+            Some(sender)
+        );
+    }
+
+    /// Accounts saved before the reverse index existed are found once
+    /// indexed.
+    #[test]
+    fn test_index_accounts() {
+        let mut deps = mock_dependencies();
+        let storage = deps.as_mut().storage;
+
+        for (local, remote) in [("a", "remote-a"), ("b", "remote-b"), ("c", "remote-c")] {
+            LOCAL_TO_REMOTE_ACCOUNT
+                .save(storage, Addr::unchecked(local), &remote.to_string())
+                .unwrap();
+        }
+        CONNECTION_LOCAL_TO_REMOTE_ACCOUNT
+            .save(
+                storage,
+                ("connection-0".to_string(), Addr::unchecked("a")),
+                &"remote-a".to_string(),
+            )
+            .unwrap();
+        assert_eq!(
+            query_local_account(storage, None, "remote-a".to_string()).unwrap(),
+            None
+        );
+
+        assert_eq!(
+            index_accounts(storage, None, None, 2).unwrap(),
+            vec![Addr::unchecked("a"), Addr::unchecked("b")]
+        );
+        assert_eq!(
+            query_local_account(storage, None, "remote-b".to_string()).unwrap(),
+            Some(Addr::unchecked("b"))
+        );
+        assert_eq!(
+            query_local_account(storage, None, "remote-c".to_string()).unwrap(),
+            None
+        );
+        assert_eq!(
+            index_accounts(storage, None, Some(Addr::unchecked("b")), 2).unwrap(),
+            vec![Addr::unchecked("c")]
+        );
+        assert!(index_accounts(storage, None, Some(Addr::unchecked("c")), 2)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            query_local_account(storage, None, "remote-c".to_string()).unwrap(),
+            Some(Addr::unchecked("c"))
+        );
+
+        let connection = Some("connection-0".to_string());
+        assert_eq!(
+            query_local_account(storage, connection.clone(), "remote-a".to_string()).unwrap(),
+            None
+        );
+        index_accounts(storage, connection.clone(), None, 2).unwrap();
+        assert_eq!(
+            query_local_account(storage, connection, "remote-a".to_string()).unwrap(),
+            Some(Addr::unchecked("a"))
+        );
+    }
+
+    #[test]
+    fn test_pending_packets() {
+        let mut deps = mock_dependencies();
+        let storage = deps.as_mut().storage;
+
+        let sender = Addr::unchecked("sender");
+        on_send_packet(storage, "channel-0".to_string(), 1, &sender, None).unwrap();
+        on_send_packet(storage, "channel-0".to_string(), 2, &sender, None).unwrap();
+        on_send_packet(storage, "channel-1".to_string(), 1, &sender, None).unwrap();
+        on_timeout(storage, "channel-0".to_string(), 1);
+
+        let pending = |channel_id: Option<&str>, start_after: Option<(&str, u64)>| {
+            pending_packets(
+                storage,
+                channel_id.map(str::to_string),
+                start_after.map(|(c, s)| (c.to_string(), s)),
+            )
+            .map(|item| item.map(|((c, s), _)| (c, s)))
+            .collect::<StdResult<Vec<_>>>()
+            .unwrap()
+        };
+
+        assert_eq!(
+            pending(None, None),
+            vec![("channel-0".to_string(), 2), ("channel-1".to_string(), 1)]
+        );
+        assert_eq!(
+            pending(None, Some(("channel-0", 2))),
+            vec![("channel-1".to_string(), 1)]
+        );
+        assert_eq!(
+            pending(Some("channel-0"), None),
+            vec![("channel-0".to_string(), 2)]
+        );
+        assert_eq!(pending(Some("channel-0"), Some(("channel-0", 2))), vec![]);
+    }
+
+    /// Sub-accounts are tracked separately from default accounts and
+    /// from each other, and resolve back to their local address.
+    #[test]
+    fn test_sub_accounts() {
+        let mut deps = mock_dependencies();
+        let storage = deps.as_mut().storage;
+
+        let sender = Addr::unchecked("sender");
+        let channel_id = "channel-0".to_string();
+        for (sequence_number, account_id, remote) in [
+            (1, None, "default"),
+            (2, Some("a"), "remote-a"),
+            (3, Some("b"), "remote-b"),
+        ] {
+            on_send_packet(
+                storage,
+                channel_id.clone(),
+                sequence_number,
+                &sender,
+                account_id.map(str::to_string),
+            )
+            .unwrap();
+            on_ack(
+                storage,
+                channel_id.clone(),
+                sequence_number,
+                None,
+                Some(remote.to_string()),
+            );
+        }
+
+        // a timed out packet leaves nothing behind.
+        on_send_packet(
+            storage,
+            channel_id.clone(),
+            4,
+            &sender,
+            Some("c".to_string()),
+        )
+        .unwrap();
+        on_timeout(storage, channel_id, 4);
+
+        let account = |account_id: &str| {
+            query_account(storage, None, sender.clone(), Some(account_id.to_string())).unwrap()
+        };
+        assert_eq!(account("a"), Some("remote-a".to_string()));
+        assert_eq!(account("b"), Some("remote-b".to_string()));
+        assert_eq!(account("c"), None);
+        assert_eq!(
+            query_account(storage, None, sender.clone(), None).unwrap(),
+            Some("default".to_string())
+        );
+        assert_eq!(
+            query_local_account(storage, None, "remote-b".to_string()).unwrap(),
+            Some(sender.clone())
+        );
+        assert_eq!(
+            query_accounts(storage, None, None, 10).unwrap(),
+            vec![(sender.clone(), "default".to_string())]
+        );
+        assert_eq!(
+            query_sub_accounts(storage, None, sender.clone(), None, 10).unwrap(),
+            vec![
+                ("a".to_string(), "remote-a".to_string()),
+                ("b".to_string(), "remote-b".to_string())
+            ]
+        );
+        assert_eq!(
+            query_sub_accounts(storage, None, sender.clone(), Some("a".to_string()), 10).unwrap(),
+            vec![("b".to_string(), "remote-b".to_string())]
+        );
+        assert!(
+            query_sub_accounts(storage, Some("connection-0".to_string()), sender, None, 10)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    /// Transferred accounts move to their new owner once ACKed, and
+    /// failed transfers change nothing.
+    #[test]
+    fn test_transfer() {
+        let mut deps = mock_dependencies();
+        let storage = deps.as_mut().storage;
+
+        let old = Addr::unchecked("old");
+        let new = Addr::unchecked("new");
+        let channel_id = "channel-0".to_string();
+        on_send_packet(storage, channel_id.clone(), 1, &old, None).unwrap();
+        on_ack(
+            storage,
+            channel_id.clone(),
+            1,
+            None,
+            Some("remote".to_string()),
+        );
+
+        on_send_packet(storage, channel_id.clone(), 2, &old, None).unwrap();
+        on_send_transfer(storage, channel_id.clone(), 2, &new).unwrap();
+        on_ack(storage, channel_id.clone(), 2, None, None);
+        assert_eq!(
+            query_account(storage, None, old.clone(), None).unwrap(),
+            Some("remote".to_string())
+        );
+
+        on_send_packet(storage, channel_id.clone(), 3, &old, None).unwrap();
+        on_send_transfer(storage, channel_id.clone(), 3, &new).unwrap();
+        on_ack(storage, channel_id, 3, None, Some("remote".to_string()));
+        assert_eq!(query_account(storage, None, old, None).unwrap(), None);
+        assert_eq!(
+            query_account(storage, None, new.clone(), None).unwrap(),
+            Some("remote".to_string())
+        );
+        assert_eq!(
+            query_local_account(storage, None, "remote".to_string()).unwrap(),
+            Some(new)
+        );
+    }
 }
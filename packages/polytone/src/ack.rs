@@ -1,7 +1,12 @@
-use cosmwasm_std::{from_json, to_json_binary, Binary, IbcAcknowledgement, SubMsgResponse, Uint64};
+use cosmwasm_std::{
+    from_json, to_json_binary, Binary, IbcAcknowledgement, SubMsgResponse, Timestamp, Uint64,
+};
 
 pub use crate::callbacks::Callback;
-use crate::callbacks::{ErrorResponse, ExecutionResponse};
+use crate::callbacks::{
+    BestEffortExecutionResponse, ConditionalExecutionResponse, ErrorResponse, ExecutionResponse,
+    TemplatedExecutionResponse,
+};
 
 /// wasmd 0.32+ will not return a hardcoded ICS-20 ACK if
 /// ibc_packet_receive errors [1] so we can safely use an ACK format
@@ -24,6 +29,12 @@ pub fn ack_query_fail(message_index: Uint64, error: String) -> Binary {
     .unwrap()
 }
 
+/// Serializes an ACK-SUCCESS containing the result of each query in
+/// a partial query.
+pub fn ack_query_partial(result: Vec<Result<Binary, String>>) -> Binary {
+    to_json_binary(&Callback::QueryPartial(result)).unwrap()
+}
+
 /// Serializes an ACK-SUCCESS for execution that succeeded.
 pub fn ack_execute_success(result: Vec<SubMsgResponse>, executed_by: String) -> Binary {
     to_json_binary(&Callback::Execute(Ok(ExecutionResponse {
@@ -33,11 +44,74 @@ pub fn ack_execute_success(result: Vec<SubMsgResponse>, executed_by: String) ->
     .unwrap()
 }
 
+/// Serializes an ACK-SUCCESS for best-effort execution.
+pub fn ack_execute_best_effort(
+    result: Vec<Result<SubMsgResponse, String>>,
+    executed_by: String,
+) -> Binary {
+    to_json_binary(&Callback::ExecuteBestEffort(BestEffortExecutionResponse {
+        result,
+        executed_by,
+    }))
+    .unwrap()
+}
+
+/// Serializes an ACK-SUCCESS for a conditional execution.
+pub fn ack_conditional_execute(response: ConditionalExecutionResponse) -> Binary {
+    to_json_binary(&Callback::ConditionalExecute(response)).unwrap()
+}
+
+/// Serializes an ACK-SUCCESS for a templated execution.
+pub fn ack_templated_execute(response: TemplatedExecutionResponse) -> Binary {
+    to_json_binary(&Callback::TemplatedExecute(response)).unwrap()
+}
+
 /// Serializes an ACK-SUCCESS for execution that failed.
 pub fn ack_execute_fail(error: String) -> Binary {
     to_json_binary(&Callback::Execute(Err(error))).unwrap()
 }
 
+/// Serializes an ACK-SUCCESS for a request that ran out of the gas it
+/// was given.
+pub fn ack_gas_limit_exceeded(gas_limit: u64) -> Binary {
+    to_json_binary(&Callback::GasLimitExceeded(Uint64::new(gas_limit))).unwrap()
+}
+
+/// Serializes an ACK-SUCCESS for a request received outside of the
+/// window it may be handled in.
+pub fn ack_outside_window(
+    received_at: Timestamp,
+    not_before: Option<Timestamp>,
+    not_after: Option<Timestamp>,
+) -> Binary {
+    to_json_binary(&Callback::OutsideWindow {
+        received_at,
+        not_before,
+        not_after,
+    })
+    .unwrap()
+}
+
+/// Serializes an ACK-SUCCESS for a request whose nonce was not the
+/// expected one.
+pub fn ack_out_of_order(nonce: u64, expected: u64) -> Binary {
+    to_json_binary(&Callback::OutOfOrder {
+        nonce: Uint64::new(nonce),
+        expected: Uint64::new(expected),
+    })
+    .unwrap()
+}
+
+/// Serializes an ACK-SUCCESS for a request containing a message the
+/// voice's message policy does not allow.
+pub fn ack_rejected(message_index: Uint64, error: String) -> Binary {
+    to_json_binary(&Callback::Rejected(ErrorResponse {
+        message_index,
+        error,
+    }))
+    .unwrap()
+}
+
 /// Serializes an ACK-FAIL containing the provided error.
 pub fn ack_fail(err: String) -> Binary {
     to_json_binary(&Callback::FatalError(err)).unwrap()
@@ -1,9 +1,9 @@
 use cosmwasm_schema::cw_serde;
 use cosmwasm_std::{
-    to_json_binary, Addr, Api, Binary, CosmosMsg, IbcPacketAckMsg, IbcPacketTimeoutMsg, StdResult,
-    Storage, SubMsgResponse, Uint64, WasmMsg,
+    to_json_binary, Addr, Api, Binary, CosmosMsg, IbcPacketAckMsg, IbcPacketTimeoutMsg, Order,
+    StdResult, Storage, SubMsgResponse, Timestamp, Uint64, WasmMsg,
 };
-use cw_storage_plus::Map;
+use cw_storage_plus::{Bound, Map};
 
 use crate::ack::unmarshal_ack;
 
@@ -17,6 +17,9 @@ use crate::ack::unmarshal_ack;
 ///       "result": ...,
 /// }}
 /// ```
+///
+/// Receivers that request `CallbackVersion::V2` also get the
+/// request's `channel_id` and `sequence`.
 #[cw_serde]
 pub struct CallbackMessage {
     /// Initaitor on the note chain.
@@ -26,8 +29,26 @@ pub struct CallbackMessage {
     pub initiator_msg: Binary,
     /// Data from the host chain.
     pub result: Callback,
+    /// The channel the request was sent on. Only set for
+    /// `CallbackVersion::V2` receivers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel_id: Option<String>,
+    /// The sequence number of the request's packet. Together with
+    /// `channel_id` this uniquely identifies the request, and matches
+    /// the data set by the note when the request was sent. Only set
+    /// for `CallbackVersion::V2` receivers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u64>,
 }
 
+/// The execution error returned for a request whose idempotency key
+/// has already been used.
+pub const DUPLICATE_IDEMPOTENCY_KEY: &str = "duplicate idempotency key";
+
+/// The error wasmd reports for a submessage that ran out of gas: the
+/// SDK's `ErrOutOfGas`, with the rest of the error redacted.
+pub const OUT_OF_GAS_ERROR: &str = "codespace: sdk, code: 11";
+
 #[cw_serde]
 pub enum Callback {
     /// Result of executing the requested query, or an error.
@@ -36,6 +57,17 @@ pub enum Callback {
     /// base64 encoded query response.
     Query(Result<Vec<Binary>, ErrorResponse>),
 
+    /// Result of executing the requested queries with `partial` set.
+    ///
+    /// result[i] corresponds to the i'th query and contains either the
+    /// base64 encoded query response or the error it failed with.
+    ///
+    /// This and the variants after `Execute`, other than `FatalError`,
+    /// are only returned to receivers that request
+    /// `CallbackVersion::V2`. See `CallbackVersion::V1` for how they
+    /// are returned to others.
+    QueryPartial(Vec<Result<Binary, String>>),
+
     /// Result of executing the requested messages, or an error.
     ///
     /// 14/04/23: if a submessage errors the reply handler can see
@@ -46,6 +78,56 @@ pub enum Callback {
     /// `codespace: sdk, code: 11`.
     Execute(Result<ExecutionResponse, String>),
 
+    /// Result of executing the requested messages with
+    /// `ExecuteMode::BestEffort`. Timeouts and fatal errors are
+    /// returned as for `Execute`.
+    ExecuteBestEffort(BestEffortExecutionResponse),
+
+    /// Result of a `Msg::ConditionalExecute`. Timeouts and fatal
+    /// errors are returned as for `Execute`.
+    ConditionalExecute(ConditionalExecutionResponse),
+
+    /// Result of a `Msg::TemplatedExecute`. Timeouts and fatal errors
+    /// are returned as for `Execute`.
+    TemplatedExecute(TemplatedExecutionResponse),
+
+    /// The request's packet timed out at the contained block time
+    /// without being executed.
+    Timeout { timed_out_at: Timestamp },
+
+    /// The request was received by the voice outside of the window
+    /// set by the packet's `not_before` and `not_after`, and was not
+    /// executed.
+    OutsideWindow {
+        /// The voice chain block time at which the request was
+        /// received.
+        received_at: Timestamp,
+        not_before: Option<Timestamp>,
+        not_after: Option<Timestamp>,
+    },
+
+    /// The request's nonce was not the next one the voice expected
+    /// from the sender, and it was not executed.
+    OutOfOrder { nonce: Uint64, expected: Uint64 },
+
+    /// The voice's message policy does not allow the message at
+    /// `message_index`, and none of the request's messages were
+    /// executed.
+    Rejected(ErrorResponse),
+
+    /// The channel the request was sent on closed at the contained
+    /// time before the request was ACKed. The request may or may not
+    /// have been executed on the remote chain, and its outcome is not
+    /// known until the packet times out. No further callback is
+    /// delivered for the request.
+    ChannelClosed(Timestamp),
+
+    /// Handling the request ran out of gas before finishing and was
+    /// rolled back. Contains the gas limit that was requested by
+    /// setting `gas_limit` on the packet, clamped to the most the
+    /// voice chain allows.
+    GasLimitExceeded(Uint64),
+
     /// An error occured that could not be recovered from. The only
     /// known way that this can occur is message handling running out
     /// of gas, in which case the error will be `codespace: sdk, code:
@@ -58,6 +140,105 @@ pub enum Callback {
     FatalError(String),
 }
 
+impl Callback {
+    /// Converts the callback to one of the variants known to
+    /// `CallbackVersion::V1` receivers, as described there.
+    pub fn into_v1(self, request_type: &CallbackRequestType) -> Self {
+        let error = |message_index: usize, error: String| match request_type {
+            CallbackRequestType::Execute => Callback::Execute(Err(error)),
+            CallbackRequestType::Query => Callback::Query(Err(ErrorResponse {
+                message_index: Uint64::new(message_index as u64),
+                error,
+            })),
+        };
+        let not_executed = |query: Result<Vec<Binary>, ErrorResponse>,
+                            failed: Option<ErrorResponse>,
+                            execute: Option<Result<ExecutionResponse, String>>,
+                            kind: &str| {
+            match (query, failed, execute) {
+                (_, _, Some(result)) => Callback::Execute(result),
+                (Err(ErrorResponse { error, .. }), _, None) => Callback::Execute(Err(error)),
+                (Ok(_), Some(ErrorResponse { error, .. }), None) => {
+                    Callback::Execute(Err(format!("{kind} failed: {error}")))
+                }
+                (Ok(_), None, None) => Callback::Execute(Err(format!("{kind} failed"))),
+            }
+        };
+        match self {
+            Callback::Query(_) | Callback::Execute(_) | Callback::FatalError(_) => self,
+            Callback::QueryPartial(results) => {
+                let mut responses = Vec::with_capacity(results.len());
+                for (index, result) in results.into_iter().enumerate() {
+                    match result {
+                        Ok(response) => responses.push(response),
+                        Err(e) => return error(index, e),
+                    }
+                }
+                Callback::Query(Ok(responses))
+            }
+            Callback::ExecuteBestEffort(BestEffortExecutionResponse {
+                executed_by,
+                result,
+            }) => match result.into_iter().collect() {
+                Ok(result) => Callback::Execute(Ok(ExecutionResponse {
+                    executed_by,
+                    result,
+                })),
+                Err(e) => Callback::Execute(Err(e)),
+            },
+            Callback::ConditionalExecute(ConditionalExecutionResponse {
+                query,
+                failed_condition,
+                execute,
+            }) => not_executed(query, failed_condition, execute, "condition"),
+            Callback::TemplatedExecute(TemplatedExecutionResponse {
+                query,
+                failed_template,
+                execute,
+            }) => not_executed(query, failed_template, execute, "template"),
+            Callback::Timeout { .. } => error(0, "timeout".to_string()),
+            Callback::OutsideWindow { .. } => error(0, "outside window".to_string()),
+            Callback::OutOfOrder { nonce, expected } => error(
+                0,
+                format!("out of order: nonce {nonce}, expected {expected}"),
+            ),
+            Callback::Rejected(ErrorResponse {
+                message_index,
+                error: e,
+            }) => error(message_index.u64() as usize, e),
+            Callback::ChannelClosed(_) => Callback::FatalError("channel closed".to_string()),
+            Callback::GasLimitExceeded(_) => Callback::FatalError(OUT_OF_GAS_ERROR.to_string()),
+        }
+    }
+
+    /// True if the request was handled without error. Partial
+    /// queries are successful even if some of their queries failed,
+    /// and best-effort executions unless every one of their messages
+    /// failed. Conditional and templated executions are only
+    /// successful if their messages were executed successfully.
+    pub fn is_success(&self) -> bool {
+        match self {
+            Callback::Query(result) => result.is_ok(),
+            Callback::QueryPartial(_) => true,
+            Callback::ExecuteBestEffort(BestEffortExecutionResponse { result, .. }) => {
+                result.is_empty() || result.iter().any(Result::is_ok)
+            }
+            Callback::Execute(result) => result.is_ok(),
+            Callback::ConditionalExecute(ConditionalExecutionResponse { execute, .. })
+            | Callback::TemplatedExecute(TemplatedExecutionResponse { execute, .. }) => {
+                matches!(execute, Some(Ok(_)))
+            }
+            Callback::Timeout { .. }
+            | Callback::OutsideWindow { .. }
+            | Callback::OutOfOrder { .. }
+            | Callback::Rejected(_)
+            | Callback::ChannelClosed(_)
+            | Callback::GasLimitExceeded(_)
+            | Callback::FatalError(_) => false,
+        }
+    }
+}
+
 #[cw_serde]
 pub struct ExecutionResponse {
     /// The address on the remote chain that executed the messages.
@@ -67,6 +248,42 @@ pub struct ExecutionResponse {
     pub result: Vec<SubMsgResponse>,
 }
 
+#[cw_serde]
+pub struct BestEffortExecutionResponse {
+    /// The address on the remote chain that executed the messages.
+    pub executed_by: String,
+    /// Index `i` corresponds to the result of executing the `i`th
+    /// message, or the error it failed with. As with
+    /// `Callback::Execute`, errors only contain their codespace.
+    pub result: Vec<Result<SubMsgResponse, String>>,
+}
+
+#[cw_serde]
+pub struct ConditionalExecutionResponse {
+    /// Result of performing the queries, as for `Callback::Query`.
+    pub query: Result<Vec<Binary>, ErrorResponse>,
+    /// If a condition did not hold, the index of the first one that
+    /// did not and why.
+    pub failed_condition: Option<ErrorResponse>,
+    /// Result of executing the messages, as for `Callback::Execute`,
+    /// or `None` if they were not executed because a query failed or
+    /// a condition did not hold.
+    pub execute: Option<Result<ExecutionResponse, String>>,
+}
+
+#[cw_serde]
+pub struct TemplatedExecutionResponse {
+    /// Result of performing the queries, as for `Callback::Query`.
+    pub query: Result<Vec<Binary>, ErrorResponse>,
+    /// If a template could not be resolved into a message, the index
+    /// of the first one that could not and why.
+    pub failed_template: Option<ErrorResponse>,
+    /// Result of executing the messages, as for `Callback::Execute`,
+    /// or `None` if they were not executed because a query or
+    /// template failed.
+    pub execute: Option<Result<ExecutionResponse, String>>,
+}
+
 #[cw_serde]
 pub struct ErrorResponse {
     /// The index of the first message who's execution failed.
@@ -80,6 +297,58 @@ pub struct ErrorResponse {
 pub struct CallbackRequest {
     pub receiver: String,
     pub msg: Binary,
+    /// The maximum amount of gas the callback may use. Clamped to the
+    /// most the note allows, which is also the default.
+    pub gas_limit: Option<Uint64>,
+    /// Which results the callback is delivered for. Defaults to
+    /// `CallbackDelivery::Always`.
+    pub delivery: Option<CallbackDelivery>,
+    /// The format of the callback. Defaults to `CallbackVersion::V1`.
+    pub version: Option<CallbackVersion>,
+}
+
+/// The format of a callback. New formats are opt-in so that existing
+/// receivers keep working.
+#[cw_serde]
+#[derive(Default)]
+pub enum CallbackVersion {
+    /// Results are only reported as `Callback::Query`,
+    /// `Callback::Execute`, or `Callback::FatalError`:
+    ///
+    /// - partial queries and best-effort executions as a `Query` or
+    ///   `Execute` error with the first error if any of their messages
+    ///   failed;
+    /// - conditional and templated executions as an `Execute` with
+    ///   the result of executing the messages, or the reason they
+    ///   were not executed;
+    /// - timeouts, requests received outside of their window or out
+    ///   of order, and rejected requests as an `Execute` or `Query`
+    ///   error, containing `"timeout"` for timeouts;
+    /// - closed channels and exceeded gas limits as a `FatalError`,
+    ///   the latter containing `OUT_OF_GAS_ERROR`.
+    #[default]
+    V1,
+    /// Results are reported with every `Callback` variant, and
+    /// `CallbackMessage` has the request's `channel_id` and
+    /// `sequence`.
+    V2,
+}
+
+/// Which results a callback is delivered for.
+#[cw_serde]
+#[derive(Default)]
+pub enum CallbackDelivery {
+    /// Every result, including timeouts.
+    #[default]
+    Always,
+    /// Only results for which `Callback::is_success` is true.
+    OnSuccess,
+    /// Only results for which `Callback::is_success` is false,
+    /// excluding timeouts.
+    OnError,
+    /// Only timeouts, and `Callback::ChannelClosed` as the outcome is
+    /// unknown until a timeout.
+    OnTimeout,
 }
 
 /// Disembiguates between a callback for remote message execution and
@@ -113,6 +382,9 @@ pub fn request_callback(
                 initiator_msg,
                 receiver,
                 request_type,
+                gas_limit: request.gas_limit,
+                delivery: request.delivery,
+                version: request.version,
             },
         )?;
     }
@@ -120,7 +392,7 @@ pub fn request_callback(
     Ok(())
 }
 
-/// Call on every packet ACK. Returns a callback message to execute,
+/// Call on every packet ACK. Returns the callback queued for delivery,
 /// if any, and the address that executed the request on the remote
 /// chain (the message initiator's remote account), if any.
 ///
@@ -132,84 +404,727 @@ pub fn on_ack(
         original_packet,
         ..
     }: &IbcPacketAckMsg,
-) -> (Option<CosmosMsg>, Option<String>) {
+) -> StdResult<(Option<QueuedCallback>, Option<String>)> {
     let result = unmarshal_ack(acknowledgement);
 
     let executed_by = match result {
         Callback::Execute(Ok(ExecutionResponse {
             ref executed_by, ..
-        })) => Some(executed_by.clone()),
+        }))
+        | Callback::ExecuteBestEffort(BestEffortExecutionResponse {
+            ref executed_by, ..
+        })
+        | Callback::ConditionalExecute(ConditionalExecutionResponse {
+            execute: Some(Ok(ExecutionResponse {
+                ref executed_by, ..
+            })),
+            ..
+        })
+        | Callback::TemplatedExecute(TemplatedExecutionResponse {
+            execute: Some(Ok(ExecutionResponse {
+                ref executed_by, ..
+            })),
+            ..
+        }) => Some(executed_by.clone()),
         _ => None,
     };
-    let callback_message = dequeue_callback(
+    let callback = dequeue_callback(
         storage,
         original_packet.src.channel_id.clone(),
         original_packet.sequence,
+    )?
+    .filter(
+        |request| match request.delivery.clone().unwrap_or_default() {
+            CallbackDelivery::Always => true,
+            CallbackDelivery::OnSuccess => result.is_success(),
+            CallbackDelivery::OnError => !result.is_success(),
+            CallbackDelivery::OnTimeout => false,
+        },
     )
-    .map(|request| callback_message(request, result));
+    .map(|request| {
+        queue_callback(
+            storage,
+            request,
+            original_packet.src.channel_id.clone(),
+            original_packet.sequence,
+            result,
+        )
+    })
+    .transpose()?;
 
-    (callback_message, executed_by)
+    Ok((callback, executed_by))
 }
 
-/// Call on every packet timeout. Returns a callback message to execute,
-/// if any.
+/// Call on every packet timeout, with the current block time. Returns
+/// the callback queued for delivery, if any.
 pub fn on_timeout(
     storage: &mut dyn Storage,
     IbcPacketTimeoutMsg { packet, .. }: &IbcPacketTimeoutMsg,
-) -> Option<CosmosMsg> {
-    let request = dequeue_callback(storage, packet.src.channel_id.clone(), packet.sequence)?;
-    let timeout = "timeout".to_string();
-    let result = match request.request_type {
-        CallbackRequestType::Execute => Callback::Execute(Err(timeout)),
-        CallbackRequestType::Query => Callback::Query(Err(ErrorResponse {
-            message_index: Uint64::zero(),
-            error: timeout,
-        })),
+    timed_out_at: Timestamp,
+) -> StdResult<Option<QueuedCallback>> {
+    let Some(request) = dequeue_callback(storage, packet.src.channel_id.clone(), packet.sequence)?
+    else {
+        return Ok(None);
+    };
+    if !matches!(
+        request.delivery.clone().unwrap_or_default(),
+        CallbackDelivery::Always | CallbackDelivery::OnTimeout
+    ) {
+        return Ok(None);
+    }
+    queue_callback(
+        storage,
+        request,
+        packet.src.channel_id.clone(),
+        packet.sequence,
+        Callback::Timeout { timed_out_at },
+    )
+    .map(Some)
+}
+
+/// Call for each packet still in flight on a channel after it closes.
+/// Returns the callback queued for delivery, if any. Once called, no
+/// callback is delivered for the packet when it times out.
+pub fn on_channel_closed(
+    storage: &mut dyn Storage,
+    channel_id: String,
+    sequence_number: u64,
+    closed_at: Timestamp,
+) -> StdResult<Option<QueuedCallback>> {
+    let Some(request) = dequeue_callback(storage, channel_id.clone(), sequence_number)? else {
+        return Ok(None);
     };
-    Some(callback_message(request, result))
+    if request.delivery == Some(CallbackDelivery::OnSuccess) {
+        return Ok(None);
+    }
+    queue_callback(
+        storage,
+        request,
+        channel_id,
+        sequence_number,
+        Callback::ChannelClosed(closed_at),
+    )
+    .map(Some)
 }
 
-fn callback_message(request: PendingCallback, result: Callback) -> CosmosMsg {
+/// A callback waiting to be delivered by `deliver_callback`.
+pub struct QueuedCallback {
+    pub channel_id: String,
+    pub sequence: u64,
+    /// The gas limit requested for the callback.
+    pub gas_limit: Option<Uint64>,
+}
+
+fn queue_callback(
+    storage: &mut dyn Storage,
+    request: PendingCallback,
+    channel_id: String,
+    sequence: u64,
+    result: Callback,
+) -> StdResult<QueuedCallback> {
+    let v2 = request.version == Some(CallbackVersion::V2);
+    UNDELIVERED.save(
+        storage,
+        (channel_id.clone(), sequence),
+        &(
+            request.receiver,
+            CallbackMessage {
+                initiator: request.initiator,
+                initiator_msg: request.initiator_msg,
+                result: if v2 {
+                    result
+                } else {
+                    result.into_v1(&request.request_type)
+                },
+                channel_id: v2.then(|| channel_id.clone()),
+                sequence: v2.then_some(sequence),
+            },
+        ),
+    )?;
+    DELIVERING.save(storage, sequence, &channel_id)?;
+    Ok(QueuedCallback {
+        channel_id,
+        sequence,
+        gas_limit: request.gas_limit,
+    })
+}
+
+/// Removes the undelivered callback for the IBC message identified by
+/// `(channel_id, sequence_number)` and returns a message delivering
+/// it, or `None` if there is no such callback.
+///
+/// Callbacks stay undelivered until the returned message succeeds, so
+/// it must be executed such that its failure reverts the removal:
+/// either directly, or in a submessage that also contains the call to
+/// this function.
+pub fn deliver_callback(
+    storage: &mut dyn Storage,
+    channel_id: String,
+    sequence_number: u64,
+) -> StdResult<Option<CosmosMsg>> {
     /// Gives the executed message a "callback" tag:
     /// `{ "callback": CallbackMsg }`.
     #[cw_serde]
     enum C {
         Callback(CallbackMessage),
     }
-    WasmMsg::Execute {
-        contract_addr: request.receiver.into_string(),
-        msg: to_json_binary(&C::Callback(CallbackMessage {
-            initiator: request.initiator,
-            initiator_msg: request.initiator_msg,
-            result,
-        }))
-        .expect("fields are known to be serializable"),
-        funds: vec![],
+    if DELIVERING.may_load(storage, sequence_number)?.as_ref() == Some(&channel_id) {
+        DELIVERING.remove(storage, sequence_number);
     }
-    .into()
+    let key = (channel_id, sequence_number);
+    let Some((receiver, message)) = UNDELIVERED.may_load(storage, key.clone())? else {
+        return Ok(None);
+    };
+    UNDELIVERED.remove(storage, key.clone());
+    CALLBACK_ERRORS.remove(storage, key);
+    Ok(Some(
+        WasmMsg::Execute {
+            contract_addr: receiver.into_string(),
+            msg: to_json_binary(&C::Callback(message))
+                .expect("fields are known to be serializable"),
+            funds: vec![],
+        }
+        .into(),
+    ))
+}
+
+/// Call when delivering a callback queued by `on_ack`, `on_timeout`,
+/// or `on_channel_closed` in the same transaction fails. Records the
+/// error, which is returned by `query_failed_callbacks` until the
+/// callback is delivered.
+pub fn on_callback_error(
+    storage: &mut dyn Storage,
+    sequence_number: u64,
+    error: String,
+) -> StdResult<()> {
+    let channel_id = DELIVERING.load(storage, sequence_number)?;
+    DELIVERING.remove(storage, sequence_number);
+    CALLBACK_ERRORS.save(storage, (channel_id, sequence_number), &error)
+}
+
+/// Lists callbacks that failed to be delivered ordered by
+/// `(channel_id, sequence_number)`.
+pub fn query_failed_callbacks(
+    storage: &dyn Storage,
+    start_after: Option<(String, u64)>,
+    limit: usize,
+) -> StdResult<Vec<FailedCallback>> {
+    UNDELIVERED
+        .range(
+            storage,
+            start_after.map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| {
+            let (key, (receiver, callback)) = item?;
+            Ok(FailedCallback {
+                receiver,
+                callback,
+                error: CALLBACK_ERRORS.may_load(storage, key)?,
+            })
+        })
+        .collect()
+}
+
+/// Returns the callback requested for the IBC message identified by
+/// `(channel_id, sequence_number)`, if it has not yet been returned.
+pub fn query_pending_callback(
+    storage: &dyn Storage,
+    channel_id: String,
+    sequence_number: u64,
+) -> StdResult<Option<PendingCallback>> {
+    CALLBACKS.may_load(storage, (channel_id, sequence_number))
 }
 
 fn dequeue_callback(
     storage: &mut dyn Storage,
     channel_id: String,
     sequence_number: u64,
-) -> Option<PendingCallback> {
-    let request = CALLBACKS
-        .may_load(storage, (channel_id.clone(), sequence_number))
-        .unwrap()?;
-    CALLBACKS.remove(storage, (channel_id, sequence_number));
-    Some(request)
+) -> StdResult<Option<PendingCallback>> {
+    let request = CALLBACKS.may_load(storage, (channel_id.clone(), sequence_number))?;
+    if request.is_some() {
+        CALLBACKS.remove(storage, (channel_id, sequence_number));
+    }
+    Ok(request)
 }
 
 #[cw_serde]
-struct PendingCallback {
-    initiator: Addr,
-    initiator_msg: Binary,
+pub struct PendingCallback {
+    pub initiator: Addr,
+    pub initiator_msg: Binary,
     /// The address that will receive the callback on completion.
-    receiver: Addr,
+    pub receiver: Addr,
     /// Used to return the appropriate callback type during timeouts.
-    request_type: CallbackRequestType,
+    pub request_type: CallbackRequestType,
+    /// The gas limit requested for the callback.
+    pub gas_limit: Option<Uint64>,
+    /// Which results the callback is delivered for, defaulting to
+    /// `CallbackDelivery::Always`.
+    pub delivery: Option<CallbackDelivery>,
+    /// The format of the callback, defaulting to
+    /// `CallbackVersion::V1`.
+    pub version: Option<CallbackVersion>,
+}
+
+/// A callback that failed to be delivered.
+#[cw_serde]
+pub struct FailedCallback {
+    /// The address the callback is delivered to.
+    pub receiver: Addr,
+    /// The message the receiver is executed with.
+    pub callback: CallbackMessage,
+    /// The error delivering the callback failed with. `None` if the
+    /// error was not recorded.
+    pub error: Option<String>,
 }
 
 /// (channel_id, sequence_number) -> callback
 const CALLBACKS: Map<(String, u64), PendingCallback> = Map::new("polytone-callbacks");
+
+/// (channel_id, sequence_number) -> (receiver, callback)
+///
+/// Callbacks are saved here when queued and removed when delivered,
+/// so after a delivery fails the callback remains.
+const UNDELIVERED: Map<(String, u64), (Addr, CallbackMessage)> =
+    Map::new("polytone-undelivered-callbacks");
+
+/// (channel_id, sequence_number) -> error
+const CALLBACK_ERRORS: Map<(String, u64), String> = Map::new("polytone-callback-errors");
+
+/// sequence_number -> channel_id
+///
+/// The channel of each queued callback that has not been delivered
+/// since, keyed by sequence number so that a failed delivery, which
+/// only knows its sequence number, can find it cheaply. Two callbacks
+/// with the same sequence number on different channels are never
+/// queued in the same transaction.
+const DELIVERING: Map<u64, String> = Map::new("polytone-delivering-callbacks");
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{
+        from_json,
+        testing::{mock_dependencies, mock_ibc_packet_ack, mock_ibc_packet_timeout},
+        Addr, DepsMut, IbcAcknowledgement,
+    };
+
+    use crate::ack::{ack_query_fail, ack_query_success};
+
+    use super::*;
+
+    fn request(deps: DepsMut, sequence: u64, delivery: Option<CallbackDelivery>) {
+        request_versioned(deps, sequence, delivery, None)
+    }
+
+    fn request_versioned(
+        deps: DepsMut,
+        sequence: u64,
+        delivery: Option<CallbackDelivery>,
+        version: Option<CallbackVersion>,
+    ) {
+        request_callback(
+            deps.storage,
+            deps.api,
+            "channel-0".to_string(),
+            sequence,
+            Addr::unchecked("initiator"),
+            Some(CallbackRequest {
+                receiver: "receiver".to_string(),
+                msg: Binary::default(),
+                gas_limit: Some(Uint64::new(100_000)),
+                delivery,
+                version,
+            }),
+            CallbackRequestType::Query,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_callback_delivery() {
+        let mut deps = mock_dependencies();
+
+        let success = IbcAcknowledgement::new(ack_query_success(vec![]));
+        let failure = IbcAcknowledgement::new(ack_query_fail(Uint64::zero(), "err".to_string()));
+
+        for (delivery, delivered_on_success, delivered_on_error, delivered_on_timeout) in [
+            (None, true, true, true),
+            (Some(CallbackDelivery::Always), true, true, true),
+            (Some(CallbackDelivery::OnSuccess), true, false, false),
+            (Some(CallbackDelivery::OnError), false, true, false),
+            (Some(CallbackDelivery::OnTimeout), false, false, true),
+        ] {
+            for (ack, expected) in [
+                (&success, delivered_on_success),
+                (&failure, delivered_on_error),
+            ] {
+                let ack = mock_ibc_packet_ack("channel-0", &(), ack.clone()).unwrap();
+                request(
+                    deps.as_mut(),
+                    ack.original_packet.sequence,
+                    delivery.clone(),
+                );
+                let (callback, _) = on_ack(deps.as_mut().storage, &ack).unwrap();
+                assert_eq!(callback.is_some(), expected, "{delivery:?}");
+                if let Some(QueuedCallback { gas_limit, .. }) = callback {
+                    assert_eq!(gas_limit, Some(Uint64::new(100_000)));
+                }
+                // the request is removed even if it isn't delivered.
+                assert!(query_pending_callback(
+                    deps.as_ref().storage,
+                    "channel-0".to_string(),
+                    ack.original_packet.sequence
+                )
+                .unwrap()
+                .is_none());
+            }
+
+            let timeout = mock_ibc_packet_timeout("channel-0", &()).unwrap();
+            request(deps.as_mut(), timeout.packet.sequence, delivery.clone());
+            assert_eq!(
+                on_timeout(deps.as_mut().storage, &timeout, Timestamp::default())
+                    .unwrap()
+                    .is_some(),
+                delivered_on_timeout,
+                "{delivery:?}"
+            );
+        }
+    }
+
+    /// Best-effort executions are successful unless every message
+    /// failed.
+    #[test]
+    fn test_best_effort_success() {
+        let best_effort = |result: Vec<Result<SubMsgResponse, String>>| {
+            Callback::ExecuteBestEffort(BestEffortExecutionResponse {
+                executed_by: "proxy".to_string(),
+                result,
+            })
+        };
+        let ok = || {
+            Ok(SubMsgResponse {
+                events: vec![],
+                data: None,
+            })
+        };
+        let err = || Err("err".to_string());
+
+        assert!(best_effort(vec![]).is_success());
+        assert!(best_effort(vec![err(), ok()]).is_success());
+        assert!(!best_effort(vec![err()]).is_success());
+        assert!(!best_effort(vec![err(), err()]).is_success());
+    }
+
+    #[test]
+    fn test_timeout_versions() {
+        let mut deps = mock_dependencies();
+        let timed_out_at = Timestamp::from_seconds(100);
+        let timeout = mock_ibc_packet_timeout("channel-0", &()).unwrap();
+        let sequence = timeout.packet.sequence;
+
+        for (version, expected) in [
+            (
+                None,
+                Callback::Query(Err(ErrorResponse {
+                    message_index: Uint64::zero(),
+                    error: "timeout".to_string(),
+                })),
+            ),
+            (
+                Some(CallbackVersion::V1),
+                Callback::Query(Err(ErrorResponse {
+                    message_index: Uint64::zero(),
+                    error: "timeout".to_string(),
+                })),
+            ),
+            (
+                Some(CallbackVersion::V2),
+                Callback::Timeout { timed_out_at },
+            ),
+        ] {
+            request_versioned(deps.as_mut(), sequence, None, version);
+            on_timeout(deps.as_mut().storage, &timeout, timed_out_at)
+                .unwrap()
+                .unwrap();
+            let failed = query_failed_callbacks(deps.as_ref().storage, None, 10).unwrap();
+            assert_eq!(failed[0].callback.result, expected);
+            deliver_callback(deps.as_mut().storage, "channel-0".to_string(), sequence).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_callback_message_versions() {
+        /// `CallbackMessage` before it had `channel_id` and `sequence`.
+        #[cw_serde]
+        struct V1CallbackMessage {
+            initiator: Addr,
+            initiator_msg: Binary,
+            result: Callback,
+        }
+
+        let mut deps = mock_dependencies();
+        let ack = mock_ibc_packet_ack(
+            "channel-0",
+            &(),
+            IbcAcknowledgement::new(ack_query_success(vec![])),
+        )
+        .unwrap();
+        let sequence = ack.original_packet.sequence;
+
+        for version in [None, Some(CallbackVersion::V1), Some(CallbackVersion::V2)] {
+            request_versioned(deps.as_mut(), sequence, None, version.clone());
+            on_ack(deps.as_mut().storage, &ack).unwrap();
+            let message = query_failed_callbacks(deps.as_ref().storage, None, 10)
+                .unwrap()
+                .remove(0)
+                .callback;
+            let json = to_json_binary(&message).unwrap();
+            if version == Some(CallbackVersion::V2) {
+                assert_eq!(message.channel_id, Some("channel-0".to_string()));
+                assert_eq!(message.sequence, Some(sequence));
+                assert!(from_json::<V1CallbackMessage>(&json).is_err());
+            } else {
+                assert_eq!(
+                    from_json::<V1CallbackMessage>(&json).unwrap(),
+                    V1CallbackMessage {
+                        initiator: Addr::unchecked("initiator"),
+                        initiator_msg: Binary::default(),
+                        result: Callback::Query(Ok(vec![])),
+                    }
+                );
+            }
+            deliver_callback(deps.as_mut().storage, "channel-0".to_string(), sequence).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_v1_callbacks() {
+        /// `Callback` before variants were added to it.
+        #[cw_serde]
+        enum V1Callback {
+            Query(Result<Vec<Binary>, ErrorResponse>),
+            Execute(Result<ExecutionResponse, String>),
+            FatalError(String),
+        }
+
+        let executed = ExecutionResponse {
+            executed_by: "proxy".to_string(),
+            result: vec![],
+        };
+        let error = |message_index: u64, error: &str| ErrorResponse {
+            message_index: Uint64::new(message_index),
+            error: error.to_string(),
+        };
+        for (callback, request_type, expected) in [
+            (
+                Callback::QueryPartial(vec![Ok(Binary::default()), Err("err".to_string())]),
+                CallbackRequestType::Query,
+                V1Callback::Query(Err(error(1, "err"))),
+            ),
+            (
+                Callback::QueryPartial(vec![Ok(Binary::default())]),
+                CallbackRequestType::Query,
+                V1Callback::Query(Ok(vec![Binary::default()])),
+            ),
+            (
+                Callback::ExecuteBestEffort(BestEffortExecutionResponse {
+                    executed_by: "proxy".to_string(),
+                    result: vec![Err("codespace: wasm, code: 5".to_string())],
+                }),
+                CallbackRequestType::Execute,
+                V1Callback::Execute(Err("codespace: wasm, code: 5".to_string())),
+            ),
+            (
+                Callback::ConditionalExecute(ConditionalExecutionResponse {
+                    query: Ok(vec![]),
+                    failed_condition: Some(error(0, "not equal")),
+                    execute: None,
+                }),
+                CallbackRequestType::Execute,
+                V1Callback::Execute(Err("condition failed: not equal".to_string())),
+            ),
+            (
+                Callback::TemplatedExecute(TemplatedExecutionResponse {
+                    query: Ok(vec![]),
+                    failed_template: None,
+                    execute: Some(Ok(executed.clone())),
+                }),
+                CallbackRequestType::Execute,
+                V1Callback::Execute(Ok(executed)),
+            ),
+            (
+                Callback::OutsideWindow {
+                    received_at: Timestamp::from_seconds(1),
+                    not_before: None,
+                    not_after: None,
+                },
+                CallbackRequestType::Query,
+                V1Callback::Query(Err(error(0, "outside window"))),
+            ),
+            (
+                Callback::OutOfOrder {
+                    nonce: Uint64::new(3),
+                    expected: Uint64::new(2),
+                },
+                CallbackRequestType::Execute,
+                V1Callback::Execute(Err("out of order: nonce 3, expected 2".to_string())),
+            ),
+            (
+                Callback::Rejected(error(1, "contract (other) is not allowed")),
+                CallbackRequestType::Execute,
+                V1Callback::Execute(Err("contract (other) is not allowed".to_string())),
+            ),
+            (
+                Callback::ChannelClosed(Timestamp::from_seconds(1)),
+                CallbackRequestType::Execute,
+                V1Callback::FatalError("channel closed".to_string()),
+            ),
+            (
+                Callback::GasLimitExceeded(Uint64::new(100)),
+                CallbackRequestType::Execute,
+                V1Callback::FatalError(OUT_OF_GAS_ERROR.to_string()),
+            ),
+        ] {
+            let json = to_json_binary(&callback.into_v1(&request_type)).unwrap();
+            assert_eq!(from_json::<V1Callback>(&json).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_channel_closed() {
+        let mut deps = mock_dependencies();
+        let closed_at = Timestamp::from_seconds(100);
+
+        for (delivery, delivered) in [
+            (None, true),
+            (Some(CallbackDelivery::OnSuccess), false),
+            (Some(CallbackDelivery::OnError), true),
+            (Some(CallbackDelivery::OnTimeout), true),
+        ] {
+            request_versioned(
+                deps.as_mut(),
+                1,
+                delivery.clone(),
+                Some(CallbackVersion::V2),
+            );
+            let callback =
+                on_channel_closed(deps.as_mut().storage, "channel-0".to_string(), 1, closed_at)
+                    .unwrap();
+            assert_eq!(callback.is_some(), delivered, "{delivery:?}");
+            if delivered {
+                let failed = query_failed_callbacks(deps.as_ref().storage, None, 10).unwrap();
+                assert_eq!(
+                    failed[0].callback.result,
+                    Callback::ChannelClosed(closed_at)
+                );
+                deliver_callback(deps.as_mut().storage, "channel-0".to_string(), 1).unwrap();
+            }
+        }
+
+        // the packet timing out later does not deliver another callback.
+        let mut timeout = mock_ibc_packet_timeout("channel-0", &()).unwrap();
+        timeout.packet.sequence = 1;
+        assert!(on_timeout(deps.as_mut().storage, &timeout, closed_at)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_failed_callbacks() {
+        let mut deps = mock_dependencies();
+
+        let ack = mock_ibc_packet_ack(
+            "channel-0",
+            &(),
+            IbcAcknowledgement::new(ack_query_success(vec![])),
+        )
+        .unwrap();
+        let sequence = ack.original_packet.sequence;
+        request(deps.as_mut(), sequence, None);
+        let (callback, _) = on_ack(deps.as_mut().storage, &ack).unwrap();
+        let callback = callback.unwrap();
+        assert_eq!(
+            (callback.channel_id.as_str(), callback.sequence),
+            ("channel-0", sequence)
+        );
+
+        // until delivered, the callback is listed without an error.
+        let failed = query_failed_callbacks(deps.as_ref().storage, None, 10).unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].error, None);
+
+        on_callback_error(
+            deps.as_mut().storage,
+            sequence,
+            "codespace: wasm, code: 5".to_string(),
+        )
+        .unwrap();
+        let failed = query_failed_callbacks(deps.as_ref().storage, None, 10).unwrap();
+        assert_eq!(failed[0].receiver, Addr::unchecked("receiver"));
+        assert_eq!(failed[0].callback.sequence, None);
+        assert_eq!(failed[0].callback.result, Callback::Query(Ok(vec![])));
+        assert_eq!(
+            failed[0].error,
+            Some("codespace: wasm, code: 5".to_string())
+        );
+        assert!(query_failed_callbacks(
+            deps.as_ref().storage,
+            Some(("channel-0".to_string(), sequence)),
+            10
+        )
+        .unwrap()
+        .is_empty());
+
+        let msg = deliver_callback(deps.as_mut().storage, "channel-0".to_string(), sequence)
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            msg,
+            CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, .. }) if contract_addr == "receiver"
+        ));
+        assert!(query_failed_callbacks(deps.as_ref().storage, None, 10)
+            .unwrap()
+            .is_empty());
+        assert!(
+            deliver_callback(deps.as_mut().storage, "channel-0".to_string(), sequence)
+                .unwrap()
+                .is_none()
+        );
+        // once delivered, the callback is no longer being delivered.
+        assert!(on_callback_error(deps.as_mut().storage, sequence, "err".to_string()).is_err());
+
+        // errors are recorded against the channel of the callback
+        // with that sequence number.
+        request_callback(
+            &mut deps.storage,
+            &deps.api,
+            "channel-1".to_string(),
+            sequence,
+            Addr::unchecked("initiator"),
+            Some(CallbackRequest {
+                receiver: "receiver".to_string(),
+                msg: Binary::default(),
+                gas_limit: None,
+                delivery: None,
+                version: None,
+            }),
+            CallbackRequestType::Query,
+        )
+        .unwrap();
+        let mut timeout = mock_ibc_packet_timeout("channel-1", &()).unwrap();
+        timeout.packet.sequence = sequence;
+        on_timeout(deps.as_mut().storage, &timeout, Timestamp::default())
+            .unwrap()
+            .unwrap();
+        on_callback_error(deps.as_mut().storage, sequence, "err".to_string()).unwrap();
+        let failed = query_failed_callbacks(
+            deps.as_ref().storage,
+            Some(("channel-0".to_string(), sequence)),
+            10,
+        )
+        .unwrap();
+        assert_eq!(failed[0].error, Some("err".to_string()));
+    }
+}
@@ -0,0 +1,175 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{from_json, Binary};
+
+use crate::value::Value;
+
+/// A condition on the responses to the queries of a
+/// `Msg::ConditionalExecute`.
+#[cw_serde]
+pub enum Condition {
+    /// The response to query `query_index` is exactly `response`.
+    Equals { query_index: u32, response: Binary },
+    /// The value at `path` in the JSON response to query
+    /// `query_index` compares to `value` as `comparison` requires.
+    /// See `Value::get` for the path format.
+    Path {
+        query_index: u32,
+        path: String,
+        comparison: Comparison,
+        value: Value,
+    },
+}
+
+#[cw_serde]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+}
+
+impl Condition {
+    /// Checks the condition against the query `responses`, returning
+    /// why it does not hold if it does not.
+    ///
+    /// Values are compared as integers if both are numbers or strings
+    /// containing integers, so `"10"` equals `10`. Ordered
+    /// comparisons fail if either value is not an integer.
+    pub fn check(&self, responses: &[Binary]) -> Result<(), String> {
+        let query_index = match self {
+            Condition::Equals { query_index, .. } | Condition::Path { query_index, .. } => {
+                *query_index
+            }
+        };
+        let response = responses
+            .get(query_index as usize)
+            .ok_or_else(|| format!("no query with index {query_index}"))?;
+        match self {
+            Condition::Equals {
+                response: expected, ..
+            } => {
+                if response == expected {
+                    Ok(())
+                } else {
+                    Err(format!("query {query_index} response does not match"))
+                }
+            }
+            Condition::Path {
+                path,
+                comparison,
+                value: expected,
+                ..
+            } => {
+                let response: Value = from_json(response)
+                    .map_err(|e| format!("query {query_index} response is not JSON: {e}"))?;
+                let actual = response.get(path).ok_or_else(|| {
+                    format!("no value at `{path}` in query {query_index} response")
+                })?;
+                let holds = match (actual.as_integer(), expected.as_integer()) {
+                    (Some(a), Some(e)) => comparison.holds(a.cmp(&e)),
+                    _ => match comparison {
+                        Comparison::Equal => actual == expected,
+                        Comparison::NotEqual => actual != expected,
+                        _ => {
+                            return Err(format!(
+                                "`{actual}` at `{path}` in query {query_index} response can not be ordered against `{expected}`"
+                            ))
+                        }
+                    },
+                };
+                if holds {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "`{actual}` at `{path}` in query {query_index} response is not {comparison:?} `{expected}`"
+                    ))
+                }
+            }
+        }
+    }
+}
+
+impl Comparison {
+    fn holds(&self, ordering: std::cmp::Ordering) -> bool {
+        use std::cmp::Ordering::*;
+        match self {
+            Comparison::Equal => ordering == Equal,
+            Comparison::NotEqual => ordering != Equal,
+            Comparison::LessThan => ordering == Less,
+            Comparison::LessThanOrEqual => ordering != Greater,
+            Comparison::GreaterThan => ordering == Greater,
+            Comparison::GreaterThanOrEqual => ordering != Less,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{coin, to_json_binary, BalanceResponse};
+
+    use super::*;
+
+    fn path(path: &str, comparison: Comparison, value: Value) -> Condition {
+        Condition::Path {
+            query_index: 0,
+            path: path.to_string(),
+            comparison,
+            value,
+        }
+    }
+
+    #[test]
+    fn test_conditions() {
+        let responses = vec![to_json_binary(&BalanceResponse {
+            amount: coin(100, "ucosm"),
+        })
+        .unwrap()];
+
+        assert!(Condition::Equals {
+            query_index: 0,
+            response: responses[0].clone(),
+        }
+        .check(&responses)
+        .is_ok());
+        assert!(Condition::Equals {
+            query_index: 1,
+            response: responses[0].clone(),
+        }
+        .check(&responses)
+        .is_err());
+
+        let denom = Value::String("ucosm".to_string());
+        assert!(path("amount.denom", Comparison::Equal, denom.clone())
+            .check(&responses)
+            .is_ok());
+        assert!(path("amount.denom", Comparison::NotEqual, denom.clone())
+            .check(&responses)
+            .is_err());
+        assert!(path("amount.denom", Comparison::LessThan, denom)
+            .check(&responses)
+            .is_err());
+
+        // "100" is compared as an integer.
+        assert!(path("amount.amount", Comparison::Equal, Value::Number(100))
+            .check(&responses)
+            .is_ok());
+        assert!(
+            path("amount.amount", Comparison::GreaterThan, Value::Number(99))
+                .check(&responses)
+                .is_ok()
+        );
+        assert!(path(
+            "amount.amount",
+            Comparison::LessThanOrEqual,
+            Value::String("99".to_string())
+        )
+        .check(&responses)
+        .is_err());
+
+        assert!(path("amount.missing", Comparison::Equal, Value::Null)
+            .check(&responses)
+            .is_err());
+    }
+}
@@ -1,14 +1,61 @@
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{CosmosMsg, Empty, QueryRequest};
+use cosmwasm_std::{CosmosMsg, Empty, QueryRequest, Timestamp, Uint64};
+
+use crate::{conditions::Condition, value::Value};
 
 pub const VERSION: &str = "polytone";
 
+/// A request from a note to a voice.
+///
+/// Optional fields added after the first release, here and in `Msg`,
+/// are not serialized if unset so that voices which predate them can
+/// handle packets that do not use them.
 #[cw_serde]
 pub struct Packet {
     /// Message sender on the note chain.
     pub sender: String,
     /// Message to execute on voice chain.
     pub msg: Msg,
+    /// The maximum amount of gas the voice may use handling `msg`. If
+    /// this runs out a `Callback::GasLimitExceeded` is returned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gas_limit: Option<Uint64>,
+    /// The earliest voice chain block time at which `msg` may be
+    /// handled. If the packet is received earlier a
+    /// `Callback::OutsideWindow` is returned and `msg` is not handled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<Timestamp>,
+    /// The latest voice chain block time at which `msg` may be
+    /// handled, as for `not_before`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_after: Option<Timestamp>,
+    /// If set, `msg` is only handled if this is one more than the
+    /// nonce of the last packet handled from `sender` with a nonce,
+    /// or one if there is no such packet. Otherwise a
+    /// `Callback::OutOfOrder` is returned. A packet's nonce is used
+    /// once it is received in order and within its window, and its
+    /// messages are allowed by the voice's message policy, even if
+    /// handling `msg` then fails. Templated messages are checked
+    /// against the policy after the nonce is used. A `FatalError` or
+    /// `GasLimitExceeded` rolls back the use of the nonce.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<Uint64>,
+    /// If set, messages executed by `msg` are not executed if the
+    /// sender's proxy has successfully executed messages for a packet
+    /// with the same key within the voice's idempotency window.
+    /// Instead an execution error of
+    /// `callbacks::DUPLICATE_IDEMPOTENCY_KEY` is returned, alongside
+    /// the query results of conditional and templated executions.
+    /// The key is only used once the packet's callback is successful,
+    /// so a packet that fails may be retried with the same key.
+    /// Ignored for queries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// If set, `msg` is executed by the sender's proxy with this ID
+    /// rather than its default proxy, so that a sender may have many
+    /// isolated proxies. Ignored for queries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<String>,
 }
 
 #[cw_serde]
@@ -16,11 +63,72 @@ pub enum Msg {
     /// Performs the requested queries on the voice chain and returns a
     /// callback of Vec<QuerierResult>, or ACK-FAIL if unmarshalling
     /// any of the query requests fails.
-    Query { msgs: Vec<QueryRequest<Empty>> },
+    Query {
+        msgs: Vec<QueryRequest<Empty>>,
+        /// If true, every query is performed even if some fail, and a
+        /// `Callback::QueryPartial` is returned with the result of
+        /// each.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        partial: Option<bool>,
+    },
     /// Executes the requested messages on the voice chain on behalf of
     /// the note chain sender. Message receivers can return data
     /// in their callbacks by calling `set_data` on their `Response`
     /// object. Returns a callback of `Vec<Callback>` where index `i`
     /// corresponds to the callback for `msgs[i]`.
-    Execute { msgs: Vec<CosmosMsg<Empty>> },
+    Execute {
+        msgs: Vec<CosmosMsg<Empty>>,
+        /// How the messages are executed. Defaults to
+        /// `ExecuteMode::Atomic`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mode: Option<ExecuteMode>,
+    },
+    /// Performs `queries`, and if every one of `conditions` holds
+    /// for their responses, executes `msgs` on behalf of the note
+    /// chain sender as `Execute` does in `ExecuteMode::Atomic`.
+    /// Returns a callback of `Callback::ConditionalExecute`.
+    ConditionalExecute {
+        queries: Vec<QueryRequest<Empty>>,
+        conditions: Vec<Condition>,
+        msgs: Vec<CosmosMsg<Empty>>,
+    },
+    /// Performs `queries`, fills the placeholders in `msgs` with
+    /// values from their responses, and executes the resulting
+    /// messages on behalf of the note chain sender as `Execute` does
+    /// in `ExecuteMode::Atomic`. `msgs` are the JSON of `CosmosMsg`s
+    /// containing placeholders as described in
+    /// `template::resolve_templates`. Returns a callback of
+    /// `Callback::TemplatedExecute`.
+    TemplatedExecute {
+        queries: Vec<QueryRequest<Empty>>,
+        msgs: Vec<Value>,
+    },
+    /// Makes `new_owner` the controller of the note chain sender's
+    /// proxy, keeping the proxy's address. The previous owner's next
+    /// proxy with the account ID gets a new address. Nonces are per
+    /// sender and are not transferred: `new_owner` keeps its own
+    /// sequence. Returns a callback of `Callback::Execute` with no
+    /// results, executed by the proxy, or with an error if the sender
+    /// has no proxy with the packet's account ID or `new_owner`
+    /// already has one.
+    TransferAccount { new_owner: String },
+    /// Sets whether the note chain sender's proxy is skipped when the
+    /// voice's admin migrates proxies to new code. Proxies are
+    /// migrated unless opted out. Returns a callback of
+    /// `Callback::Execute` with no results, executed by the proxy.
+    SetMigrationOptOut { opt_out: bool },
+}
+
+#[cw_serde]
+#[derive(Default)]
+pub enum ExecuteMode {
+    /// If any message fails, all of the messages are rolled back and
+    /// a `Callback::Execute` error is returned.
+    #[default]
+    Atomic,
+    /// Each message is executed on its own. A message that fails is
+    /// rolled back without rolling back the others, and a
+    /// `Callback::ExecuteBestEffort` is returned with the result of
+    /// each.
+    BestEffort,
 }
@@ -1,6 +1,9 @@
 pub mod accounts;
 pub mod ack;
 pub mod callbacks;
+pub mod conditions;
 pub mod ibc;
+pub mod template;
+pub mod value;
 
 pub mod handshake;
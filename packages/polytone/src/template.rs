@@ -0,0 +1,125 @@
+use cosmwasm_std::{from_json, to_json_vec, Binary, CosmosMsg, Empty, Uint64};
+
+use crate::{callbacks::ErrorResponse, value::Value};
+
+/// Fills the placeholders in `templates` with values from the query
+/// `responses` and parses the results as messages. If a template can
+/// not be resolved, returns an error whose `message_index` is the
+/// index of the template.
+///
+/// A placeholder is a string of the form `$q<index>.<path>`, which is
+/// replaced with the value at `path` in the JSON response to query
+/// `index` (see `Value::get`), or `$q<index>` which is replaced with
+/// the whole response. Strings starting with `$$` are not
+/// placeholders and have their first `$` removed.
+pub fn resolve_templates(
+    templates: Vec<Value>,
+    responses: &[Binary],
+) -> Result<Vec<CosmosMsg<Empty>>, ErrorResponse> {
+    let mut cache = vec![None; responses.len()];
+    templates
+        .into_iter()
+        .enumerate()
+        .map(|(index, template)| {
+            resolve(template, responses, &mut cache)
+                .and_then(|msg| {
+                    from_json(to_json_vec(&msg).map_err(|e| e.to_string())?)
+                        .map_err(|e| format!("resolved template is not a message: {e}"))
+                })
+                .map_err(|error| ErrorResponse {
+                    message_index: Uint64::new(index as u64),
+                    error,
+                })
+        })
+        .collect()
+}
+
+fn resolve(
+    template: Value,
+    responses: &[Binary],
+    cache: &mut [Option<Value>],
+) -> Result<Value, String> {
+    Ok(match template {
+        Value::String(s) => {
+            if let Some(escaped) = s.strip_prefix("$$") {
+                Value::String(format!("${escaped}"))
+            } else if let Some(placeholder) = s.strip_prefix("$q") {
+                let (index, path) = placeholder.split_once('.').unwrap_or((placeholder, ""));
+                let index: usize = index
+                    .parse()
+                    .map_err(|_| format!("invalid placeholder `{s}`"))?;
+                let response = responses
+                    .get(index)
+                    .ok_or_else(|| format!("no query with index {index} for `{s}`"))?;
+                if cache[index].is_none() {
+                    cache[index] = Some(
+                        from_json(response)
+                            .map_err(|e| format!("query {index} response is not JSON: {e}"))?,
+                    );
+                }
+                cache[index]
+                    .as_ref()
+                    .and_then(|response| response.get(path))
+                    .cloned()
+                    .ok_or_else(|| format!("no value at `{path}` in query {index} response"))?
+            } else {
+                Value::String(s)
+            }
+        }
+        Value::Array(array) => Value::Array(
+            array
+                .into_iter()
+                .map(|v| resolve(v, responses, cache))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Object(object) => Value::Object(
+            object
+                .into_iter()
+                .map(|(k, v)| Ok((k, resolve(v, responses, cache)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+        value => value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{coin, coins, to_json_binary, BalanceResponse, BankMsg};
+
+    use super::*;
+
+    #[test]
+    fn test_resolve_templates() {
+        let responses = vec![to_json_binary(&BalanceResponse {
+            amount: coin(100, "ucosm"),
+        })
+        .unwrap()];
+
+        let template: Value = from_json(
+            r#"{"bank":{"send":{"to_address":"$$addr","amount":[{"denom":"$q0.amount.denom","amount":"$q0.amount.amount"}]}}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            resolve_templates(vec![template], &responses).unwrap(),
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                to_address: "$addr".to_string(),
+                amount: coins(100, "ucosm"),
+            })]
+        );
+
+        let missing: Value =
+            from_json(r#"{"bank":{"burn":{"amount":"$q0.amount.missing"}}}"#).unwrap();
+        let not_a_msg: Value = from_json(r#"{"bank":"$q0"}"#).unwrap();
+        let out_of_range: Value = from_json(r#""$q1""#).unwrap();
+        let burn: Value = from_json(r#"{"bank":{"burn":{"amount":[]}}}"#).unwrap();
+        for (template, error) in [
+            (missing, "no value at `amount.missing` in query 0 response"),
+            (not_a_msg, "resolved template is not a message"),
+            (out_of_range, "no query with index 1"),
+        ] {
+            let err = resolve_templates(vec![burn.clone(), template], &responses).unwrap_err();
+            assert_eq!(err.message_index, Uint64::new(1));
+            assert!(err.error.starts_with(error), "{}", err.error);
+        }
+    }
+}
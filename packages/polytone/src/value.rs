@@ -0,0 +1,216 @@
+use std::{collections::BTreeMap, fmt};
+
+use cosmwasm_schema::schemars::{
+    gen::SchemaGenerator,
+    schema::{Schema, SchemaObject},
+    JsonSchema,
+};
+use serde::{
+    de::{self, MapAccess, SeqAccess, Visitor},
+    ser::{SerializeMap, SerializeSeq},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+/// A JSON value. Unlike `serde_json::Value` this has no floating
+/// point numbers, which CosmWasm contracts may not use, so numbers
+/// are integers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(i128),
+    String(String),
+    Array(Vec<Value>),
+    Object(BTreeMap<String, Value>),
+}
+
+impl Value {
+    /// Returns the value at `path`, a `.` separated list of object
+    /// keys and array indexes (for example `balances.0.amount`), or
+    /// `None` if there is no value there. The empty path refers to
+    /// `self`.
+    pub fn get(&self, path: &str) -> Option<&Value> {
+        if path.is_empty() {
+            return Some(self);
+        }
+        path.split('.').try_fold(self, |value, key| match value {
+            Value::Object(map) => map.get(key),
+            Value::Array(array) => array.get(key.parse::<usize>().ok()?),
+            _ => None,
+        })
+    }
+
+    /// Returns the value as an integer if it is a number, or a string
+    /// containing one. Strings are accepted as large integers like
+    /// `Uint128` are serialized as strings.
+    pub fn as_integer(&self) -> Option<i128> {
+        match self {
+            Value::Number(n) => Some(*n),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = cosmwasm_std::to_json_string(self).map_err(|_| fmt::Error)?;
+        f.write_str(&json)
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Null => serializer.serialize_unit(),
+            Value::Bool(b) => serializer.serialize_bool(*b),
+            Value::Number(n) => {
+                if let Ok(n) = u64::try_from(*n) {
+                    serializer.serialize_u64(n)
+                } else if let Ok(n) = i64::try_from(*n) {
+                    serializer.serialize_i64(n)
+                } else {
+                    serializer.serialize_i128(*n)
+                }
+            }
+            Value::String(s) => serializer.serialize_str(s),
+            Value::Array(array) => {
+                let mut seq = serializer.serialize_seq(Some(array.len()))?;
+                for value in array {
+                    seq.serialize_element(value)?;
+                }
+                seq.end()
+            }
+            Value::Object(object) => {
+                let mut map = serializer.serialize_map(Some(object.len()))?;
+                for (key, value) in object {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ValueVisitor;
+
+        impl<'de> Visitor<'de> for ValueVisitor {
+            type Value = Value;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a JSON value without floating point numbers")
+            }
+
+            fn visit_unit<E>(self) -> Result<Value, E> {
+                Ok(Value::Null)
+            }
+
+            fn visit_none<E>(self) -> Result<Value, E> {
+                Ok(Value::Null)
+            }
+
+            fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+                Deserialize::deserialize(deserializer)
+            }
+
+            fn visit_bool<E>(self, v: bool) -> Result<Value, E> {
+                Ok(Value::Bool(v))
+            }
+
+            fn visit_i64<E>(self, v: i64) -> Result<Value, E> {
+                Ok(Value::Number(v.into()))
+            }
+
+            fn visit_u64<E>(self, v: u64) -> Result<Value, E> {
+                Ok(Value::Number(v.into()))
+            }
+
+            fn visit_i128<E>(self, v: i128) -> Result<Value, E> {
+                Ok(Value::Number(v))
+            }
+
+            fn visit_u128<E: de::Error>(self, v: u128) -> Result<Value, E> {
+                i128::try_from(v)
+                    .map(Value::Number)
+                    .map_err(|_| E::custom("integer out of range"))
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Value, E> {
+                Ok(Value::String(v.to_string()))
+            }
+
+            fn visit_string<E>(self, v: String) -> Result<Value, E> {
+                Ok(Value::String(v))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+                let mut array = Vec::new();
+                while let Some(value) = seq.next_element()? {
+                    array.push(value);
+                }
+                Ok(Value::Array(array))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+                let mut object = BTreeMap::new();
+                while let Some((key, value)) = map.next_entry()? {
+                    object.insert(key, value);
+                }
+                Ok(Value::Object(object))
+            }
+        }
+
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+impl JsonSchema for Value {
+    fn schema_name() -> String {
+        "Value".to_string()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        // any JSON value.
+        SchemaObject::default().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{from_json, to_json_string};
+
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let json = r#"{"a":[1,-2,"3",null,true],"b":{"c":"d"},"e":"170141183460469231731687303715884105727"}"#;
+        let value: Value = from_json(json).unwrap();
+        assert_eq!(to_json_string(&value).unwrap(), json);
+        assert_eq!(value.to_string(), json);
+    }
+
+    #[test]
+    fn test_get() {
+        let value: Value = from_json(r#"{"balances":[{"amount":"10"},{"amount":20}]}"#).unwrap();
+
+        assert_eq!(value.get(""), Some(&value));
+        assert_eq!(
+            value.get("balances.0.amount"),
+            Some(&Value::String("10".to_string()))
+        );
+        assert_eq!(
+            value.get("balances.1.amount").and_then(Value::as_integer),
+            Some(20)
+        );
+        assert_eq!(value.get("balances.2.amount"), None);
+        assert_eq!(value.get("balances.x"), None);
+        assert_eq!(value.get("balances.0.amount.denom"), None);
+    }
+
+    #[test]
+    fn test_no_floats() {
+        assert!(from_json::<Value>("1.5").is_err());
+    }
+}
@@ -30,10 +30,11 @@ type NoteExecute struct {
 }
 
 var (
-	NoteQueryActiveChannel = `"active_channel"`
-	NoteQueryPair          = `"pair"`
-	NoteQueryBlockMaxGas   = `"block_max_gas"`
-	NoteQueryRemoteAddress = func(local_address string) string {
+	NoteQueryActiveChannel   = `"active_channel"`
+	NoteQueryPair            = `"pair"`
+	NoteQueryBlockMaxGas     = `"block_max_gas"`
+	NoteQueryFailedCallbacks = `{"failed_callbacks":{}}`
+	NoteQueryRemoteAddress   = func(local_address string) string {
 		return fmt.Sprintf(`{"remote_address":{"local_address":"%s"}}`, local_address)
 	}
 )
@@ -49,6 +50,11 @@ type NoteExecuteMsg struct {
 	Msgs           []w.CosmosMsg    `json:"msgs"`
 	TimeoutSeconds uint64           `json:"timeout_seconds,string"`
 	Callback       *CallbackRequest `json:"callback,omitempty"`
+	Options        *RequestOptions  `json:"options,omitempty"`
+}
+
+type RequestOptions struct {
+	GasLimit uint64 `json:"gas_limit,string,omitempty"`
 }
 
 type PolytoneMessage struct {
@@ -67,6 +73,7 @@ type PolytoneExecute struct {
 type CallbackRequest struct {
 	Receiver string `json:"receiver"`
 	Msg      string `json:"msg"`
+	Version  string `json:"version,omitempty"`
 }
 
 type CallbackMessage struct {
@@ -75,10 +82,17 @@ type CallbackMessage struct {
 	Result       Callback `json:"result"`
 }
 
+type FailedCallback struct {
+	Receiver string          `json:"receiver"`
+	Callback CallbackMessage `json:"callback"`
+	Error    *string         `json:"error"`
+}
+
 type Callback struct {
-	Execute    CallbackDataExecute `json:"execute,omitempty"`
-	Query      CallbackDataQuery   `json:"query,omitempty"`
-	FatalError string              `json:"fatal_error,omitempty"`
+	Execute          CallbackDataExecute `json:"execute,omitempty"`
+	Query            CallbackDataQuery   `json:"query,omitempty"`
+	FatalError       string              `json:"fatal_error,omitempty"`
+	GasLimitExceeded string              `json:"gas_limit_exceeded,omitempty"`
 }
 
 type CallbackDataQuery struct {
@@ -189,6 +203,25 @@ func QueryRemoteAccount(
 	return string(query)
 }
 
+func QueryFailedCallbacks(
+	chain *ibctesting.TestChain,
+	note sdk.AccAddress,
+) []FailedCallback {
+	query, err := chain.App.WasmKeeper.QuerySmart(
+		chain.GetContext(),
+		note,
+		[]byte(NoteQueryFailedCallbacks),
+	)
+	if err != nil {
+		panic(err)
+	}
+	var response []FailedCallback
+	if err := json.Unmarshal(query, &response); err != nil {
+		panic(err)
+	}
+	return response
+}
+
 func QueryBlockMaxGas(
 	chain *ibctesting.TestChain,
 	note sdk.AccAddress,
@@ -215,6 +215,42 @@ func TestVoiceOutOfGas(t *testing.T) {
 	)
 }
 
+// Tests that a request which runs out of the gas limit it set gets a
+// gas_limit_exceeded callback. This relies on the voice recognizing
+// the error wasmd returns for a submessage that runs out of gas.
+func TestVoiceGasLimitExceeded(t *testing.T) {
+	suite := NewSuite(t)
+
+	path := suite.SetupDefaultPath(&suite.ChainA, &suite.ChainB)
+
+	account := GenAccount(t, &suite.ChainA)
+	gasMsg := w.CosmosMsg{
+		Wasm: &w.WasmMsg{
+			Execute: &w.ExecuteMsg{
+				ContractAddr: suite.ChainB.Tester.String(),
+				Msg:          []byte(`{"run_out_of_gas":{}}`),
+				Funds:        []w.Coin{},
+			},
+		},
+	}
+
+	callback, err := suite.RoundtripMessage(t, path, &account, NoteExecute{
+		Execute: &NoteExecuteMsg{
+			Msgs:           []w.CosmosMsg{gasMsg},
+			TimeoutSeconds: 100,
+			Callback: &CallbackRequest{
+				Receiver: account.SuiteChain.Tester.String(),
+				Msg:      "aGVsbG8K",
+				Version:  "v2",
+			},
+			Options: &RequestOptions{GasLimit: 1_000_000},
+		},
+	})
+	require.NoError(t, err)
+	require.Equal(t, "", callback.FatalError)
+	require.Equal(t, "1000000", callback.GasLimitExceeded)
+}
+
 // Tests that if a callback receiver runs out of gas the ACK is still
 // comitted on the sending chain and the local to remote address
 // mapping is still updated.
@@ -244,6 +280,10 @@ func TestNoteOutOfGas(t *testing.T) {
 	callbacks := QueryCallbackHistory(account.Chain, account.SuiteChain.Tester)
 	require.Equal(t, len(startCallbacks), len(callbacks), "no new callbacks")
 
+	failed := QueryFailedCallbacks(suite.ChainA.Chain, suite.ChainA.Note)
+	require.Len(t, failed, 1, "failed callback kept for retrying")
+	require.NotNil(t, failed[0].Error, "reply had enough gas to record the error")
+
 	remote := QueryRemoteAccount(suite.ChainA.Chain, suite.ChainA.Note, account.Address)
 	require.NotEmpty(t, remote, "remote account set even though callback rolled back")
 }